@@ -0,0 +1,81 @@
+//! Age of the fixes relative to the current time, to tell how stale the data of a receiver is.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chrono::{DateTime, Days, NaiveDateTime};
+
+use crate::parse::Fix;
+
+/// Current UTC time from the system clock. Everything else takes the current time as an argument,
+/// so it can be tested without depending on the wall time.
+pub fn system_now() -> NaiveDateTime {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    DateTime::from_timestamp(since_epoch.as_secs() as i64, since_epoch.subsec_nanos())
+        .unwrap_or_default()
+        .naive_utc()
+}
+
+/// UTC date and time of the last of the fixes, if its date can be resolved. GGA sentences have no
+/// date, so it is taken from the RMC sentence of the last fix, or otherwise from the last fix with
+/// one, moved to the next day if the time went back past midnight since then.
+pub fn last_fix_time(fixes: &[Fix]) -> Option<NaiveDateTime> {
+    let last = fixes.last()?;
+    let time = last.time()?;
+    let (dated_time, date) = fixes.iter().rev().find_map(|fix| {
+        let rmc = fix.epoch?.rmc?;
+        Some((fix.time()?, rmc.date?))
+    })?;
+    let date = if time < dated_time {
+        date.checked_add_days(Days::new(1))?
+    } else {
+        date
+    };
+    Some(date.and_time(time))
+}
+
+/// Seconds from `time` to `now`, which is negative if `time` is in the future.
+pub fn age_s(time: NaiveDateTime, now: NaiveDateTime) -> f64 {
+    (now - time).num_milliseconds() as f64 / 1000.
+}
+
+/// Parses a duration made of a number and a unit, `s`, `m`, `h` or `d`, like `90m`, into seconds.
+pub fn parse_duration(arg: &str) -> Result<f64, String> {
+    let arg = arg.trim();
+    let split = arg.len() - arg.chars().last().map_or(0, char::len_utf8);
+    let (number, unit) = arg.split_at(split);
+    let factor = match unit {
+        "s" => 1.,
+        "m" => 60.,
+        "h" => 3600.,
+        "d" => 86400.,
+        _ => return Err("expected a number followed by s, m, h or d, like 90m".to_owned()),
+    };
+    let number = number
+        .parse::<f64>()
+        .map_err(|err| format!("invalid number {number:?}: {err}"))?;
+    if number.is_nan() || number.is_infinite() || number < 0. {
+        return Err("the duration must be a non-negative number".to_owned());
+    }
+    Ok(number * factor)
+}
+
+/// Formats a number of seconds with the largest units which fit it, like `2h 5m 3s`.
+pub fn format_duration(seconds: f64) -> String {
+    let sign = if seconds < 0. { "-" } else { "" };
+    let total = seconds.abs().round() as u64;
+    let (days, hours, minutes, seconds) = (
+        total / 86400,
+        total / 3600 % 24,
+        total / 60 % 60,
+        total % 60,
+    );
+    let parts = [(days, "d"), (hours, "h"), (minutes, "m")]
+        .into_iter()
+        .skip_while(|(value, _)| *value == 0)
+        .map(|(value, unit)| format!("{value}{unit}"))
+        .chain(std::iter::once(format!("{seconds}s")))
+        .collect::<Vec<_>>();
+    format!("{sign}{}", parts.join(" "))
+}
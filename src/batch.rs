@@ -0,0 +1,438 @@
+//! Batch mode, which runs the jobs listed in a TOML job file.
+//!
+//! A job file holds an optional `[defaults]` table and one `[[job]]` table per job. Both accept the
+//! same settings, except for `name`, `inputs` and `output`, which are only accepted by jobs:
+//!
+//! ```toml
+//! [defaults]
+//! sigma = 2.5
+//! max_std_dev_m = 3.0
+//!
+//! [[job]]
+//! name = "mark-1"
+//! inputs = ["mark-1/morning.nmea", "mark-1/evening.nmea"]
+//! output = "mark-1/average.txt"
+//! reference = [37.3754, -6.0003, 38.0]
+//! max_error_m = 1.5
+//! ```
+//!
+//! Paths are relative to the directory of the job file. Jobs are run in order, and a failed job
+//! doesn't stop the rest.
+
+use std::{
+    collections::HashSet,
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+use glam::DVec3;
+
+use crate::{
+    age::format_duration,
+    parse::{check_input_options, check_position, LinePolicy, OptionNames},
+    read_inputs, Filtered, HdopFilter, InputFormat, InputOptions, Options, ParseOptions, Summary,
+};
+
+/// Settings of a job, as written in the job file. Missing settings are taken from the defaults.
+#[derive(Clone, Debug, Default, PartialEq, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JobSettings {
+    name: Option<String>,
+    inputs: Option<Vec<PathBuf>>,
+    output: Option<PathBuf>,
+    input_format: Option<InputFormat>,
+    weight_column: Option<usize>,
+    staleness: Option<f64>,
+    lenient: Option<bool>,
+    max_hdop: Option<f64>,
+    hdop_spike_window: Option<f64>,
+    sigma: Option<f64>,
+    robust: Option<bool>,
+    iterations: Option<u32>,
+    reference: Option<[f64; 3]>,
+    max_std_dev_m: Option<f64>,
+    max_error_m: Option<f64>,
+    min_fixes: Option<usize>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JobFile {
+    #[serde(default)]
+    defaults: JobSettings,
+    #[serde(default, rename = "job")]
+    jobs: Vec<JobSettings>,
+}
+
+/// Thresholds a job must meet to pass.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Gates {
+    /// Maximum horizontal standard deviation of the filtered fixes, in meters.
+    pub max_std_dev_m: Option<f64>,
+    /// Maximum horizontal distance between the average and the reference position, in meters.
+    pub max_error_m: Option<f64>,
+    /// Minimum number of fixes remaining after filtering outliers.
+    pub min_fixes: Option<usize>,
+    /// Maximum age of the last filtered fix relative to the current time, in seconds.
+    pub max_age_s: Option<f64>,
+}
+
+impl Gates {
+    /// Returns a description of each gate the summary doesn't meet. `error_m` is the horizontal
+    /// distance between the average and the reference position, if there is one, and `age_s` the
+    /// age of the last filtered fix, if its date can be resolved.
+    pub fn check(
+        &self,
+        summary: &Summary,
+        error_m: Option<f64>,
+        age_s: Option<f64>,
+    ) -> Vec<String> {
+        self.check_values(
+            summary.n_filtered,
+            summary.std_dev_horizontal_m(),
+            error_m,
+            age_s,
+        )
+    }
+
+    /// Like [`Self::check`], with the number of filtered fixes and their horizontal standard
+    /// deviation instead of the summary, as read back from saved results.
+    pub fn check_values(
+        &self,
+        n_filtered: usize,
+        std_dev_m: f64,
+        error_m: Option<f64>,
+        age_s: Option<f64>,
+    ) -> Vec<String> {
+        let mut failed_gates = Vec::new();
+        if let Some(min_fixes) = self.min_fixes {
+            if n_filtered < min_fixes {
+                failed_gates.push(format!(
+                    "{n_filtered} fixes after filter, below the minimum of {min_fixes}"
+                ));
+            }
+        }
+        if let Some(max_std_dev_m) = self.max_std_dev_m {
+            if std_dev_m > max_std_dev_m {
+                failed_gates.push(format!(
+                    "horizontal standard deviation of {std_dev_m:.2}m, above the maximum of {max_std_dev_m}m"
+                ));
+            }
+        }
+        match (self.max_error_m, error_m) {
+            (Some(max_error_m), Some(error_m)) if error_m > max_error_m => failed_gates.push(
+                format!("error of {error_m:.2}m, above the maximum of {max_error_m}m"),
+            ),
+            (Some(_), None) => failed_gates.push("no reference to compute the error".to_owned()),
+            _ => {}
+        }
+        match (self.max_age_s, age_s) {
+            (Some(max_age_s), Some(age_s)) if age_s > max_age_s => failed_gates.push(format!(
+                "last fix {} old, above the maximum of {}",
+                format_duration(age_s),
+                format_duration(max_age_s)
+            )),
+            (Some(_), None) => failed_gates
+                .push("no date in the RMC sentences to compute the age of the fixes".to_owned()),
+            _ => {}
+        }
+        failed_gates
+    }
+}
+
+/// A job of the job file, with its settings resolved.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Job {
+    pub name: String,
+    pub inputs: Vec<PathBuf>,
+    /// File the average is written to, in the same format as the short output.
+    pub output: Option<PathBuf>,
+    pub input: InputOptions,
+    pub options: Options,
+    /// Known position of the mark, as (latitude, longitude, altitude).
+    pub reference: Option<DVec3>,
+    pub gates: Gates,
+}
+
+/// Results of a job which could be run.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Outcome {
+    pub summary: Summary,
+    /// Horizontal distance between the average and the reference position, in meters.
+    pub error_m: Option<f64>,
+    /// Description of each gate the job didn't meet.
+    pub failed_gates: Vec<String>,
+}
+
+impl Outcome {
+    pub fn passed(&self) -> bool {
+        self.failed_gates.is_empty()
+    }
+}
+
+/// A job along with its outcome, or the error which stopped it.
+#[derive(Debug)]
+pub struct JobResult {
+    pub name: String,
+    pub outcome: anyhow::Result<Outcome>,
+}
+
+impl JobResult {
+    pub fn passed(&self) -> bool {
+        self.outcome.as_ref().is_ok_and(Outcome::passed)
+    }
+}
+
+/// Parses and validates the contents of a job file. Relative paths are resolved against
+/// `base_dir`.
+pub fn parse_job_file(text: &str, base_dir: &Path) -> anyhow::Result<Vec<Job>> {
+    let JobFile { defaults, jobs } = toml::from_str(text)?;
+    if defaults.name.is_some() || defaults.inputs.is_some() || defaults.output.is_some() {
+        return Err(anyhow!(
+            "The defaults can't set the name, inputs or output of the jobs"
+        ));
+    }
+    if jobs.is_empty() {
+        return Err(anyhow!("The job file has no jobs"));
+    }
+
+    let mut names = HashSet::new();
+    let mut resolved = Vec::with_capacity(jobs.len());
+    for (idx, settings) in jobs.into_iter().enumerate() {
+        let name = settings
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("job-{}", idx + 1));
+        if !names.insert(name.clone()) {
+            return Err(anyhow!("There is more than one job named '{name}'"));
+        }
+        let job = resolve_job(name.clone(), settings, &defaults, base_dir)
+            .with_context(|| format!("Invalid settings for job '{name}'"))?;
+        resolved.push(job);
+    }
+    Ok(resolved)
+}
+
+/// Builds a job from its settings, taking the missing ones from `defaults`.
+fn resolve_job(
+    name: String,
+    settings: JobSettings,
+    defaults: &JobSettings,
+    base_dir: &Path,
+) -> anyhow::Result<Job> {
+    let inputs = match settings.inputs {
+        Some(inputs) if !inputs.is_empty() => inputs,
+        _ => return Err(anyhow!("The job has no inputs")),
+    };
+
+    let format = settings
+        .input_format
+        .or(defaults.input_format)
+        .unwrap_or(InputFormat::Nmea);
+    let weight_column = settings.weight_column.or(defaults.weight_column);
+    let staleness = settings
+        .staleness
+        .or(defaults.staleness)
+        .unwrap_or(ParseOptions::default().staleness);
+    let max_hdop = settings.max_hdop.or(defaults.max_hdop);
+    let hdop_spike_window = settings.hdop_spike_window.or(defaults.hdop_spike_window);
+    if hdop_spike_window.is_some() && max_hdop.is_none() {
+        return Err(anyhow!("A HDOP spike window requires a maximum HDOP"));
+    }
+    let input = InputOptions {
+        format,
+        weight_column,
+        parse: ParseOptions {
+            staleness,
+            policy: if settings.lenient.or(defaults.lenient).unwrap_or(false) {
+                LinePolicy::lenient()
+            } else {
+                LinePolicy::default()
+            },
+            ..ParseOptions::default()
+        },
+        hdop: max_hdop.map(|max_hdop| HdopFilter {
+            max_hdop,
+            spike_window_s: hdop_spike_window,
+        }),
+        ..InputOptions::default()
+    };
+    check_input_options(&input, OptionNames::JobSettings)?;
+
+    let default_options = Options::default();
+    let cutoff = settings
+        .sigma
+        .or(defaults.sigma)
+        .unwrap_or(default_options.cutoff);
+    if cutoff.is_nan() || cutoff <= 0. {
+        return Err(anyhow!(
+            "The sigma must be a positive number of standard deviations"
+        ));
+    }
+    let max_passes = settings
+        .iterations
+        .or(defaults.iterations)
+        .unwrap_or(default_options.max_passes);
+    if max_passes == 0 {
+        return Err(anyhow!("The number of iterations must be at least 1"));
+    }
+
+    let reference = settings
+        .reference
+        .or(defaults.reference)
+        .map(|reference| check_position(DVec3::from_array(reference)))
+        .transpose()
+        .context("Invalid reference")?;
+    let gates = Gates {
+        max_std_dev_m: settings.max_std_dev_m.or(defaults.max_std_dev_m),
+        max_error_m: settings.max_error_m.or(defaults.max_error_m),
+        min_fixes: settings.min_fixes.or(defaults.min_fixes),
+        max_age_s: None,
+    };
+    if gates
+        .max_std_dev_m
+        .is_some_and(|max_std_dev_m| max_std_dev_m.is_nan() || max_std_dev_m < 0.)
+    {
+        return Err(anyhow!(
+            "max_std_dev_m must be a non-negative number of meters"
+        ));
+    }
+    if gates
+        .max_error_m
+        .is_some_and(|max_error_m| max_error_m.is_nan() || max_error_m < 0.)
+    {
+        return Err(anyhow!(
+            "max_error_m must be a non-negative number of meters"
+        ));
+    }
+
+    Ok(Job {
+        name,
+        inputs: inputs.iter().map(|path| base_dir.join(path)).collect(),
+        output: settings.output.map(|path| base_dir.join(path)),
+        input,
+        options: Options {
+            cutoff,
+            robust: settings.robust.or(defaults.robust).unwrap_or(false),
+            max_passes,
+            weighted: weight_column.is_some(),
+            ..default_options
+        },
+        reference,
+        gates,
+    })
+}
+
+/// Runs a job, writing its output file if it has one.
+pub fn run_job(job: &Job) -> anyhow::Result<Outcome> {
+    let input_data = read_inputs(&job.inputs, &job.input)?;
+    let filtered = Filtered::new(&input_data.fixes, &job.options)?;
+    let summary = Summary::new(&input_data.fixes, filtered, &job.options);
+    let error_m = job
+        .reference
+        .map(|reference| summary.reference_error(reference).horizontal);
+
+    // Jobs have no age limit, so the age of their fixes isn't needed.
+    let failed_gates = job.gates.check(&summary, error_m, None);
+
+    if let Some(output) = &job.output {
+        let avg = summary.avg;
+        fs::write(output, format!("{}, {}, {}\n", avg.x, avg.y, avg.z))
+            .with_context(|| format!("Failed to write output file at {}", output.display()))?;
+    }
+
+    Ok(Outcome {
+        summary,
+        error_m,
+        failed_gates,
+    })
+}
+
+/// Runs every job, in order, regardless of the outcome of the previous ones.
+pub fn run_jobs(jobs: &[Job]) -> Vec<JobResult> {
+    jobs.iter()
+        .map(|job| JobResult {
+            name: job.name.clone(),
+            outcome: run_job(job),
+        })
+        .collect()
+}
+
+/// Formats a table with the results of each job, followed by the reasons of the failures.
+pub fn format_results(results: &[JobResult]) -> String {
+    let name_width = results
+        .iter()
+        .map(|result| result.name.chars().count())
+        .chain(std::iter::once("Job".len()))
+        .max()
+        .unwrap_or_default();
+
+    let mut table = format!(
+        "{:name_width$}  {:>7}  {:>8}  {:>12}  {:>12}  {:>8}  {:>8}  {:>9}  Status\n",
+        "Job", "Fixes", "Filtered", "Latitude", "Longitude", "Altitude", "σ_h (m)", "Error (m)"
+    );
+    let mut failures = String::new();
+    for result in results {
+        let name = &result.name;
+        match &result.outcome {
+            Ok(outcome) => {
+                let summary = &outcome.summary;
+                let error = outcome
+                    .error_m
+                    .map(|error_m| format!("{error_m:.2}"))
+                    .unwrap_or_else(|| "-".to_owned());
+                let status = if outcome.passed() { "pass" } else { "fail" };
+                writeln!(
+                    table,
+                    "{name:name_width$}  {:>7}  {:>8}  {:>12.7}  {:>12.7}  {:>8.2}  {:>8.2}  {error:>9}  {status}",
+                    summary.n,
+                    summary.n_filtered,
+                    summary.avg.x,
+                    summary.avg.y,
+                    summary.avg.z,
+                    summary.std_dev_horizontal_m(),
+                )
+                .unwrap();
+                for gate in &outcome.failed_gates {
+                    writeln!(failures, "{name}: {gate}").unwrap();
+                }
+            }
+            Err(error) => {
+                writeln!(
+                    table,
+                    "{name:name_width$}  {:>7}  {:>8}  {:>12}  {:>12}  {:>8}  {:>8}  {:>9}  error",
+                    "-", "-", "-", "-", "-", "-", "-"
+                )
+                .unwrap();
+                writeln!(failures, "{name}: {error:#}").unwrap();
+            }
+        }
+    }
+
+    if !failures.is_empty() {
+        table.push('\n');
+        table.push_str(&failures);
+    }
+    table
+}
+
+/// Runs the jobs of the job file at `path` and prints their results. Returns an error if any job
+/// didn't pass.
+pub fn run(path: &Path) -> anyhow::Result<()> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read job file at {}", path.display()))?;
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+    let jobs = parse_job_file(&text, base_dir)
+        .with_context(|| format!("Failed to parse job file at {}", path.display()))?;
+
+    let results = run_jobs(&jobs);
+    print!("{}", format_results(&results));
+
+    let failed = results.iter().filter(|result| !result.passed()).count();
+    if failed > 0 {
+        return Err(anyhow!("{failed} of {} jobs failed", results.len()));
+    }
+    Ok(())
+}
@@ -0,0 +1,670 @@
+//! Command line interface of the `gpsavg` program.
+
+use std::{ops::RangeInclusive, path::PathBuf};
+
+use anyhow::anyhow;
+use chrono::NaiveTime;
+use clap::{ArgMatches, CommandFactory, FromArgMatches};
+use glam::DVec3;
+
+use crate::{
+    age::parse_duration,
+    batch::Gates,
+    config::{ColorChoice, Config, DigitGrouping, OutputFormat, OutputOptions},
+    coords::CoordFormat,
+    histogram::BinLayout,
+    jumps::{JumpDetection, DEFAULT_MAX_GAP_S, DEFAULT_MAX_JUMP_M},
+    parse::{
+        check_input_options, merge_ranges, parse_position, Exclusions, LinePolicy, OptionNames,
+        SentenceClass, Strictness, DEFAULT_DEDUP_M, DEFAULT_REORDER_WINDOW_S,
+    },
+    serial::SerialOptions,
+    stats::{ConfidenceLevel, MAX_FILTER_PASSES},
+    HdopFilter, HeightSystem, InputFormat, InputOptions, Options, ParseOptions, TimeWindow,
+};
+
+/// Command line arguments. They are parsed with [`command`], to use the custom help template,
+/// and then read into this struct by [`action`].
+#[derive(clap::Parser)]
+#[command(author, version, about, long_about = None)]
+#[command(
+    after_help = "Exit status:\n  0  Success\n  1  The inputs or arguments are invalid, or some job of the batch mode failed\n  2  The results don't meet --max-stddev-m, --min-fixes or --max-age"
+)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Input {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[arg(required_unless_present = "serial", conflicts_with = "serial")]
+    /// Input files. The fixes of all of them are combined as if they were a single file.
+    input_path: Vec<PathBuf>,
+
+    #[arg(long, value_name = "PORT")]
+    /// Read the NMEA sentences from this serial port instead of input files, until --duration, --count or --stop-at-se is reached or Ctrl-C is pressed, and then compute the statistics of the sentences read.
+    serial: Option<String>,
+
+    #[arg(long, requires = "serial", conflicts_with = "input_path")]
+    /// Baud rate of the serial port [default: 9600].
+    baud: Option<u32>,
+
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        requires = "serial",
+        conflicts_with = "input_path"
+    )]
+    /// Stop reading from the serial port after this number of seconds.
+    duration: Option<f64>,
+
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..), requires = "serial", conflicts_with = "input_path")]
+    /// Stop reading from the serial port after this number of fixes.
+    count: Option<u32>,
+
+    #[arg(
+        long,
+        value_name = "METERS",
+        requires = "serial",
+        conflicts_with = "input_path"
+    )]
+    /// Stop reading from the serial port once the horizontal standard error of the average is below this number of meters. The standard error is corrected for the autocorrelation of consecutive fixes, which makes it larger than the one of the confidence interval. If the target is never reached, the capture stops at --duration.
+    stop_at_se: Option<f64>,
+
+    #[arg(long, value_name = "SECONDS", requires = "stop_at_se")]
+    /// Don't stop for --stop-at-se before this number of seconds. GPS errors drift over minutes, so the standard error of a short capture is unreliable.
+    min_duration: Option<f64>,
+
+    #[arg(long, requires = "serial", conflicts_with = "input_path")]
+    /// Print the number of fixes read from the serial port so far to stderr.
+    progress: bool,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["serial", "show_histogram", "histogram_csv", "detect_jumps", "timing", "meta_json"]
+    )]
+    /// Read input files multiplexing several receivers, with each line prefixed with the tag of its receiver and a colon, like `A:$GPGGA,...`. The results are returned for each tag found, along with the baseline between the averages when there are two tags.
+    demux_prefix: bool,
+
+    #[arg(short, conflicts_with = "format")]
+    /// Return _only_ the average, with no other text. Useful for passing onto another programs or storing into a file.
+    short: bool,
+
+    #[arg(short, long, requires = "short")]
+    /// Don't print the audit line of the short output to stderr. Otherwise a single line summarizing the run is printed there, as `# gpsavg: N fixes (M after FILTERS), DURATION, σ_h=STD_DEVm`, so that the logs of a pipeline capturing only stdout show what produced the results.
+    quiet: bool,
+
+    #[arg(short = 'l')]
+    /// Return additionally the histogram for each of the coordinates. Useful for detecting anomalies.
+    show_histogram: bool,
+
+    #[arg(long, conflicts_with = "short")]
+    /// Print the average in every coordinate format, as decimal degrees, as degrees, minutes and seconds, and in UTM, besides the one of --coord-format. The JSON results gain them too.
+    all_formats: bool,
+
+    #[arg(long, conflicts_with_all = ["serial", "demux_prefix"])]
+    /// Include in the JSON results the SHA-256 of each input file, the ranges of lines of the fixes which remain after filtering outliers, and the SHA-256 of their sentences, so that `gpsavg verify-provenance` can check which logs and which sentences the average was computed from. Requires --format json and NMEA input.
+    provenance: bool,
+
+    #[arg(long, conflicts_with_all = ["short", "format", "demux_prefix"])]
+    /// After the results, explain them in plain language: what the standard deviation means in meters, whether there are enough fixes over a long enough time, which warnings fired and what to do about them, and a verdict on how far the average can be trusted.
+    explain: bool,
+
+    #[arg(long, value_name = "PATH")]
+    /// Write the histogram of each of the coordinates to this file as CSV, with a row per bin holding the coordinate, the bounds of the bin and its count.
+    histogram_csv: Option<PathBuf>,
+
+    #[arg(long)]
+    /// Count the positions filtered as outliers in the histograms too, in a second column and in red at the end of the bars. Otherwise the histograms only count the positions which remain after filtering.
+    hist_include_rejected: bool,
+
+    #[arg(long, value_name = "COUNT|auto", value_parser = parse_bins)]
+    /// Total number of bins of the histogram, or `auto` to choose the width of the bins of each coordinate with the Freedman–Diaconis rule, 2·IQR·n^(-1/3), up to 200 bins. When given, the bins span the whole range of the filtered data instead of a range based on the standard deviation.
+    bins: Option<BinLayout>,
+
+    #[arg(long, default_value_t = 3., conflicts_with = "bins")]
+    /// Range of the histogram at each side of the average, measured in standard deviations.
+    hist_span: f64,
+
+    #[arg(long, default_value_t = 6, value_parser = clap::value_parser!(u32).range(1..), conflicts_with = "bins")]
+    /// Number of histogram bins per standard deviation.
+    divisions: u32,
+
+    #[arg(long, default_value_t = 3.)]
+    /// Positions further than this number of standard deviations from the average on any coordinate are filtered as outliers.
+    sigma: f64,
+
+    #[arg(long)]
+    /// Filter outliers using the median and median absolute deviation of each coordinate instead of the average and standard deviation, which are less sensitive to outliers. The median and median absolute deviation are returned additionally.
+    robust: bool,
+
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..), conflicts_with = "iterate")]
+    /// Maximum number of passes of the outlier filter. The average and standard deviation are recomputed from the remaining positions before each pass, and filtering stops early when a pass removes no positions.
+    iterations: u32,
+
+    #[arg(long)]
+    /// Repeat the outlier filter until no more positions are removed (up to 20 passes).
+    iterate: bool,
+
+    #[arg(long, value_enum, default_value_t = ConfidenceLevel::P95)]
+    /// Confidence level, in percent, of the confidence interval of the average.
+    confidence: ConfidenceLevel,
+
+    #[arg(long)]
+    /// Sort the fixes before computing the statistics, so that the results are identical down to the last digit whatever the order of the fixes, such as when the input files are given in another order. The fixes are still read in order for --every, --max-samples, --dedup and --hdop-spike-window.
+    deterministic: bool,
+
+    #[arg(long)]
+    /// Report the time gaps and the position jumps between consecutive fixes, with the lines of the input files they are at. Position jumps suggest that the log holds more than one occupation point, and a warning is printed before the results if there is any.
+    detect_jumps: bool,
+
+    #[arg(long, value_name = "SECONDS", requires = "detect_jumps")]
+    /// Consecutive fixes further apart in time than this are reported by --detect-jumps as a time gap [default: 10].
+    max_gap: Option<f64>,
+
+    #[arg(long, value_name = "METERS", requires = "detect_jumps")]
+    /// Consecutive fixes further apart horizontally than this are reported by --detect-jumps as a position jump [default: 5].
+    max_jump: Option<f64>,
+
+    #[arg(long, requires = "detect_jumps")]
+    /// Compute the average and the standard deviation of each segment between position jumps too, and return them after the results of all the fixes.
+    split_on_jump: bool,
+
+    #[arg(long)]
+    /// Report how long each stage of the program took, along with the parsing throughput.
+    timing: bool,
+
+    #[arg(long)]
+    /// Print a JSON object describing the run to stderr.
+    meta_json: bool,
+
+    #[arg(long, default_value_t = 1.)]
+    /// Maximum time difference in seconds between a GGA fix and the GST or RMC sentences associated to it, used when no sentence has the same time as the fix.
+    staleness: f64,
+
+    #[arg(long, value_name = "SECONDS")]
+    /// Put back in order the sentences up to this number of seconds older than the newest one read, by their timestamps, before associating the GSA, GST and RMC sentences with the GGA fixes. For sources delivering sentences in bursts with reordering inside each burst. Sentences arriving after newer ones were put in order are dropped, and their number is returned additionally [default: 2 with --serial, off otherwise].
+    reorder_window: Option<f64>,
+
+    #[arg(long)]
+    /// Skip the lines of the input files which can't be parsed, instead of failing, as a shorthand for --lenient-for gga,other. The number of lines skipped is returned additionally.
+    lenient: bool,
+
+    #[arg(long, value_enum, value_name = "CLASS", value_delimiter = ',')]
+    /// Fail on the lines of these classes of sentences which can't be parsed or whose checksum doesn't match, taking precedence over --lenient.
+    strict_for: Vec<SentenceClass>,
+
+    #[arg(long, value_enum, value_name = "CLASS", value_delimiter = ',')]
+    /// Skip the lines of these classes of sentences which can't be parsed, like --lenient does for every class.
+    lenient_for: Vec<SentenceClass>,
+
+    #[arg(long, value_name = "BOOL", default_value_t = true, action = clap::ArgAction::Set)]
+    /// Check the signs of the latitude and longitude of GGA sentences against their N/S and E/W letters, correcting them to match the letters when they disagree. The sentences corrected are counted with a warning.
+    verify_hemisphere: bool,
+
+    #[arg(long)]
+    /// Collapse runs of consecutive fixes with the same position into their first fix, since stationary receivers may repeat the same fix many times. The number of fixes collapsed is returned additionally.
+    dedup: bool,
+
+    #[arg(long, value_name = "METERS", requires = "dedup")]
+    /// Consecutive fixes closer than this distance to the first fix of their run, in every direction, are collapsed by --dedup [default: 0.001].
+    dedup_epsilon: Option<f64>,
+
+    #[arg(long, value_enum, default_value_t = InputFormat::Nmea)]
+    /// Format of the input file.
+    input_format: InputFormat,
+
+    #[arg(long, value_enum, default_value_t = HeightSystem::Orthometric)]
+    /// Height system of the altitudes. Ellipsoidal heights add the geoid separation of each GGA sentence to its altitude, and ignore the fixes without one; the number of fixes ignored is returned additionally.
+    height: HeightSystem,
+
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    /// Column (starting at 1) of the plain input holding the weight of each position. When given, the weighted average is returned additionally.
+    weight_column: Option<u32>,
+
+    #[arg(long, value_name = "HH:MM:SS", value_parser = parse_time)]
+    /// Ignore the fixes before this UTC time. May be later than --to, for logs crossing midnight.
+    from: Option<NaiveTime>,
+
+    #[arg(long, value_name = "HH:MM:SS", value_parser = parse_time)]
+    /// Ignore the fixes after this UTC time.
+    to: Option<NaiveTime>,
+
+    #[arg(long, value_name = "HHMMSS", value_parser = parse_nmea_time, conflicts_with = "from")]
+    /// Same as --from, with the time written as in NMEA sentences.
+    start: Option<NaiveTime>,
+
+    #[arg(long, value_name = "HHMMSS", value_parser = parse_nmea_time, conflicts_with = "to")]
+    /// Same as --to, with the time written as in NMEA sentences.
+    end: Option<NaiveTime>,
+
+    #[arg(long, value_name = "FROM..TO", value_parser = parse_line_range)]
+    /// Ignore the fixes read from these lines of each input file, both included, before anything else. May be given several times. The number of fixes excluded is returned additionally.
+    exclude_lines: Vec<RangeInclusive<usize>>,
+
+    #[arg(long, value_name = "FROM..TO", value_parser = parse_time_range)]
+    /// Ignore the fixes between these UTC times, both included and given as HH:MM or HH:MM:SS, before anything else. The range may wrap around midnight, and may be given several times.
+    exclude_time: Vec<TimeWindow>,
+
+    #[arg(long)]
+    /// Ignore the fixes with a HDOP above this value.
+    max_hdop: Option<f64>,
+
+    #[arg(long, value_name = "SECONDS", requires = "max_hdop")]
+    /// Ignore also the fixes up to this number of seconds after one with a HDOP above --max-hdop, while the position recovers.
+    hdop_spike_window: Option<f64>,
+
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    /// Keep only every n-th fix, to reduce the correlation between consecutive fixes.
+    every: u32,
+
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    /// Subsample the fixes uniformly down to this number, if there are more.
+    max_samples: Option<u32>,
+
+    #[arg(long, value_name = "LAT,LON,ALT", value_parser = parse_reference, allow_hyphen_values = true)]
+    /// Known position to compare the average against. The error is returned in meters, as east, north and up components along with the horizontal and 3D distances.
+    reference: Option<DVec3>,
+
+    #[arg(long, value_name = "METERS")]
+    /// Fail with exit status 2 if the horizontal standard deviation of the filtered fixes is above this value. The results are printed anyway.
+    max_stddev_m: Option<f64>,
+
+    #[arg(long)]
+    /// Fail with exit status 2 if fewer fixes than this remain after filtering outliers. The results are printed anyway.
+    min_fixes: Option<usize>,
+
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    /// Fail with exit status 2 if the last filtered fix is older than this, like 90m, 2h or 1d, relative to the current time. The date of the fix is taken from the RMC sentences. The results are printed anyway.
+    max_age: Option<f64>,
+
+    #[arg(short, long, value_name = "PATH")]
+    /// Write the results to this file, in the format given by --format, and print the text report to stdout. Existing files aren't overwritten unless --force is given.
+    output: Option<PathBuf>,
+
+    #[arg(long, requires = "output")]
+    /// Overwrite the file given by --output if it exists.
+    force: bool,
+
+    #[arg(long, value_enum, default_value_t = OutputFormat::Plain)]
+    /// Format of the results.
+    format: OutputFormat,
+
+    #[arg(long, value_enum, default_value_t = CoordFormat::Dd)]
+    /// Format of the coordinates of the average and the other positions printed as text, including the short output.
+    coord_format: CoordFormat,
+
+    #[arg(long, value_enum, default_value_t = DigitGrouping::Comma)]
+    /// Separator between groups of thousands in the counts printed as text. The JSON output and the coordinates are never grouped.
+    group_digits: DigitGrouping,
+
+    #[arg(long, value_enum, default_value_t = ColorChoice::Auto)]
+    /// When to style the text printed with colors, bold and italics.
+    color: ColorChoice,
+
+    #[arg(long)]
+    /// Print the configuration and the stages of the run without reading the input files, as text or as JSON depending on --format.
+    dry_run: bool,
+}
+
+/// Parses a time of the day given as `HH:MM:SS`, with optional fractional seconds.
+fn parse_time(arg: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(arg, "%H:%M:%S%.f")
+        .map_err(|_| format!("expected a time as HH:MM:SS, found '{arg}'"))
+}
+
+/// Parses a time of the day given as `HHMMSS`, with optional fractional seconds.
+fn parse_nmea_time(arg: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(arg, "%H%M%S%.f")
+        .map_err(|_| format!("expected a time as HHMMSS, found '{arg}'"))
+}
+
+/// Parses a range of lines given as `FROM..TO`, both included.
+fn parse_line_range(arg: &str) -> Result<RangeInclusive<usize>, String> {
+    let invalid = || format!("expected a range of lines as FROM..TO, found '{arg}'");
+    let (from, to) = arg.split_once("..").ok_or_else(invalid)?;
+    let from = from.parse::<usize>().map_err(|_| invalid())?;
+    let to = to.parse::<usize>().map_err(|_| invalid())?;
+    if from == 0 || from > to {
+        return Err(format!(
+            "the range of lines '{arg}' must start at line 1 or later, and not after its end"
+        ));
+    }
+    Ok(from..=to)
+}
+
+/// Parses a range of times of the day given as `FROM..TO`, both included, with the times as
+/// `HH:MM` or `HH:MM:SS`.
+fn parse_time_range(arg: &str) -> Result<TimeWindow, String> {
+    let invalid = || format!("expected a range of times as HH:MM..HH:MM, found '{arg}'");
+    let parse = |time: &str| {
+        NaiveTime::parse_from_str(time, "%H:%M:%S%.f")
+            .or_else(|_| NaiveTime::parse_from_str(time, "%H:%M"))
+            .map_err(|_| invalid())
+    };
+    let (from, to) = arg.split_once("..").ok_or_else(invalid)?;
+    Ok(TimeWindow {
+        from: Some(parse(from)?),
+        to: Some(parse(to)?),
+    })
+}
+
+/// Parses the number of bins of the histogram, or `auto`.
+fn parse_bins(arg: &str) -> Result<BinLayout, String> {
+    if arg == "auto" {
+        return Ok(BinLayout::Auto);
+    }
+    match arg.parse::<u32>() {
+        Ok(count) if count > 0 => Ok(BinLayout::Count(count)),
+        _ => Err(format!(
+            "expected a positive number of bins or 'auto', found '{arg}'"
+        )),
+    }
+}
+
+fn parse_reference(arg: &str) -> Result<DVec3, String> {
+    parse_position(arg).map_err(|err| err.to_string())
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Run the jobs listed in a TOML job file, and print a table with the results of each one.
+    Batch {
+        /// Job file. Paths in it are relative to the directory of the file.
+        job_file: PathBuf,
+    },
+    /// Check that the parsing, the filtering, the statistics and the formatting of this binary
+    /// give the expected results for embedded golden data, and print the outcome of each check.
+    Selftest,
+    /// Compare two results saved with --format json, without the logs they were computed from: the displacement of the average, and the changes in the spread, the counts and the duration.
+    SummaryDiff {
+        /// Older results.
+        old: PathBuf,
+        /// Newer results.
+        new: PathBuf,
+        #[arg(long, value_name = "METERS")]
+        /// Check whether the horizontal standard deviation of each results is at most this value.
+        max_stddev_m: Option<f64>,
+        #[arg(long)]
+        /// Check whether each results have at least this number of fixes after filtering outliers.
+        min_fixes: Option<usize>,
+        #[arg(long, value_name = "METERS")]
+        /// Check whether the horizontal error from the reference of each results is at most this value.
+        max_error_m: Option<f64>,
+    },
+    /// Check that the input files hash to the provenance of results saved with --provenance, and that the sentences of the accepted fixes are the same.
+    VerifyProvenance {
+        /// Results saved with --format json --provenance.
+        results: PathBuf,
+        /// Input files the results were computed from, in the same order.
+        #[arg(required = true)]
+        input_paths: Vec<PathBuf>,
+    },
+}
+
+/// What the program was asked to do.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action {
+    /// Run the jobs of a job file.
+    Batch { job_file: PathBuf },
+    /// Run the checks against the embedded golden data.
+    Selftest,
+    /// Compare two JSON results, checking each against the gates.
+    SummaryDiff {
+        old: PathBuf,
+        new: PathBuf,
+        gates: Gates,
+    },
+    /// Check the input files against the provenance of JSON results.
+    VerifyProvenance {
+        results: PathBuf,
+        input_paths: Vec<PathBuf>,
+    },
+    /// Compute the statistics of the input files, or only describe how with `dry_run`.
+    Run { config: Box<Config>, dry_run: bool },
+}
+
+/// Combines --lenient, --lenient-for and --strict-for into the strictness of each class of
+/// sentences.
+fn line_policy(
+    lenient: bool,
+    lenient_for: &[SentenceClass],
+    strict_for: &[SentenceClass],
+) -> anyhow::Result<LinePolicy> {
+    let mut policy = if lenient {
+        LinePolicy::lenient()
+    } else {
+        LinePolicy::default()
+    };
+    for class in lenient_for {
+        policy.set(*class, Strictness::Lenient);
+    }
+    for class in strict_for {
+        if lenient_for.contains(class) {
+            return Err(anyhow!(
+                "--strict-for and --lenient-for can't both be given for the same class"
+            ));
+        }
+        policy.set(*class, Strictness::Strict);
+    }
+    Ok(policy)
+}
+
+/// Returns the command line parser of the program.
+pub fn command() -> clap::Command {
+    Input::command().help_template(include_str!("help_template"))
+}
+
+/// Builds the action from the parsed command line arguments, checking the values which clap
+/// doesn't.
+pub fn action(matches: &ArgMatches) -> anyhow::Result<Action> {
+    let input = Input::from_arg_matches(matches)?;
+    match input.command {
+        Some(Command::Batch { job_file }) => return Ok(Action::Batch { job_file }),
+        Some(Command::Selftest) => return Ok(Action::Selftest),
+        Some(Command::SummaryDiff {
+            old,
+            new,
+            max_stddev_m,
+            min_fixes,
+            max_error_m,
+        }) => {
+            return Ok(Action::SummaryDiff {
+                old,
+                new,
+                gates: Gates {
+                    max_std_dev_m: max_stddev_m,
+                    min_fixes,
+                    max_error_m,
+                    max_age_s: None,
+                },
+            })
+        }
+        Some(Command::VerifyProvenance {
+            results,
+            input_paths,
+        }) => {
+            return Ok(Action::VerifyProvenance {
+                results,
+                input_paths,
+            })
+        }
+        None => {}
+    }
+
+    let input_paths = input.input_path;
+    let serial = input.serial.map(|port| SerialOptions {
+        port,
+        baud_rate: input.baud.unwrap_or(9600),
+        duration_s: input.duration,
+        count: input.count.map(|count| count as usize),
+        stop_at_se_m: input.stop_at_se,
+        min_duration_s: input.min_duration,
+        progress: input.progress,
+    });
+    let bins = input.bins;
+    let hist_span = input.hist_span;
+    let divisions_per_sigma = input.divisions;
+    let cutoff = input.sigma;
+    let robust = input.robust;
+    let max_passes = if input.iterate {
+        MAX_FILTER_PASSES
+    } else {
+        input.iterations
+    };
+    let reference = input.reference;
+    let input_options = InputOptions {
+        format: input.input_format,
+        weight_column: input.weight_column.map(|column| column as usize),
+        demux: input.demux_prefix,
+        exclusions: Exclusions {
+            lines: merge_ranges(input.exclude_lines),
+            times: input.exclude_time,
+        },
+        parse: ParseOptions {
+            staleness: input.staleness,
+            policy: line_policy(input.lenient, &input.lenient_for, &input.strict_for)?,
+            verify_hemisphere: input.verify_hemisphere,
+            dedup_m: input
+                .dedup
+                .then(|| input.dedup_epsilon.unwrap_or(DEFAULT_DEDUP_M)),
+            reorder_window_s: input
+                .reorder_window
+                .or(serial.is_some().then_some(DEFAULT_REORDER_WINDOW_S)),
+        },
+        height: input.height,
+        hdop: input.max_hdop.map(|max_hdop| HdopFilter {
+            max_hdop,
+            spike_window_s: input.hdop_spike_window,
+        }),
+        every: input.every as usize,
+        max_samples: input.max_samples.map(|max_samples| max_samples as usize),
+        window: match (input.from.or(input.start), input.to.or(input.end)) {
+            (None, None) => None,
+            (from, to) => Some(TimeWindow { from, to }),
+        },
+    };
+    check_input_options(&input_options, OptionNames::Arguments)?;
+    if serial.is_some() && input_options.format != InputFormat::Nmea {
+        return Err(anyhow!("--serial requires --input-format nmea"));
+    }
+    if serial
+        .as_ref()
+        .and_then(|serial| serial.duration_s)
+        .is_some_and(|duration| duration.is_nan() || duration <= 0.)
+    {
+        return Err(anyhow!("--duration must be a positive number of seconds"));
+    }
+    if let Some(serial) = &serial {
+        if serial
+            .stop_at_se_m
+            .is_some_and(|target| target.is_nan() || target <= 0.)
+        {
+            return Err(anyhow!("--stop-at-se must be a positive number of meters"));
+        }
+        if let Some(min_duration) = serial.min_duration_s {
+            if min_duration.is_nan() || min_duration < 0. {
+                return Err(anyhow!(
+                    "--min-duration must be a non-negative number of seconds"
+                ));
+            }
+            if serial
+                .duration_s
+                .is_some_and(|duration| min_duration > duration)
+            {
+                return Err(anyhow!("--min-duration can't be longer than --duration"));
+            }
+        }
+    }
+    if input.provenance {
+        if input.format != OutputFormat::Json {
+            return Err(anyhow!("--provenance requires --format json"));
+        }
+        if input_options.format != InputFormat::Nmea {
+            return Err(anyhow!("--provenance requires --input-format nmea"));
+        }
+    }
+    if hist_span.is_nan() || hist_span <= 0. {
+        return Err(anyhow!(
+            "--hist-span must be a positive number of standard deviations"
+        ));
+    }
+    if cutoff.is_nan() || cutoff <= 0. {
+        return Err(anyhow!(
+            "--sigma must be a positive number of standard deviations"
+        ));
+    }
+    let gates = Gates {
+        max_std_dev_m: input.max_stddev_m,
+        min_fixes: input.min_fixes,
+        max_error_m: None,
+        max_age_s: input.max_age,
+    };
+    if gates.max_age_s.is_some() && input_options.format != InputFormat::Nmea {
+        return Err(anyhow!("--max-age requires --input-format nmea"));
+    }
+    if gates
+        .max_std_dev_m
+        .is_some_and(|max_std_dev_m| max_std_dev_m.is_nan() || max_std_dev_m < 0.)
+    {
+        return Err(anyhow!(
+            "--max-stddev-m must be a non-negative number of meters"
+        ));
+    }
+    let jumps = input.detect_jumps.then(|| JumpDetection {
+        max_gap_s: input.max_gap.unwrap_or(DEFAULT_MAX_GAP_S),
+        max_jump_m: input.max_jump.unwrap_or(DEFAULT_MAX_JUMP_M),
+        split: input.split_on_jump,
+    });
+    if let Some(jumps) = jumps {
+        if jumps.max_gap_s.is_nan() || jumps.max_gap_s <= 0. {
+            return Err(anyhow!("--max-gap must be a positive number of seconds"));
+        }
+        if jumps.max_jump_m.is_nan() || jumps.max_jump_m < 0. {
+            return Err(anyhow!(
+                "--max-jump must be a non-negative number of meters"
+            ));
+        }
+    }
+    let options = Options {
+        cutoff,
+        robust,
+        max_passes,
+        bin_layout: bins.unwrap_or(BinLayout::Sigma {
+            span: hist_span,
+            divisions: divisions_per_sigma,
+        }),
+        weighted: input_options.weight_column.is_some(),
+        hist_include_rejected: input.hist_include_rejected,
+        confidence: input.confidence,
+        deterministic: input.deterministic,
+    };
+
+    Ok(Action::Run {
+        config: Box::new(Config {
+            input_paths,
+            serial,
+            input: input_options,
+            options,
+            reference,
+            gates,
+            jumps,
+            output: OutputOptions {
+                format: input.format,
+                coord_format: input.coord_format,
+                digit_grouping: input.group_digits,
+                color: input.color,
+                short: input.short,
+                quiet: input.quiet,
+                show_histogram: input.show_histogram,
+                all_formats: input.all_formats,
+                provenance: input.provenance,
+                explain: input.explain,
+                timing: input.timing,
+                meta_json: input.meta_json,
+                path: input.output,
+                force: input.force,
+                histogram_csv: input.histogram_csv,
+            },
+        }),
+        dry_run: input.dry_run,
+    })
+}
@@ -0,0 +1,593 @@
+//! Configuration of a run, and the plan of what it does.
+
+use std::{
+    ffi::OsStr,
+    fmt,
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+use glam::DVec3;
+
+use crate::{
+    batch::Gates,
+    coords::CoordFormat,
+    histogram::{BinLayout, MAX_AUTO_BINS},
+    jumps::JumpDetection,
+    serial::SerialOptions,
+    HeightSystem, InputFormat, InputOptions, Options,
+};
+
+/// Format of the results printed to stdout.
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// Human readable text.
+    Plain,
+    /// A JSON object.
+    Json,
+}
+
+/// Separator between groups of thousands in the counts printed as text.
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DigitGrouping {
+    /// Like 2,846,305.
+    Comma,
+    /// A thin space, like 2 846 305.
+    Thin,
+    /// No separator, like 2846305.
+    None,
+}
+
+impl DigitGrouping {
+    /// Formats a count with its digits grouped in thousands.
+    pub fn format(self, count: usize) -> String {
+        let separator = match self {
+            DigitGrouping::Comma => ',',
+            DigitGrouping::Thin => '\u{2009}',
+            DigitGrouping::None => return count.to_string(),
+        };
+        let digits = count.to_string();
+        let mut formatted = String::with_capacity(digits.len() * 4 / 3);
+        for (idx, digit) in digits.chars().enumerate() {
+            if idx > 0 && (digits.len() - idx).is_multiple_of(3) {
+                formatted.push(separator);
+            }
+            formatted.push(digit);
+        }
+        formatted
+    }
+}
+
+/// When the text printed is styled with colors, bold and italics.
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorChoice {
+    /// Only when stdout is a terminal and the `NO_COLOR` environment variable isn't set.
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Returns whether to style the text, given whether stdout is a terminal and the value of the
+    /// `NO_COLOR` environment variable, which disables the styling unless it is empty.
+    pub fn enabled(self, is_terminal: bool, no_color: Option<&OsStr>) -> bool {
+        match self {
+            ColorChoice::Auto => is_terminal && no_color.is_none_or(OsStr::is_empty),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+/// What is printed after computing the statistics.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutputOptions {
+    pub format: OutputFormat,
+    /// Format of the coordinates of the positions printed as text.
+    pub coord_format: CoordFormat,
+    /// Separator between groups of thousands in the counts printed as text.
+    pub digit_grouping: DigitGrouping,
+    pub color: ColorChoice,
+    /// Print only the average.
+    pub short: bool,
+    /// Don't print the audit line of the short output to stderr.
+    pub quiet: bool,
+    pub show_histogram: bool,
+    /// Print the average in every coordinate format.
+    pub all_formats: bool,
+    /// Include the provenance of the fixes in the JSON results.
+    pub provenance: bool,
+    /// Print a plain-language interpretation of the results after them.
+    pub explain: bool,
+    /// Print the time taken by each stage to stderr.
+    pub timing: bool,
+    /// Print a JSON object describing the run to stderr.
+    pub meta_json: bool,
+    /// File the results are written to, in `format`, while the text report is printed to stdout.
+    pub path: Option<PathBuf>,
+    /// Overwrite the file at `path` if it exists.
+    pub force: bool,
+    /// File the histogram is written to, as CSV.
+    pub histogram_csv: Option<PathBuf>,
+}
+
+impl Default for OutputOptions {
+    fn default() -> Self {
+        Self {
+            format: OutputFormat::Plain,
+            coord_format: CoordFormat::Dd,
+            digit_grouping: DigitGrouping::Comma,
+            color: ColorChoice::Auto,
+            short: false,
+            quiet: false,
+            show_histogram: false,
+            all_formats: false,
+            provenance: false,
+            explain: false,
+            timing: false,
+            meta_json: false,
+            path: None,
+            force: false,
+            histogram_csv: None,
+        }
+    }
+}
+
+impl OutputOptions {
+    /// Writes the results to the file at `path`, if there is one. Fails if the file exists, unless
+    /// `force` is set.
+    pub fn write_results(&self, results: &str) -> anyhow::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let file = if self.force {
+            File::create(path)
+        } else {
+            OpenOptions::new().write(true).create_new(true).open(path)
+        };
+        let mut file = match file {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                return Err(anyhow!(
+                    "Output file at {} already exists; pass --force to overwrite it",
+                    path.display()
+                ))
+            }
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("Failed to write output file at {}", path.display()))
+            }
+        };
+        writeln!(file, "{results}")
+            .with_context(|| format!("Failed to write output file at {}", path.display()))
+    }
+}
+
+/// Everything which determines what a run does.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    pub input_paths: Vec<PathBuf>,
+    /// Read the sentences from a serial port instead of the input files.
+    pub serial: Option<SerialOptions>,
+    pub input: InputOptions,
+    pub options: Options,
+    /// Known position to compare the average against, as (latitude, longitude, altitude).
+    pub reference: Option<DVec3>,
+    /// Quality thresholds which the run must meet to succeed.
+    pub gates: Gates,
+    /// Report the time gaps and position jumps between consecutive fixes.
+    pub jumps: Option<JumpDetection>,
+    pub output: OutputOptions,
+}
+
+impl Config {
+    /// Describes the stages of the run with this configuration. Only checks that the input files
+    /// exist, without reading them.
+    pub fn plan(&self) -> Plan {
+        let staleness_s =
+            (self.input.format == InputFormat::Nmea).then_some(self.input.parse.staleness);
+        let sources = match &self.serial {
+            Some(serial) => vec![Source {
+                path: PathBuf::from(&serial.port),
+                exists: Path::new(&serial.port).exists(),
+                format: InputFormat::Nmea,
+                staleness_s,
+                reorder_window_s: self.input.parse.reorder_window_s,
+                weight_column: None,
+                tagged: false,
+                baud_rate: Some(serial.baud_rate),
+            }],
+            None => self
+                .input_paths
+                .iter()
+                .map(|path| Source {
+                    path: path.clone(),
+                    exists: path.is_file(),
+                    format: self.input.format,
+                    staleness_s,
+                    reorder_window_s: self.input.parse.reorder_window_s,
+                    weight_column: self.input.weight_column,
+                    tagged: self.input.demux,
+                    baud_rate: None,
+                })
+                .collect(),
+        };
+
+        let mut filters = Vec::new();
+        if let Some(epsilon_m) = self.input.parse.dedup_m {
+            filters.push(Filter::Duplicates { epsilon_m });
+        }
+        let exclusions = &self.input.exclusions;
+        if !exclusions.is_empty() {
+            filters.push(Filter::Exclusions {
+                lines: exclusions
+                    .lines
+                    .iter()
+                    .map(|lines| [*lines.start(), *lines.end()])
+                    .collect(),
+                times: exclusions
+                    .times
+                    .iter()
+                    .filter_map(|times| Some([times.from?.to_string(), times.to?.to_string()]))
+                    .collect(),
+            });
+        }
+        if self.input.height == HeightSystem::Ellipsoidal {
+            filters.push(Filter::NoGeoidSeparation);
+        }
+        if let Some(window) = self.input.window {
+            let format = |time: Option<chrono::NaiveTime>| time.map(|time| time.to_string());
+            filters.push(Filter::TimeWindow {
+                from: format(window.from),
+                to: format(window.to),
+            });
+        }
+        if let Some(hdop) = self.input.hdop {
+            filters.push(Filter::Hdop {
+                max_hdop: hdop.max_hdop,
+                spike_window_s: hdop.spike_window_s,
+            });
+        }
+        if self.input.every > 1 || self.input.max_samples.is_some() {
+            filters.push(Filter::Decimation {
+                every: self.input.every,
+                max_samples: self.input.max_samples,
+            });
+        }
+        filters.push(Filter::Outliers {
+            center: if self.options.robust {
+                "median"
+            } else {
+                "average"
+            },
+            cutoff_sigma: self.options.cutoff,
+            max_passes: self.options.max_passes,
+        });
+
+        let mut estimators = vec![
+            Estimator::Average,
+            Estimator::StdDev,
+            Estimator::ConfidenceInterval {
+                level: self.options.confidence.percent(),
+            },
+        ];
+        if self.options.weighted {
+            estimators.push(Estimator::WeightedAverage);
+        }
+        if self.options.robust {
+            estimators.push(Estimator::MedianAndMad);
+        }
+        if let Some(reference) = self.reference {
+            estimators.push(Estimator::ReferenceError {
+                reference: reference.to_array(),
+            });
+        }
+        if let Some(jumps) = self.jumps {
+            estimators.push(Estimator::Jumps {
+                max_gap_s: jumps.max_gap_s,
+                max_jump_m: jumps.max_jump_m,
+                split: jumps.split,
+            });
+        }
+        if self.output.show_histogram {
+            estimators.push(Estimator::Histogram {
+                bin_layout: self.options.bin_layout,
+                include_rejected: self.options.hist_include_rejected,
+            });
+        }
+
+        let mut outputs = vec![Output {
+            path: "stdout".to_owned(),
+            contents: if self.output.short {
+                "average"
+            } else if self.output.path.is_some() {
+                // The JSON results go to the file instead.
+                "results as text"
+            } else {
+                match self.output.format {
+                    OutputFormat::Plain => "results as text",
+                    OutputFormat::Json => "results as JSON",
+                }
+            },
+        }];
+        if let Some(path) = &self.output.path {
+            outputs.push(Output {
+                path: path.display().to_string(),
+                contents: match self.output.format {
+                    OutputFormat::Plain => "average",
+                    OutputFormat::Json => "results as JSON",
+                },
+            });
+        }
+        if let Some(path) = &self.output.histogram_csv {
+            outputs.push(Output {
+                path: path.display().to_string(),
+                contents: "histogram as CSV",
+            });
+        }
+        if self.output.timing {
+            outputs.push(Output {
+                path: "stderr".to_owned(),
+                contents: "timing",
+            });
+        }
+        if self.output.meta_json {
+            outputs.push(Output {
+                path: "stderr".to_owned(),
+                contents: "metadata as JSON",
+            });
+        }
+
+        Plan {
+            sources,
+            filters,
+            estimators,
+            outputs,
+        }
+    }
+}
+
+/// Stages of a run, in order, as printed by `--dry-run`.
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct Plan {
+    pub sources: Vec<Source>,
+    pub filters: Vec<Filter>,
+    pub estimators: Vec<Estimator>,
+    pub outputs: Vec<Output>,
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct Source {
+    pub path: PathBuf,
+    pub exists: bool,
+    pub format: InputFormat,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub staleness_s: Option<f64>,
+    /// Seconds the sentences are kept to put them back in order.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reorder_window_s: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub weight_column: Option<usize>,
+    /// Whether the lines are prefixed with the tag of their receiver.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub tagged: bool,
+    /// Baud rate of the serial port, if the source is one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub baud_rate: Option<u32>,
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+#[serde(tag = "filter", rename_all = "snake_case")]
+pub enum Filter {
+    Duplicates {
+        epsilon_m: f64,
+    },
+    /// Ranges of lines and of times, both ends included.
+    Exclusions {
+        lines: Vec<[usize; 2]>,
+        times: Vec<[String; 2]>,
+    },
+    NoGeoidSeparation,
+    TimeWindow {
+        from: Option<String>,
+        to: Option<String>,
+    },
+    Hdop {
+        max_hdop: f64,
+        spike_window_s: Option<f64>,
+    },
+    Decimation {
+        every: usize,
+        max_samples: Option<usize>,
+    },
+    Outliers {
+        center: &'static str,
+        cutoff_sigma: f64,
+        max_passes: u32,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+#[serde(tag = "estimator", rename_all = "snake_case")]
+pub enum Estimator {
+    Average,
+    StdDev,
+    ConfidenceInterval {
+        level: u32,
+    },
+    WeightedAverage,
+    MedianAndMad,
+    ReferenceError {
+        reference: [f64; 3],
+    },
+    Jumps {
+        max_gap_s: f64,
+        max_jump_m: f64,
+        split: bool,
+    },
+    Histogram {
+        bin_layout: BinLayout,
+        include_rejected: bool,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct Output {
+    pub path: String,
+    pub contents: &'static str,
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Sources:")?;
+        for source in &self.sources {
+            let format = match source.format {
+                InputFormat::Nmea => "NMEA",
+                InputFormat::Plain => "plain",
+            };
+            write!(f, "  {} ({format}", source.path.display())?;
+            if let Some(baud_rate) = source.baud_rate {
+                write!(f, ", serial port at {baud_rate} baud")?;
+            }
+            if let Some(staleness_s) = source.staleness_s {
+                write!(f, ", staleness {staleness_s}s")?;
+            }
+            if let Some(reorder_window_s) = source.reorder_window_s {
+                write!(f, ", reordered within {reorder_window_s}s")?;
+            }
+            if let Some(weight_column) = source.weight_column {
+                write!(f, ", weights in column {weight_column}")?;
+            }
+            if source.tagged {
+                write!(f, ", tagged receivers")?;
+            }
+            writeln!(f, "){}", if source.exists { "" } else { " MISSING" })?;
+        }
+
+        writeln!(f, "Filters:")?;
+        for (idx, filter) in self.filters.iter().enumerate() {
+            write!(f, "  {}. ", idx + 1)?;
+            match filter {
+                Filter::Duplicates { epsilon_m } => writeln!(
+                    f,
+                    "Consecutive fixes within {epsilon_m}m of the first one of their run"
+                )?,
+                Filter::Exclusions { lines, times } => {
+                    let ranges = lines
+                        .iter()
+                        .map(|[from, to]| format!("lines {from} to {to}"))
+                        .chain(
+                            times
+                                .iter()
+                                .map(|[from, to]| format!("times {from} to {to}")),
+                        )
+                        .collect::<Vec<_>>();
+                    writeln!(f, "Fixes in the excluded ranges: {}", ranges.join(", "))?
+                }
+                Filter::NoGeoidSeparation => {
+                    writeln!(f, "Fixes without geoid separation, for ellipsoidal heights")?
+                }
+                Filter::TimeWindow { from, to } => writeln!(
+                    f,
+                    "Time window from {} to {}",
+                    from.as_deref().unwrap_or("the start"),
+                    to.as_deref().unwrap_or("the end")
+                )?,
+                Filter::Hdop {
+                    max_hdop,
+                    spike_window_s,
+                } => {
+                    write!(f, "HDOP above {max_hdop}")?;
+                    match spike_window_s {
+                        Some(window) => writeln!(f, ", and the following {window}s")?,
+                        None => writeln!(f)?,
+                    }
+                }
+                Filter::Decimation { every, max_samples } => {
+                    write!(f, "Keep one of every {every} fixes")?;
+                    match max_samples {
+                        Some(max_samples) => writeln!(f, ", up to {max_samples} fixes")?,
+                        None => writeln!(f)?,
+                    }
+                }
+                Filter::Outliers {
+                    center,
+                    cutoff_sigma,
+                    max_passes,
+                } => {
+                    let passes = if *max_passes == 1 { "pass" } else { "passes" };
+                    writeln!(
+                        f,
+                        "Outliers further than {cutoff_sigma} sigma from the {center}, up to {max_passes} {passes}"
+                    )?
+                }
+            }
+        }
+
+        writeln!(f, "Estimators:")?;
+        for estimator in &self.estimators {
+            match estimator {
+                Estimator::Average => writeln!(f, "  Average")?,
+                Estimator::StdDev => writeln!(f, "  Standard deviation")?,
+                Estimator::ConfidenceInterval { level } => {
+                    writeln!(f, "  {level}% confidence interval of the average")?
+                }
+                Estimator::WeightedAverage => writeln!(f, "  Weighted average")?,
+                Estimator::MedianAndMad => writeln!(f, "  Median and median absolute deviation")?,
+                Estimator::ReferenceError {
+                    reference: [lat, lon, alt],
+                } => writeln!(f, "  Error from reference ({lat}, {lon}, {alt})")?,
+                Estimator::Jumps {
+                    max_gap_s,
+                    max_jump_m,
+                    split,
+                } => {
+                    write!(
+                        f,
+                        "  Time gaps above {max_gap_s}s and position jumps above {max_jump_m}m"
+                    )?;
+                    if *split {
+                        writeln!(f, ", with the statistics of each segment")?
+                    } else {
+                        writeln!(f)?
+                    }
+                }
+                Estimator::Histogram {
+                    bin_layout,
+                    include_rejected,
+                } => {
+                    match bin_layout {
+                        BinLayout::Sigma { span, divisions } => write!(
+                            f,
+                            "  Histogram spanning {span} sigma, {divisions} bins per sigma"
+                        )?,
+                        BinLayout::Count(count) => write!(f, "  Histogram with {count} bins")?,
+                        BinLayout::Fixed { count, .. } => {
+                            write!(f, "  Histogram with {count} bins between fixed bounds")?
+                        }
+                        BinLayout::Auto => write!(
+                            f,
+                            "  Histogram with bins by the Freedman–Diaconis rule, up to {MAX_AUTO_BINS}"
+                        )?,
+                    }
+                    if *include_rejected {
+                        writeln!(f, ", including the outliers")?
+                    } else {
+                        writeln!(f)?
+                    }
+                }
+            }
+        }
+
+        writeln!(f, "Outputs:")?;
+        for output in &self.outputs {
+            writeln!(f, "  {}: {}", output.path, output.contents)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,309 @@
+//! Formatting of coordinates as degrees, minutes and seconds, or as UTM coordinates.
+
+use std::{collections::BTreeMap, fmt};
+
+use anyhow::anyhow;
+use glam::DVec3;
+
+use crate::stats::enu_offsets;
+
+/// How the coordinates of positions are printed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CoordFormat {
+    /// Decimal degrees.
+    Dd,
+    /// Degrees, minutes and seconds, like 41°23'12.345"N 2°10'26.789"E.
+    Dms,
+    /// UTM zone, easting and northing.
+    Utm,
+}
+
+/// Decimals of the degrees in decimal degrees, of the seconds in degrees, minutes and seconds, of
+/// the UTM easting and northing, and of the altitude of positions in the long output.
+pub const DD_DECIMALS: usize = 4;
+pub const DMS_DECIMALS: usize = 3;
+pub const UTM_DECIMALS: usize = 3;
+pub const ALTITUDE_DECIMALS: usize = 1;
+
+impl CoordFormat {
+    /// Every format, in the order of `--all-formats`.
+    pub const ALL: [CoordFormat; 3] = [CoordFormat::Dd, CoordFormat::Dms, CoordFormat::Utm];
+
+    /// Name of the format in the text output.
+    pub fn label(&self) -> &'static str {
+        match self {
+            CoordFormat::Dd => "Decimal degrees",
+            CoordFormat::Dms => "Degrees, minutes and seconds",
+            CoordFormat::Utm => "UTM",
+        }
+    }
+
+    /// Formats a latitude and longitude in decimal degrees in this format, rounded as in the long
+    /// output.
+    pub fn format(&self, latitude: f64, longitude: f64) -> anyhow::Result<String> {
+        Ok(match self {
+            CoordFormat::Dd => format!(
+                "{}º, {}º",
+                format_rounded(latitude, DD_DECIMALS),
+                format_rounded(longitude, DD_DECIMALS)
+            ),
+            CoordFormat::Dms => format_dms(latitude, longitude),
+            CoordFormat::Utm => to_utm(latitude, longitude)?.to_string(),
+        })
+    }
+
+    /// Largest distance in meters, as (north, east, up), between `position` and its coordinates
+    /// rounded for the long output in this format. For UTM, the distances on the grid are taken
+    /// as distances on the ground.
+    pub fn rounding_error_m(&self, position: DVec3) -> DVec3 {
+        let half_unit = |decimals: usize| 0.5 * 10f64.powi(-(decimals as i32));
+        let up = half_unit(ALTITUDE_DECIMALS);
+        let half_degrees = match self {
+            CoordFormat::Dd => half_unit(DD_DECIMALS),
+            CoordFormat::Dms => half_unit(DMS_DECIMALS) / 3600.,
+            CoordFormat::Utm => {
+                let half_m = half_unit(UTM_DECIMALS);
+                return DVec3::new(half_m, half_m, up);
+            }
+        };
+        let corner = position + DVec3::new(half_degrees, half_degrees, 0.);
+        let offset = enu_offsets(&[corner], position)[0].abs();
+        DVec3::new(offset.x, offset.y, up)
+    }
+}
+
+/// Formats a latitude and longitude in every format of [`CoordFormat::ALL`], leaving out those
+/// which can't represent it, like UTM near the poles.
+pub fn representations(latitude: f64, longitude: f64) -> BTreeMap<CoordFormat, String> {
+    CoordFormat::ALL
+        .into_iter()
+        .filter_map(|coord_format| {
+            Some((coord_format, coord_format.format(latitude, longitude).ok()?))
+        })
+        .collect()
+}
+
+/// Formats a number with `decimals` decimals, rounding half to even the shortest decimal
+/// representation of the number, the one printed by `{}`. Unlike `{:.2}`, which rounds the binary
+/// value, 1.005 and 1.015 are ties for 2 decimals, and are rounded to 1.00 and 1.02.
+pub fn format_rounded(value: f64, decimals: usize) -> String {
+    if !value.is_finite() {
+        return format!("{value:.decimals$}");
+    }
+    let shortest = value.abs().to_string();
+    let (integer, fraction) = shortest.split_once('.').unwrap_or((&shortest, ""));
+    let kept = fraction.len().min(decimals);
+    let mut digits = integer
+        .bytes()
+        .chain(fraction[..kept].bytes())
+        .chain(std::iter::repeat_n(b'0', decimals - kept))
+        .collect::<Vec<_>>();
+    let rest = &fraction[kept..];
+    let round_up = match rest.as_bytes().first() {
+        Some(b'6'..=b'9') => true,
+        Some(b'5') => {
+            let is_tie = rest[1..].bytes().all(|digit| digit == b'0');
+            !is_tie || digits.last().is_some_and(|digit| (digit - b'0') % 2 == 1)
+        }
+        _ => false,
+    };
+    if round_up {
+        let carried = digits.iter_mut().rev().all(|digit| {
+            let carry = *digit == b'9';
+            *digit = if carry { b'0' } else { *digit + 1 };
+            carry
+        });
+        if carried {
+            digits.insert(0, b'1');
+        }
+    }
+    let (integer, fraction) = digits.split_at(digits.len() - decimals);
+    // A value rounded to zero has no sign.
+    let is_zero = digits.iter().all(|digit| *digit == b'0');
+    let sign = if value.is_sign_negative() && !is_zero {
+        "-"
+    } else {
+        ""
+    };
+    let integer = String::from_utf8_lossy(integer);
+    if decimals == 0 {
+        format!("{sign}{integer}")
+    } else {
+        format!("{sign}{integer}.{}", String::from_utf8_lossy(fraction))
+    }
+}
+
+/// Formats a latitude and longitude in decimal degrees as degrees, minutes and seconds, with the
+/// seconds rounded half to even to thousandths, as by [`format_rounded`].
+pub fn format_dms(latitude: f64, longitude: f64) -> String {
+    format!(
+        "{} {}",
+        format_dms_angle(latitude, ['N', 'S']),
+        format_dms_angle(longitude, ['E', 'W'])
+    )
+}
+
+fn format_dms_angle(angle: f64, [positive, negative]: [char; 2]) -> String {
+    // Round before splitting, so that the seconds never round up to 60.
+    let seconds = format_rounded(angle.abs() * 3600., 3);
+    let milliseconds = seconds.replace('.', "").parse::<u64>().unwrap_or_default();
+    let (degrees, rest) = (milliseconds / 3_600_000, milliseconds % 3_600_000);
+    let (minutes, rest) = (rest / 60_000, rest % 60_000);
+    let hemisphere = if angle < 0. && milliseconds > 0 {
+        negative
+    } else {
+        positive
+    };
+    format!(
+        "{degrees}°{minutes:02}'{:02}.{:03}\"{hemisphere}",
+        rest / 1000,
+        rest % 1000
+    )
+}
+
+/// Parses an angle formatted as degrees, minutes and seconds followed by the hemisphere, like
+/// `41°23'12.345"N`, into decimal degrees.
+pub fn parse_dms_angle(text: &str) -> anyhow::Result<f64> {
+    let invalid = || anyhow!("Invalid angle {text:?}, expected something like 41°23'12.345\"N");
+
+    let (degrees, rest) = text.split_once('°').ok_or_else(invalid)?;
+    let (minutes, rest) = rest.split_once('\'').ok_or_else(invalid)?;
+    let (seconds, hemisphere) = rest.split_once('"').ok_or_else(invalid)?;
+    let sign = match hemisphere {
+        "N" | "E" => 1.,
+        "S" | "W" => -1.,
+        _ => return Err(invalid()),
+    };
+    let degrees = degrees.parse::<u32>().map_err(|_| invalid())?;
+    let minutes = minutes.parse::<u32>().map_err(|_| invalid())?;
+    let seconds = seconds.parse::<f64>().map_err(|_| invalid())?;
+    if minutes >= 60 || !(0. ..60.).contains(&seconds) {
+        return Err(invalid());
+    }
+    Ok(sign * (degrees as f64 + minutes as f64 / 60. + seconds / 3600.))
+}
+
+/// A position in the Universal Transverse Mercator system, on the WGS84 ellipsoid.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Utm {
+    pub zone: u8,
+    pub north: bool,
+    pub easting: f64,
+    pub northing: f64,
+}
+
+impl fmt::Display for Utm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}{} {}mE {}mN",
+            self.zone,
+            if self.north { 'N' } else { 'S' },
+            format_rounded(self.easting, UTM_DECIMALS),
+            format_rounded(self.northing, UTM_DECIMALS)
+        )
+    }
+}
+
+const UTM_SCALE: f64 = 0.9996;
+const UTM_FALSE_EASTING: f64 = 500e3;
+const UTM_FALSE_NORTHING_SOUTH: f64 = 10_000e3;
+
+/// Constants of the Krüger series for the WGS84 ellipsoid: the rectifying radius, and the
+/// coefficients of the forward (alpha), inverse (beta) and latitude (delta) series.
+struct Kruger {
+    radius: f64,
+    alpha: [f64; 3],
+    beta: [f64; 3],
+    delta: [f64; 3],
+    ecc_factor: f64,
+}
+
+fn kruger() -> Kruger {
+    let a = 6378137.;
+    let f = 1. / 298.257223563;
+    let n = f / (2. - f);
+    let (n2, n3) = (n * n, n * n * n);
+    Kruger {
+        radius: a / (1. + n) * (1. + n2 / 4. + n2 * n2 / 64.),
+        alpha: [
+            n / 2. - 2. * n2 / 3. + 5. * n3 / 16.,
+            13. * n2 / 48. - 3. * n3 / 5.,
+            61. * n3 / 240.,
+        ],
+        beta: [
+            n / 2. - 2. * n2 / 3. + 37. * n3 / 96.,
+            n2 / 48. + n3 / 15.,
+            17. * n3 / 480.,
+        ],
+        delta: [
+            2. * n - 2. * n2 / 3. - 2. * n3,
+            7. * n2 / 3. - 8. * n3 / 5.,
+            56. * n3 / 15.,
+        ],
+        ecc_factor: 2. * n.sqrt() / (1. + n),
+    }
+}
+
+fn central_meridian(zone: u8) -> f64 {
+    (zone as f64 * 6. - 183.).to_radians()
+}
+
+/// Converts a latitude and longitude in decimal degrees into UTM coordinates, in the standard zone
+/// of the longitude (the exceptions around Norway and Svalbard are not applied).
+pub fn to_utm(latitude: f64, longitude: f64) -> anyhow::Result<Utm> {
+    if !(-80. ..=84.).contains(&latitude) || !(-180. ..=180.).contains(&longitude) {
+        return Err(anyhow!(
+            "UTM coordinates are only defined between 80°S and 84°N"
+        ));
+    }
+    let zone = (((longitude + 180.) / 6.).floor() as u8).min(59) + 1;
+    let k = kruger();
+    let phi = latitude.to_radians();
+    let lambda = longitude.to_radians() - central_meridian(zone);
+
+    let t = (phi.sin().atanh() - k.ecc_factor * (k.ecc_factor * phi.sin()).atanh()).sinh();
+    let xi_prime = t.atan2(lambda.cos());
+    let eta_prime = (lambda.sin() / (1. + t * t).sqrt()).atanh();
+    let (mut xi, mut eta) = (xi_prime, eta_prime);
+    for (j, alpha) in k.alpha.iter().enumerate() {
+        let j = 2. * (j + 1) as f64;
+        xi += alpha * (j * xi_prime).sin() * (j * eta_prime).cosh();
+        eta += alpha * (j * xi_prime).cos() * (j * eta_prime).sinh();
+    }
+
+    let north = latitude >= 0.;
+    Ok(Utm {
+        zone,
+        north,
+        easting: UTM_FALSE_EASTING + UTM_SCALE * k.radius * eta,
+        northing: UTM_SCALE * k.radius * xi + if north { 0. } else { UTM_FALSE_NORTHING_SOUTH },
+    })
+}
+
+/// Converts UTM coordinates into a latitude and longitude in decimal degrees.
+pub fn from_utm(utm: Utm) -> (f64, f64) {
+    let k = kruger();
+    let false_northing = if utm.north {
+        0.
+    } else {
+        UTM_FALSE_NORTHING_SOUTH
+    };
+    let xi = (utm.northing - false_northing) / (UTM_SCALE * k.radius);
+    let eta = (utm.easting - UTM_FALSE_EASTING) / (UTM_SCALE * k.radius);
+
+    let (mut xi_prime, mut eta_prime) = (xi, eta);
+    for (j, beta) in k.beta.iter().enumerate() {
+        let j = 2. * (j + 1) as f64;
+        xi_prime -= beta * (j * xi).sin() * (j * eta).cosh();
+        eta_prime -= beta * (j * xi).cos() * (j * eta).sinh();
+    }
+    let chi = (xi_prime.sin() / eta_prime.cosh()).asin();
+    let mut phi = chi;
+    for (j, delta) in k.delta.iter().enumerate() {
+        phi += delta * (2. * (j + 1) as f64 * chi).sin();
+    }
+    let lambda = central_meridian(utm.zone) + eta_prime.sinh().atan2(xi_prime.cos());
+    (phi.to_degrees(), lambda.to_degrees())
+}
@@ -0,0 +1,217 @@
+//! Comparison of the JSON results of two runs, with `gpsavg summary-diff`, so that archived
+//! results can be compared without the logs they were computed from.
+//!
+//! Results saved by older versions lack some of the fields, which are shown as unknown. Fields
+//! added by newer versions are ignored.
+
+use std::{fs, path::Path};
+
+use anyhow::Context;
+use glam::DVec3;
+
+use crate::{
+    age::format_duration,
+    batch::Gates,
+    stats::enu_offsets,
+    summary::{ConfidenceInterval, ReferenceError},
+    HeightSystem,
+};
+
+/// Version of the schema of the JSON results, increased when fields read by `summary-diff` are
+/// added. Results saved before the version was recorded have version 1.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Position as saved in the JSON results.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize)]
+pub struct SavedPosition {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude: f64,
+}
+
+impl From<SavedPosition> for DVec3 {
+    fn from(position: SavedPosition) -> Self {
+        DVec3::new(position.latitude, position.longitude, position.altitude)
+    }
+}
+
+/// Offsets or spreads in meters as saved in the JSON results.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize)]
+pub struct SavedEnu {
+    pub east: f64,
+    pub north: f64,
+    pub up: f64,
+}
+
+/// Results of a run as saved with `--format json`. Only the fields which are compared are read.
+#[derive(Clone, Debug, PartialEq, serde::Deserialize)]
+pub struct SavedSummary {
+    #[serde(default = "first_schema_version")]
+    pub schema_version: u32,
+    pub average: SavedPosition,
+    /// Height system of the altitudes, if recorded.
+    pub height: Option<HeightSystem>,
+    pub entries: usize,
+    pub entries_filtered: usize,
+    pub std_dev_m: SavedEnu,
+    pub confidence_interval_m: Option<ConfidenceInterval>,
+    pub error_m: Option<ReferenceError>,
+    /// Seconds from the first fix to the last one, if recorded and known.
+    pub duration_s: Option<f64>,
+}
+
+fn first_schema_version() -> u32 {
+    1
+}
+
+impl SavedSummary {
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read results file at {}", path.display()))?;
+        serde_json::from_str(&contents)
+            .with_context(|| format!("Failed to parse results file at {}", path.display()))
+    }
+
+    pub fn std_dev_horizontal_m(&self) -> f64 {
+        self.std_dev_m.north.hypot(self.std_dev_m.east)
+    }
+
+    /// Returns a description of each gate the results don't meet, like [`Gates::check`]. The age
+    /// of the last fix isn't checked, since it depends on when the results are compared.
+    pub fn failed_gates(&self, gates: &Gates) -> Vec<String> {
+        let gates = Gates {
+            max_age_s: None,
+            ..gates.clone()
+        };
+        gates.check_values(
+            self.entries_filtered,
+            self.std_dev_horizontal_m(),
+            self.error_m.map(|error| error.horizontal),
+            None,
+        )
+    }
+}
+
+/// Returns the lines comparing the `old` results with the `new` ones, and telling whether each
+/// meets the `gates`, if any.
+pub fn diff(old: &SavedSummary, new: &SavedSummary, gates: &Gates) -> Vec<String> {
+    let mut lines = Vec::new();
+    if old.schema_version != new.schema_version {
+        lines.push(format!(
+            "The results have different schema versions ({} and {}); the fields missing from the older ones are unknown.",
+            old.schema_version, new.schema_version
+        ));
+    }
+    let (old_height, new_height) = (
+        old.height.unwrap_or(HeightSystem::Orthometric),
+        new.height.unwrap_or(HeightSystem::Orthometric),
+    );
+    if old_height != new_height {
+        lines.push(format!(
+            "Warning: the altitudes are in different height systems ({} and {}), so the vertical displacement includes the geoid separation.",
+            height_name(old_height),
+            height_name(new_height)
+        ));
+    }
+
+    let offset = enu_offsets(&[new.average.into()], old.average.into())[0];
+    lines.push(format!(
+        "Displacement of the average: N {:+.3}m, E {:+.3}m, U {:+.3}m, {:.3}m horizontally",
+        offset.x,
+        offset.y,
+        offset.z,
+        offset.x.hypot(offset.y)
+    ));
+    lines.push(format!(
+        "Fixes: {} → {} ({:+})",
+        old.entries,
+        new.entries,
+        new.entries as i64 - old.entries as i64
+    ));
+    lines.push(format!(
+        "Fixes after filter: {} → {} ({:+})",
+        old.entries_filtered,
+        new.entries_filtered,
+        new.entries_filtered as i64 - old.entries_filtered as i64
+    ));
+    lines.push(change_m(
+        "Horizontal standard deviation",
+        Some(old.std_dev_horizontal_m()),
+        Some(new.std_dev_horizontal_m()),
+    ));
+    lines.push(change_m(
+        "Vertical standard deviation",
+        Some(old.std_dev_m.up),
+        Some(new.std_dev_m.up),
+    ));
+    let confidence = |summary: &SavedSummary| summary.confidence_interval_m;
+    if let Some(level) = confidence(new)
+        .or(confidence(old))
+        .map(|interval| interval.level)
+    {
+        let label =
+            match [old, new].map(|summary| confidence(summary).map(|interval| interval.level)) {
+                [Some(old_level), Some(new_level)] if old_level != new_level => {
+                    format!("Horizontal confidence radius ({old_level}% and {new_level}%)")
+                }
+                _ => format!("Horizontal {level}% confidence radius"),
+            };
+        lines.push(change_m(
+            &label,
+            confidence(old).map(|interval| interval.horizontal),
+            confidence(new).map(|interval| interval.horizontal),
+        ));
+    }
+    if old.error_m.is_some() || new.error_m.is_some() {
+        lines.push(change_m(
+            "Horizontal error from the reference",
+            old.error_m.map(|error| error.horizontal),
+            new.error_m.map(|error| error.horizontal),
+        ));
+    }
+    let duration = |summary: &SavedSummary| {
+        summary
+            .duration_s
+            .map_or("unknown".to_owned(), format_duration)
+    };
+    lines.push(format!("Duration: {} → {}", duration(old), duration(new)));
+
+    let has_gates =
+        gates.max_std_dev_m.is_some() || gates.min_fixes.is_some() || gates.max_error_m.is_some();
+    if has_gates {
+        for (name, summary) in [("Old", old), ("New", new)] {
+            let failed = summary.failed_gates(gates);
+            lines.push(if failed.is_empty() {
+                format!("{name} results pass the quality checks")
+            } else {
+                format!(
+                    "{name} results fail the quality checks: {}",
+                    failed.join("; ")
+                )
+            });
+        }
+    }
+    lines
+}
+
+fn height_name(height: HeightSystem) -> &'static str {
+    match height {
+        HeightSystem::Orthometric => "orthometric",
+        HeightSystem::Ellipsoidal => "ellipsoidal",
+    }
+}
+
+/// Formats the change of a value in meters, which may be unknown in either results.
+fn change_m(label: &str, old: Option<f64>, new: Option<f64>) -> String {
+    let value =
+        |value: Option<f64>| value.map_or("unknown".to_owned(), |value| format!("{value:.3}m"));
+    match (old, new) {
+        (Some(old), Some(new)) => format!(
+            "{label}: {} → {} ({:+.3}m)",
+            value(Some(old)),
+            value(Some(new)),
+            new - old
+        ),
+        _ => format!("{label}: {} → {}", value(old), value(new)),
+    }
+}
@@ -0,0 +1,438 @@
+//! Association of the auxiliary NMEA sentences (GSA, GST and RMC) with the GGA fixes they
+//! describe.
+//!
+//! Sentences are grouped into epochs, one per GGA sentence, following these rules:
+//! - A GGA sentence starts a new epoch, which completes the previous one.
+//! - Timestamped sentences (GST and RMC) are attached to the epoch with the same fix time. When
+//!   there is none, they are attached to the closest epoch in time within the staleness limit,
+//!   without replacing a closer match. Sentences newer than the current epoch wait for a GGA
+//!   sentence at least as new as them.
+//! - Sentences without a timestamp (GSA), or arriving while the current epoch has none, are
+//!   attached to the preceding GGA sentence unless it already has one (receivers tracking several
+//!   constellations emit one GSA sentence per constellation). Those appearing before the first
+//!   GGA sentence are attached to it.
+//! - Sentences which can't be attached following these rules are dropped.
+
+use chrono::{NaiveDate, NaiveTime};
+use glam::DVec3;
+use nmea::sentences::FixType;
+
+/// Data from a GGA sentence.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Gga {
+    pub time: Option<NaiveTime>,
+    /// Latitude, longitude and altitude of the fix, if all of them are present.
+    pub position: Option<DVec3>,
+    pub fix_type: Option<FixType>,
+    pub satellites: Option<u32>,
+    pub hdop: Option<f32>,
+    /// Height of the geoid above the ellipsoid, in meters.
+    pub geoid_separation: Option<f64>,
+    /// The sign of the latitude or the longitude disagreed with the hemisphere letter of the
+    /// sentence, and was corrected to match it.
+    pub hemisphere_corrected: bool,
+}
+
+/// Dilution of precision values, from a GSA sentence.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Dop {
+    pub pdop: Option<f32>,
+    pub hdop: Option<f32>,
+    pub vdop: Option<f32>,
+}
+
+/// Standard deviation of the position errors in meters, from a GST sentence.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Gst {
+    pub time: Option<NaiveTime>,
+    pub lat_sigma: Option<f64>,
+    pub lon_sigma: Option<f64>,
+    pub alt_sigma: Option<f64>,
+}
+
+/// Data from a RMC sentence.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rmc {
+    pub time: Option<NaiveTime>,
+    pub date: Option<NaiveDate>,
+    pub speed_knots: Option<f32>,
+}
+
+/// A sentence relevant to the assembly of epochs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sentence {
+    Gga(Gga),
+    Gsa(Dop),
+    Gst(Gst),
+    Rmc(Rmc),
+}
+
+impl Sentence {
+    /// Time of the sentence, for the sentence types which have one.
+    pub fn time(&self) -> Option<NaiveTime> {
+        match self {
+            Sentence::Gga(gga) => gga.time,
+            Sentence::Gsa(_) => None,
+            Sentence::Gst(gst) => gst.time,
+            Sentence::Rmc(rmc) => rmc.time,
+        }
+    }
+}
+
+/// A GGA fix along with the data of the sentences associated to it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Epoch {
+    /// Line of the input the GGA sentence was read from, starting at 1.
+    pub line: usize,
+    pub gga: Gga,
+    pub dop: Option<Dop>,
+    pub gst: Option<Gst>,
+    pub rmc: Option<Rmc>,
+}
+
+/// Groups sentences into [`Epoch`]s, following the rules described in the [module
+/// documentation](self).
+#[derive(Debug)]
+pub struct EpochAssembler {
+    staleness: f64,
+    current: Option<Assembling>,
+    /// Sentences waiting for a GGA sentence as new as them.
+    pending: Vec<Sentence>,
+    dropped: usize,
+}
+
+/// An epoch which may still get sentences attached, along with the time difference in seconds
+/// between its fix time and the timestamped sentences attached so far.
+#[derive(Debug)]
+struct Assembling {
+    epoch: Epoch,
+    gst_offset: f64,
+    rmc_offset: f64,
+}
+
+impl EpochAssembler {
+    /// Creates an assembler which attaches timestamped sentences up to `staleness` seconds apart
+    /// from the fix time when there is no sentence with the same time.
+    pub fn new(staleness: f64) -> Self {
+        Self {
+            staleness,
+            current: None,
+            pending: Vec::new(),
+            dropped: 0,
+        }
+    }
+
+    /// Adds the next sentence, read from the given line of the input. Returns the previous epoch
+    /// when it is complete.
+    pub fn push(&mut self, sentence: Sentence, line: usize) -> Option<Epoch> {
+        match sentence {
+            Sentence::Gga(gga) => {
+                let completed = self.current.take().map(|assembling| assembling.epoch);
+                self.current = Some(Assembling {
+                    epoch: Epoch {
+                        line,
+                        gga,
+                        dop: None,
+                        gst: None,
+                        rmc: None,
+                    },
+                    gst_offset: f64::INFINITY,
+                    rmc_offset: f64::INFINITY,
+                });
+                let (early, ready) =
+                    std::mem::take(&mut self.pending)
+                        .into_iter()
+                        .partition(|sentence| match (gga.time, sentence.time()) {
+                            (Some(fix_time), Some(time)) => seconds_between(fix_time, time) > 0.,
+                            _ => false,
+                        });
+                self.pending = early;
+                for sentence in ready {
+                    self.attach(sentence);
+                }
+                completed
+            }
+            sentence => {
+                match &self.current {
+                    None => self.pending.push(sentence),
+                    Some(assembling) => match (assembling.epoch.gga.time, sentence.time()) {
+                        (Some(fix_time), Some(time)) if seconds_between(fix_time, time) > 0. => {
+                            self.pending.push(sentence)
+                        }
+                        _ => self.attach(sentence),
+                    },
+                }
+                None
+            }
+        }
+    }
+
+    /// Returns the last epoch, if any. Sentences still waiting for a GGA sentence are dropped.
+    pub fn finish(&mut self) -> Option<Epoch> {
+        self.dropped += self.pending.len();
+        self.pending.clear();
+        self.current.take().map(|assembling| assembling.epoch)
+    }
+
+    /// Number of sentences which could not be attached to any epoch.
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    /// Attaches the sentence to the current epoch, or drops it if the rules don't allow it.
+    fn attach(&mut self, sentence: Sentence) {
+        let Some(assembling) = &mut self.current else {
+            self.dropped += 1;
+            return;
+        };
+
+        // Time difference with the fix, for timestamped sentences.
+        let offset = match (assembling.epoch.gga.time, sentence.time()) {
+            (Some(fix_time), Some(time)) => Some(seconds_between(fix_time, time).abs()),
+            _ => None,
+        };
+
+        let attached = match sentence {
+            Sentence::Gga(_) => unreachable!("GGA sentences start a new epoch"),
+            Sentence::Gsa(dop) => {
+                let empty = assembling.epoch.dop.is_none();
+                if empty {
+                    assembling.epoch.dop = Some(dop);
+                }
+                empty
+            }
+            Sentence::Gst(gst) => replace_if_closer(
+                (&mut assembling.epoch.gst, &mut assembling.gst_offset),
+                (gst, offset),
+                self.staleness,
+            ),
+            Sentence::Rmc(rmc) => replace_if_closer(
+                (&mut assembling.epoch.rmc, &mut assembling.rmc_offset),
+                (rmc, offset),
+                self.staleness,
+            ),
+        };
+        if !attached {
+            self.dropped += 1;
+        }
+    }
+}
+
+/// Puts back in order the sentences of sources which deliver them in bursts, reordered within
+/// each burst, before they are fed to the [`EpochAssembler`].
+///
+/// Each timestamped sentence starts a group, along with the following sentences without a
+/// timestamp, which describe the same fix, and with the following ones with the same time. Groups
+/// are kept until one `window_s` seconds newer than them arrives, and then released in time order.
+/// Groups older than one already released are stragglers, and are dropped along with the sentences
+/// of their group. Sentences without a timestamp before the first timestamped one are released
+/// right away.
+#[derive(Debug)]
+pub struct Reorderer {
+    window_s: f64,
+    /// Groups not released yet, sorted by time.
+    buffer: Vec<Group>,
+    /// Where the group of the last timestamped sentence is.
+    last: Last,
+    next_id: usize,
+    newest: Option<NaiveTime>,
+    released: Option<NaiveTime>,
+    stragglers: usize,
+}
+
+/// Where the group of the last timestamped sentence given to a [`Reorderer`] is, which gets the
+/// sentences without a timestamp following it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Last {
+    /// There was no timestamped sentence yet.
+    None,
+    /// The group with this identifier is buffered.
+    Buffered(usize),
+    /// The group was released, and it is the last group released while it was.
+    Released,
+    /// The group was dropped, or released before other groups were.
+    Dropped,
+}
+
+/// Sentences with the same time, along with the line each one was read from.
+#[derive(Debug)]
+struct Group {
+    id: usize,
+    time: NaiveTime,
+    sentences: Vec<(Sentence, usize)>,
+}
+
+impl Reorderer {
+    /// Creates a reorderer keeping the sentences up to `window_s` seconds older than the newest
+    /// one.
+    pub fn new(window_s: f64) -> Self {
+        Self {
+            window_s,
+            buffer: Vec::new(),
+            last: Last::None,
+            next_id: 0,
+            newest: None,
+            released: None,
+            stragglers: 0,
+        }
+    }
+
+    /// Adds the next sentence, read from the given line of the input. Returns the sentences
+    /// released, in order, along with their lines.
+    pub fn push(&mut self, sentence: Sentence, line: usize) -> Vec<(Sentence, usize)> {
+        let Some(time) = sentence.time() else {
+            match self.last {
+                Last::None | Last::Released => return vec![(sentence, line)],
+                Last::Buffered(id) => {
+                    let group = self.buffer.iter_mut().find(|group| group.id == id);
+                    group
+                        .expect("the last group is buffered")
+                        .sentences
+                        .push((sentence, line));
+                }
+                Last::Dropped => self.stragglers += 1,
+            }
+            return Vec::new();
+        };
+
+        let is_straggler = self
+            .released
+            .is_some_and(|released| seconds_between(released, time) < 0.);
+        if is_straggler {
+            self.stragglers += 1;
+            self.last = Last::Dropped;
+            return Vec::new();
+        }
+        let joins_last = match self.last {
+            Last::Buffered(id) => self
+                .buffer
+                .iter_mut()
+                .find(|group| group.id == id && group.time == time),
+            _ => None,
+        };
+        match joins_last {
+            Some(group) => group.sentences.push((sentence, line)),
+            None => {
+                let id = self.next_id;
+                self.next_id += 1;
+                // After the groups with the same time, to keep their order of arrival.
+                let idx = self
+                    .buffer
+                    .partition_point(|group| seconds_between(group.time, time) >= 0.);
+                self.buffer.insert(
+                    idx,
+                    Group {
+                        id,
+                        time,
+                        sentences: vec![(sentence, line)],
+                    },
+                );
+                self.last = Last::Buffered(id);
+            }
+        }
+        if self
+            .newest
+            .is_none_or(|newest| seconds_between(newest, time) > 0.)
+        {
+            self.newest = Some(time);
+        }
+
+        let newest = self.newest.unwrap_or(time);
+        let ready = self
+            .buffer
+            .iter()
+            .take_while(|group| seconds_between(group.time, newest) > self.window_s)
+            .count();
+        self.release(ready)
+    }
+
+    /// Returns the sentences still buffered, in order, along with their lines.
+    pub fn finish(&mut self) -> Vec<(Sentence, usize)> {
+        self.release(self.buffer.len())
+    }
+
+    /// Number of sentences dropped for arriving after newer ones were released.
+    pub fn stragglers(&self) -> usize {
+        self.stragglers
+    }
+
+    /// Releases the first `count` groups of the buffer.
+    fn release(&mut self, count: usize) -> Vec<(Sentence, usize)> {
+        let released = self.buffer.drain(..count).collect::<Vec<_>>();
+        if let Some(group) = released.last() {
+            self.released = Some(group.time);
+        }
+        match self.last {
+            Last::Buffered(id) => match released.iter().position(|group| group.id == id) {
+                Some(idx) if idx + 1 == released.len() => self.last = Last::Released,
+                Some(_) => self.last = Last::Dropped,
+                None => {}
+            },
+            Last::Released if !released.is_empty() => self.last = Last::Dropped,
+            _ => {}
+        }
+        released
+            .into_iter()
+            .flat_map(|group| group.sentences)
+            .collect()
+    }
+}
+
+/// Stores `value` in `slot` if its time offset is within the staleness limit and smaller than the
+/// one of the value already stored. Values without an offset are only stored in empty slots.
+/// Returns whether `value` was stored.
+fn replace_if_closer<T>(
+    (slot, slot_offset): (&mut Option<T>, &mut f64),
+    (value, offset): (T, Option<f64>),
+    staleness: f64,
+) -> bool {
+    let closer = match offset {
+        Some(offset) => offset <= staleness && offset < *slot_offset,
+        None => slot.is_none(),
+    };
+    if closer {
+        *slot = Some(value);
+        *slot_offset = offset.unwrap_or(f64::INFINITY);
+    }
+    closer
+}
+
+/// Returns the time in seconds from `from` to `to`, assuming they are less than 12 hours apart,
+/// so that times after midnight are considered later than times before it.
+pub fn seconds_between(from: NaiveTime, to: NaiveTime) -> f64 {
+    const DAY: f64 = 24. * 3600.;
+
+    let seconds = (to - from).num_milliseconds() as f64 / 1e3;
+    if seconds >= DAY / 2. {
+        seconds - DAY
+    } else if seconds < -DAY / 2. {
+        seconds + DAY
+    } else {
+        seconds
+    }
+}
+
+/// Parses the data fields of a GST sentence. Returns `None` if the sentence is malformed.
+pub fn parse_gst(data: &str) -> Option<Gst> {
+    let fields = data.split(',').collect::<Vec<_>>();
+    if fields.len() != 8 {
+        return None;
+    }
+    let number = |idx: usize| -> Option<Option<f64>> {
+        match fields[idx] {
+            "" => Some(None),
+            field => field.parse().ok().map(Some),
+        }
+    };
+
+    let time = match fields[0] {
+        "" => None,
+        field => Some(NaiveTime::parse_from_str(field, "%H%M%S%.f").ok()?),
+    };
+    Some(Gst {
+        time,
+        lat_sigma: number(5)?,
+        lon_sigma: number(6)?,
+        alt_sigma: number(7)?,
+    })
+}
@@ -0,0 +1,224 @@
+//! Plain-language interpretation of the results of a run, printed with `--explain` for users who
+//! don't know how far to trust an average.
+//!
+//! The explanation follows a few rules of thumb:
+//! - The horizontal standard deviation is put in terms of how far the fixes were from the average,
+//!   and the confidence interval in terms of how far the average may be from the true position.
+//! - At least [`MIN_FIXES`] filtered fixes are needed for the statistics to mean anything, and
+//!   [`GOOD_FIXES`] for them to be stable.
+//! - GPS errors are correlated over minutes, so the fixes should span at least
+//!   [`MIN_DURATION_S`], and preferably [`GOOD_DURATION_S`].
+//! - A static receiver rarely spreads more than [`HIGH_STD_DEV_M`] horizontally, and rarely has
+//!   more than [`MAX_REJECTED_FRACTION`] of its fixes rejected as outliers.
+//!
+//! The explanation ends with a verdict, which is the worst of what the rules found.
+
+use crate::{
+    age::format_duration,
+    jumps::{Event, EventKind},
+    summary::ConfidenceInterval,
+    Summary,
+};
+
+/// Minimum number of filtered fixes for the statistics to be meaningful.
+pub const MIN_FIXES: usize = 30;
+/// Number of filtered fixes above which the statistics are stable.
+pub const GOOD_FIXES: usize = 300;
+/// Minimum time in seconds spanned by the fixes for the average not to follow the slow drift of
+/// the errors.
+pub const MIN_DURATION_S: f64 = 600.;
+/// Time in seconds spanned by the fixes above which the average is as good as a consumer receiver
+/// gets.
+pub const GOOD_DURATION_S: f64 = 3600.;
+/// Horizontal standard deviation in meters above which a static receiver is considered noisy.
+pub const HIGH_STD_DEV_M: f64 = 5.;
+/// Fraction of fixes rejected as outliers above which the data is considered suspicious.
+pub const MAX_REJECTED_FRACTION: f64 = 0.05;
+
+/// What the explanation of a run is based on, besides its summary.
+pub struct Findings<'a> {
+    pub confidence: ConfidenceInterval,
+    /// Seconds from the first fix to the last one, if they have times.
+    pub duration_s: Option<f64>,
+    /// Time gaps and position jumps between the fixes.
+    pub events: &'a [Event],
+    /// Number of GGA sentences whose coordinates were corrected to match their hemisphere letters.
+    pub hemisphere_corrected: usize,
+    /// Descriptions of the quality thresholds the results don't meet.
+    pub failed_gates: &'a [String],
+}
+
+/// How far the average can be trusted, from best to worst.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Verdict {
+    Reliable,
+    Caution,
+    Unreliable,
+}
+
+/// Returns the sentences explaining the results of a run, one per line, ending with the verdict.
+pub fn explain(summary: &Summary, findings: &Findings) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut verdict = Verdict::Reliable;
+    let mut reasons = Vec::new();
+    let mut flag = |level: Verdict, reason: &str| {
+        verdict = verdict.max(level);
+        reasons.push(reason.to_owned());
+    };
+
+    let std_dev_m = summary.std_dev_horizontal_m();
+    let confidence = &findings.confidence;
+    lines.push(format!(
+        "The horizontal standard deviation is {std_dev_m:.2}m: roughly two thirds of the fixes were within {std_dev_m:.2}m of the average, and nearly all of them within {:.2}m.",
+        2. * std_dev_m
+    ));
+    lines.push(format!(
+        "The average itself is much more precise than each fix: it is within {:.3}m of the mean position of the receiver with {}% confidence, provided the errors of the fixes are independent, which over short periods they are not.",
+        confidence.horizontal, confidence.level
+    ));
+    if std_dev_m > HIGH_STD_DEV_M {
+        lines.push(format!(
+            "A standard deviation above {HIGH_STD_DEV_M}m is high for a static receiver, which usually spreads between 1m and 3m: check that the antenna had a clear view of the sky, away from walls and metal, and that the receiver didn't move."
+        ));
+        flag(Verdict::Caution, "the fixes are widely spread");
+    }
+
+    let n_filtered = summary.n_filtered;
+    if n_filtered < MIN_FIXES {
+        lines.push(format!(
+            "Only {n_filtered} fixes remain after the filter, too few for the standard deviation and the confidence interval to mean much: collect at least {MIN_FIXES}, and preferably several hundred."
+        ));
+        flag(Verdict::Unreliable, "there are too few fixes");
+    } else if n_filtered < GOOD_FIXES {
+        lines.push(format!(
+            "The {n_filtered} fixes after the filter are enough for the statistics, though {GOOD_FIXES} or more would make them more stable."
+        ));
+    } else {
+        lines.push(format!(
+            "The {n_filtered} fixes after the filter are plenty for the statistics."
+        ));
+    }
+    let rejected = summary.n - n_filtered;
+    if summary.n > 0 && rejected as f64 > MAX_REJECTED_FRACTION * summary.n as f64 {
+        lines.push(format!(
+            "{rejected} of the {} fixes ({:.0}%) were rejected as outliers, which is a lot: reflections off nearby surfaces (multipath) or a receiver which moved are the usual causes.",
+            summary.n,
+            100. * rejected as f64 / summary.n as f64
+        ));
+        flag(Verdict::Caution, "many fixes were outliers");
+    }
+
+    match findings.duration_s {
+        None => {
+            lines.push("The fixes have no times, so it is unknown how long they span: GPS errors drift over minutes, so an average over a short period may be off by more than the confidence interval suggests.".to_owned());
+            flag(Verdict::Caution, "the duration is unknown");
+        }
+        Some(duration_s) if duration_s < MIN_DURATION_S => {
+            lines.push(format!(
+                "The fixes span only {}: GPS errors drift over minutes, so the average may be off by more than the confidence interval suggests. Log for at least {} minutes, and for an hour or more when every decimeter counts.",
+                format_duration(duration_s),
+                MIN_DURATION_S / 60.
+            ));
+            flag(Verdict::Caution, "the log is short");
+        }
+        Some(duration_s) if duration_s < GOOD_DURATION_S => lines.push(format!(
+            "The fixes span {}, enough to average out most of the drift of the errors, though an hour or more would do better.",
+            format_duration(duration_s)
+        )),
+        Some(duration_s) => lines.push(format!(
+            "The fixes span {}, long enough to average out the drift of the errors.",
+            format_duration(duration_s)
+        )),
+    }
+
+    let jumps = findings
+        .events
+        .iter()
+        .filter(|event| event.is_jump())
+        .count();
+    if jumps > 0 {
+        lines.push(format!(
+            "Warning: there {} between consecutive fixes, so the receiver probably moved and the average may mix several places. Split the log with --detect-jumps --split-on-jump, or keep only the static part with --from and --to.",
+            counted(jumps, "position jump")
+        ));
+        flag(Verdict::Unreliable, "the receiver moved");
+    }
+    let gaps = findings
+        .events
+        .iter()
+        .filter(|event| matches!(event.kind, EventKind::Gap { .. }))
+        .count();
+    if gaps > 0 {
+        lines.push(format!(
+            "Warning: there {} in the times of the fixes, where the receiver lost the fix or the logging stopped. They don't spoil the average by themselves, but check that the receiver stayed in place during them.",
+            counted(gaps, "gap")
+        ));
+    }
+    let repeated = ["latitude", "longitude", "altitude"]
+        .into_iter()
+        .zip(summary.repeated)
+        .filter_map(|(name, value)| value.map(|_| name))
+        .collect::<Vec<_>>();
+    if !repeated.is_empty() {
+        lines.push(format!(
+            "Warning: the {} of all the fixes {} the same, as when the receiver repeats its last fix without updating it. Check that it had a fix at all while logging.",
+            enumerate(&repeated),
+            if repeated.len() == 1 { "is" } else { "are" }
+        ));
+        flag(Verdict::Unreliable, "the receiver output is frozen");
+    }
+    if findings.hemisphere_corrected > 0 {
+        lines.push(format!(
+            "Warning: {} GGA sentences had coordinates with a sign contradicting their N/S or E/W letter. They were corrected, but the program which wrote them is likely buggy.",
+            findings.hemisphere_corrected
+        ));
+        flag(Verdict::Caution, "some sentences were inconsistent");
+    }
+    for gate in findings.failed_gates {
+        lines.push(format!(
+            "Warning: the results don't meet a quality threshold you set: {gate}."
+        ));
+        flag(Verdict::Caution, "a quality threshold isn't met");
+    }
+
+    lines.push(match verdict {
+        Verdict::Reliable => format!(
+            "Verdict: the average is reliable, to about {:.2}m horizontally.",
+            confidence.horizontal
+        ),
+        Verdict::Caution => format!(
+            "Verdict: use the average with caution, since {}.",
+            enumerate(&reasons)
+        ),
+        Verdict::Unreliable => format!(
+            "Verdict: don't rely on the average, since {}.",
+            enumerate(&reasons)
+        ),
+    });
+    lines
+}
+
+/// Formats a count of things after "there", like `is 1 gap` or `are 2 gaps`.
+fn counted(count: usize, thing: &str) -> String {
+    match count {
+        1 => format!("is 1 {thing}"),
+        count => format!("are {count} {thing}s"),
+    }
+}
+
+/// Joins the items like `a, b and c`.
+fn enumerate(items: &[impl AsRef<str>]) -> String {
+    match items {
+        [] => String::new(),
+        [item] => item.as_ref().to_owned(),
+        [items @ .., last] => format!(
+            "{} and {}",
+            items
+                .iter()
+                .map(AsRef::as_ref)
+                .collect::<Vec<_>>()
+                .join(", "),
+            last.as_ref()
+        ),
+    }
+}
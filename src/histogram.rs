@@ -0,0 +1,343 @@
+//! Histograms of each of the coordinates.
+
+use anyhow::anyhow;
+use colored::Colorize;
+use glam::DVec3;
+
+use crate::stats::iqr;
+
+/// Maximum number of bins of the histograms laid out by [`BinLayout::Auto`], for data which is
+/// mostly concentrated in a single value.
+pub const MAX_AUTO_BINS: u32 = 200;
+
+/// How the bin edges of the histogram are laid out for each coordinate.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BinLayout {
+    /// Bins of `1 / divisions` standard deviations, covering `span` standard deviations at each
+    /// side of the average.
+    Sigma { span: f64, divisions: u32 },
+    /// A fixed number of bins covering the whole range of the given positions.
+    Count(u32),
+    /// Bins covering the whole range of the given positions, with the width given by the
+    /// Freedman–Diaconis rule, `2·IQR·n^(-1/3)`, which stands heavy tails better than the standard
+    /// deviation. There are at most [`MAX_AUTO_BINS`] bins.
+    Auto,
+    /// A fixed number of bins between the same bounds of each coordinate whatever the positions,
+    /// given as (latitude, longitude, altitude), so that the histograms of separate chunks of the
+    /// same data can be merged.
+    Fixed {
+        min: [f64; 3],
+        max: [f64; 3],
+        count: u32,
+    },
+}
+
+impl BinLayout {
+    /// Returns the edges of the bins (one more than the number of bins) for the coordinate
+    /// selected by `r_variable`.
+    pub fn edges(
+        &self,
+        positions: &[DVec3],
+        r_variable: fn(&DVec3) -> f64,
+        (avg, std_dev): (DVec3, DVec3),
+    ) -> Vec<f64> {
+        match *self {
+            BinLayout::Sigma { span, divisions } => {
+                let half_bins = (span * divisions as f64).round().max(1.) as i32;
+                (-half_bins..=half_bins)
+                    .map(|i| {
+                        (i as f64) / (divisions as f64) * r_variable(&std_dev) + r_variable(&avg)
+                    })
+                    .collect()
+            }
+            BinLayout::Count(count) => {
+                let (min, max) = range(positions, r_variable);
+                let width = (max - min) / count as f64;
+                (0..=count).map(|i| min + i as f64 * width).collect()
+            }
+            BinLayout::Auto => {
+                let (min, max) = range(positions, r_variable);
+                let mut values = positions.iter().map(r_variable).collect::<Vec<_>>();
+                let width = 2. * iqr(&mut values) * (values.len() as f64).powf(-1. / 3.);
+                let count = ((max - min) / width).ceil();
+                if count.is_nan() || count > MAX_AUTO_BINS as f64 {
+                    return BinLayout::Count(MAX_AUTO_BINS).edges(
+                        positions,
+                        r_variable,
+                        (avg, std_dev),
+                    );
+                }
+                let count = count.max(1.) as u32;
+                (0..=count).map(|i| min + i as f64 * width).collect()
+            }
+            BinLayout::Fixed { min, max, count } => {
+                let (min, max) = (r_variable(&min.into()), r_variable(&max.into()));
+                let width = (max - min) / count as f64;
+                (0..=count).map(|i| min + i as f64 * width).collect()
+            }
+        }
+    }
+}
+
+/// Returns the minimum and the maximum of the coordinate selected by `r_variable`.
+fn range(positions: &[DVec3], r_variable: fn(&DVec3) -> f64) -> (f64, f64) {
+    positions
+        .iter()
+        .map(r_variable)
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), x| {
+            (min.min(x), max.max(x))
+        })
+}
+
+/// Counts how many positions fall in each of the bins delimited by `edges`. Values outside the
+/// edges are not counted. The last bin includes its upper edge, so that the maximum of the data
+/// is counted when the edges span the data range.
+pub fn histogram(positions: &[DVec3], r_variable: fn(&DVec3) -> f64, edges: &[f64]) -> Vec<i32> {
+    let mut histogram_val = vec![0; edges.len().saturating_sub(1)];
+    let (Some(&first), Some(&last)) = (edges.first(), edges.last()) else {
+        return histogram_val;
+    };
+
+    for value in positions.iter().map(r_variable) {
+        if value < first || value > last {
+            continue;
+        }
+        // Index of the first edge greater than the value, minus one.
+        let idx = edges
+            .partition_point(|edge| *edge <= value)
+            .saturating_sub(1);
+        let last_bin = histogram_val.len() - 1;
+        histogram_val[idx.min(last_bin)] += 1;
+    }
+    histogram_val
+}
+
+/// Histogram of a single coordinate.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Histogram {
+    /// Edges of the bins, one more than the number of bins.
+    pub edges: Vec<f64>,
+    pub counts: Vec<i32>,
+    /// Counts of the fixes rejected as outliers, when they are included.
+    pub rejected: Option<Vec<i32>>,
+}
+
+impl Histogram {
+    /// Counts the positions falling in each of the bins delimited by `edges`, for the coordinate
+    /// selected by `r_variable`.
+    pub fn new(positions: &[DVec3], r_variable: fn(&DVec3) -> f64, edges: Vec<f64>) -> Self {
+        let counts = histogram(positions, r_variable, &edges);
+        Self {
+            edges,
+            counts,
+            rejected: None,
+        }
+    }
+
+    /// Adds the counts of the rejected positions to the histogram, in the same bins.
+    pub fn with_rejected(self, positions: &[DVec3], r_variable: fn(&DVec3) -> f64) -> Self {
+        Self {
+            rejected: Some(histogram(positions, r_variable, &self.edges)),
+            ..self
+        }
+    }
+
+    /// Adds up the counts of two histograms with the same bin edges, like those of two chunks of
+    /// the same data.
+    pub fn merge(&self, other: &Histogram) -> anyhow::Result<Histogram> {
+        if self.edges != other.edges {
+            return Err(anyhow!("The histograms have different bin edges"));
+        }
+        let add = |a: &[i32], b: &[i32]| a.iter().zip(b).map(|(a, b)| a + b).collect::<Vec<_>>();
+        let rejected = match (&self.rejected, &other.rejected) {
+            (Some(rejected), Some(other_rejected)) => Some(add(rejected, other_rejected)),
+            (None, None) => None,
+            _ => {
+                return Err(anyhow!(
+                    "Only one of the histograms includes the rejected fixes"
+                ))
+            }
+        };
+        Ok(Histogram {
+            edges: self.edges.clone(),
+            counts: add(&self.counts, &other.counts),
+            rejected,
+        })
+    }
+
+    /// Returns the width of the bins, which all have the same, or `None` if there are no bins.
+    pub fn bin_width(&self) -> Option<f64> {
+        Some(self.edges.get(1)? - self.edges[0])
+    }
+
+    /// Returns the (lower, upper) bounds of each bin.
+    pub fn divisions(&self) -> Vec<(f64, f64)> {
+        divisions(&self.edges)
+    }
+
+    /// Returns the count of each bin, including the rejected fixes.
+    pub fn totals(&self) -> Vec<i32> {
+        match &self.rejected {
+            Some(rejected) => self
+                .counts
+                .iter()
+                .zip(rejected)
+                .map(|(count, rejected)| count + rejected)
+                .collect(),
+            None => self.counts.clone(),
+        }
+    }
+
+    /// Describes the count columns after the name of the coordinate, when there are two.
+    fn columns_note(&self) -> &'static str {
+        if self.rejected.is_some() {
+            " (filtered, rejected)"
+        } else {
+            ""
+        }
+    }
+}
+
+/// Printed instead of the bins of the coordinates without a histogram.
+const NO_BINS: &str = "no bins, all the filtered fixes have the same value";
+
+/// Maximum width, in characters, of the bars of the histogram chart.
+pub const HISTOGRAM_BAR_WIDTH: usize = 40;
+
+/// Histogram of a single coordinate, along with the name of the coordinate.
+pub struct AxisHistogram<'a> {
+    pub name: &'a str,
+    pub histogram: &'a Histogram,
+}
+
+/// Formats the histogram of each coordinate as a bar chart, with each bar scaled to the largest
+/// count of its coordinate. The rejected fixes, if included, are counted in a second column and
+/// stacked in red at the end of the bars.
+pub fn format_histogram_bars(axes: [AxisHistogram; 3]) -> String {
+    let mut formatted = String::new();
+    for AxisHistogram { name, histogram } in axes {
+        let totals = histogram.totals();
+        let max = totals.iter().copied().max().unwrap_or(0);
+        formatted.push_str(&format!("  {name}{}\n", histogram.columns_note()));
+        if histogram.counts.is_empty() {
+            formatted.push_str(&format!("{NO_BINS}\n"));
+        }
+        for (idx, ((inf, sup), count)) in histogram
+            .divisions()
+            .iter()
+            .zip(&histogram.counts)
+            .enumerate()
+        {
+            let bar = histogram_bar(*count, max, HISTOGRAM_BAR_WIDTH);
+            match &histogram.rejected {
+                Some(rejected) => {
+                    let total_len = histogram_bar(totals[idx], max, HISTOGRAM_BAR_WIDTH)
+                        .chars()
+                        .count();
+                    let rejected_bar = "█".repeat(total_len.saturating_sub(bar.chars().count()));
+                    formatted.push_str(&format!(
+                        "({inf:.6} , {sup:.6})\t{count:>6} {:>6} {}{}\n",
+                        rejected[idx],
+                        bar.cyan(),
+                        rejected_bar.red()
+                    ));
+                }
+                None => formatted.push_str(&format!(
+                    "({inf:.6} , {sup:.6})\t{count:>6} {}\n",
+                    bar.cyan()
+                )),
+            }
+        }
+        formatted.push('\n');
+    }
+    formatted
+}
+
+/// Formats the histogram of each coordinate as a table, with the counts and the bounds of the bins
+/// of each coordinate side by side. The rejected fixes, if included, are counted in a second
+/// column. Coordinates without bins are left blank, with a note below.
+pub fn format_histogram_table(axes: [AxisHistogram; 3]) -> String {
+    let mut formatted = axes
+        .iter()
+        .map(|axis| format!("  {}{}", axis.name, axis.histogram.columns_note()))
+        .collect::<Vec<_>>()
+        .join("\t\t\t\t");
+    formatted.push('\n');
+
+    let columns = axes.each_ref().map(|axis| {
+        let histogram = axis.histogram;
+        histogram
+            .divisions()
+            .into_iter()
+            .zip(&histogram.counts)
+            .enumerate()
+            .map(|(idx, ((inf, sup), count))| match &histogram.rejected {
+                Some(rejected) => format!("{count}\t{}\t({inf:.6} , {sup:.6})", rejected[idx]),
+                None => format!("{count}\t({inf:.6} , {sup:.6})"),
+            })
+            .collect::<Vec<_>>()
+    });
+    let rows = columns.iter().map(Vec::len).max().unwrap_or(0);
+    for row in 0..rows {
+        let cells = columns
+            .iter()
+            .map(|column| column.get(row).map_or("\t", String::as_str))
+            .collect::<Vec<_>>();
+        formatted.push_str(&format!("{}\n", cells.join("\t\t")));
+    }
+    for axis in &axes {
+        if axis.histogram.counts.is_empty() {
+            formatted.push_str(&format!("  {}: {NO_BINS}\n", axis.name));
+        }
+    }
+    formatted
+}
+
+/// Formats the histograms of the latitude, longitude and altitude as CSV, with a row per bin
+/// holding the coordinate, the bounds of the bin and its count, followed by the count of rejected
+/// fixes if they are included. The bounds are written with all their digits.
+pub fn format_histogram_csv(histograms: &[Histogram; 3]) -> String {
+    let include_rejected = histograms
+        .iter()
+        .any(|histogram| histogram.rejected.is_some());
+    let mut formatted = String::from("axis,lower,upper,count");
+    if include_rejected {
+        formatted.push_str(",rejected");
+    }
+    formatted.push('\n');
+    for (name, histogram) in ["latitude", "longitude", "altitude"].iter().zip(histograms) {
+        for (idx, ((inf, sup), count)) in histogram
+            .divisions()
+            .iter()
+            .zip(&histogram.counts)
+            .enumerate()
+        {
+            formatted.push_str(&format!("{name},{inf},{sup},{count}"));
+            if include_rejected {
+                let rejected = histogram
+                    .rejected
+                    .as_ref()
+                    .map_or(0, |rejected| rejected[idx]);
+                formatted.push_str(&format!(",{rejected}"));
+            }
+            formatted.push('\n');
+        }
+    }
+    formatted
+}
+
+/// Returns a bar of up to `width` characters, proportional to `count / max`. Non-zero counts
+/// always get at least one character.
+pub fn histogram_bar(count: i32, max: i32, width: usize) -> String {
+    if count <= 0 || max <= 0 {
+        return String::new();
+    }
+    let len = (count as f64 / max as f64 * width as f64).round().max(1.) as usize;
+    "█".repeat(len.min(width))
+}
+
+/// Returns the (lower, upper) bounds of each bin delimited by `edges`.
+pub fn divisions(edges: &[f64]) -> Vec<(f64, f64)> {
+    edges.windows(2).map(|pair| (pair[0], pair[1])).collect()
+}
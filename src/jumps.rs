@@ -0,0 +1,133 @@
+//! Detection of time gaps and position jumps between consecutive fixes, which give away logs
+//! holding more than one occupation point, like when the tripod was moved or the receiver was left
+//! running in the car.
+
+use std::fmt;
+
+use crate::{epoch::seconds_between, parse::Fix, stats::enu_offsets};
+
+pub const DEFAULT_MAX_GAP_S: f64 = 10.;
+pub const DEFAULT_MAX_JUMP_M: f64 = 5.;
+
+/// Limits above which the time and the distance between consecutive fixes are reported.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct JumpDetection {
+    pub max_gap_s: f64,
+    /// Limit on the horizontal distance, since the altitude is usually much noisier.
+    pub max_jump_m: f64,
+    /// Compute the statistics of each segment between position jumps separately.
+    pub split: bool,
+}
+
+impl Default for JumpDetection {
+    fn default() -> Self {
+        Self {
+            max_gap_s: DEFAULT_MAX_GAP_S,
+            max_jump_m: DEFAULT_MAX_JUMP_M,
+            split: false,
+        }
+    }
+}
+
+/// A time gap or a position jump between a fix and the previous one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Event {
+    /// Index of the fix after the event.
+    pub index: usize,
+    /// Lines of the input file the fixes before and after the event were read from.
+    pub previous_line: usize,
+    pub line: usize,
+    pub kind: EventKind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EventKind {
+    /// Seconds between the fixes.
+    Gap { seconds: f64 },
+    /// Offset in meters of the fix from the previous one, as (north, east, up), and its
+    /// horizontal length.
+    Jump {
+        north: f64,
+        east: f64,
+        up: f64,
+        horizontal: f64,
+    },
+}
+
+impl Event {
+    pub fn is_jump(&self) -> bool {
+        matches!(self.kind, EventKind::Jump { .. })
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            EventKind::Gap { seconds } => write!(
+                f,
+                "Time gap of {seconds:.1}s between lines {} and {}",
+                self.previous_line, self.line
+            ),
+            EventKind::Jump {
+                north,
+                east,
+                up,
+                horizontal,
+            } => write!(
+                f,
+                "Position jump of {horizontal:.3}m (E {east:.3}m, N {north:.3}m, U {up:.3}m) between lines {} and {}",
+                self.previous_line, self.line
+            ),
+        }
+    }
+}
+
+impl JumpDetection {
+    /// Returns the gaps and the jumps between consecutive fixes, in order. Fixes without a time
+    /// are never considered after a gap. A fix can be after both a gap and a jump, with the gap
+    /// first.
+    pub fn detect(&self, fixes: &[Fix]) -> Vec<Event> {
+        let mut events = Vec::new();
+        for (index, pair) in fixes.windows(2).enumerate() {
+            let (previous, fix) = (&pair[0], &pair[1]);
+            let event = |kind| Event {
+                index: index + 1,
+                previous_line: previous.line,
+                line: fix.line,
+                kind,
+            };
+            if let (Some(from), Some(to)) = (previous.time(), fix.time()) {
+                let seconds = seconds_between(from, to);
+                if seconds > self.max_gap_s {
+                    events.push(event(EventKind::Gap { seconds }));
+                }
+            }
+            let offset = enu_offsets(&[fix.position], previous.position)[0];
+            let horizontal = offset.x.hypot(offset.y);
+            if horizontal > self.max_jump_m {
+                events.push(event(EventKind::Jump {
+                    north: offset.x,
+                    east: offset.y,
+                    up: offset.z,
+                    horizontal,
+                }));
+            }
+        }
+        events
+    }
+}
+
+/// Splits the fixes into the segments between the position jumps of `events`, which must have
+/// been detected on the same fixes. Time gaps don't split the fixes.
+pub fn segments<'a>(fixes: &'a [Fix], events: &[Event]) -> Vec<&'a [Fix]> {
+    let mut segments = Vec::new();
+    let mut start = 0;
+    for event in events {
+        if event.is_jump() {
+            segments.push(&fixes[start..event.index]);
+            start = event.index;
+        }
+    }
+    segments.push(&fixes[start..]);
+    segments
+}
@@ -0,0 +1,29 @@
+//! Library behind the `gpsavg` program, which obtains the average position of GPS devices and
+//! other stats from their logs.
+
+pub mod age;
+pub mod batch;
+pub mod cli;
+pub mod config;
+pub mod coords;
+pub mod diff;
+pub mod epoch;
+pub mod explain;
+pub mod histogram;
+pub mod jumps;
+pub mod parse;
+pub mod provenance;
+pub mod selftest;
+pub mod serial;
+pub mod stats;
+pub mod summary;
+pub mod timing;
+
+pub use parse::{
+    read_demuxed_inputs, read_inputs, Exclusions, Fix, HdopFilter, HeightSystem, InputData,
+    InputFormat, InputOptions, LinePolicy, ParseOptions, SentenceClass, Strictness, TimeWindow,
+};
+pub use summary::{Filtered, Options, ReferenceError, Summary};
+
+#[cfg(test)]
+mod tests;
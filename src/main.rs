@@ -1,133 +1,442 @@
 use std::{
-    fs::File,
-    io::{BufRead, BufReader},
-    path::PathBuf,
-    str::FromStr,
+    collections::BTreeMap, fs, io::IsTerminal, path::PathBuf, process::ExitCode, time::Instant,
 };
 
 use anyhow::{anyhow, Context};
-use clap::CommandFactory;
+use chrono::{NaiveDateTime, NaiveTime};
 use colored::Colorize;
 use glam::DVec3;
-use map_3d::geodetic2enu;
-use nmea::{
-    parse_nmea_sentence,
-    sentences::{parse_gga, GgaData},
-    NmeaSentence,
+use gpsavg::{
+    age::{format_duration, system_now},
+    cli::{self, Action},
+    config::{ColorChoice, Config, OutputFormat},
+    coords::{
+        format_dms, format_rounded, representations, to_utm, CoordFormat, ALTITUDE_DECIMALS,
+        UTM_DECIMALS,
+    },
+    diff::{diff, SavedSummary, SCHEMA_VERSION},
+    explain::{explain, Findings},
+    histogram::{
+        format_histogram_bars, format_histogram_csv, format_histogram_table, AxisHistogram,
+        BinLayout,
+    },
+    jumps::{segments, Event, JumpDetection},
+    provenance::Provenance,
+    read_demuxed_inputs, read_inputs, selftest,
+    serial::read_serial,
+    stats::{duration_s, mean_interval_s},
+    summary::ConfidenceInterval,
+    timing::Timing,
+    Filtered, Fix, HeightSystem, InputOptions, Options, ReferenceError, Summary,
 };
 
-#[cfg(test)]
-mod tests;
+/// Printed before the results when there are position jumps.
+const JUMPS_WARNING: &str = "Warning: the position jumps between some fixes, so the input may hold more than one occupation point and the average may be meaningless";
 
-#[derive(clap::Parser)]
-#[command(author, version, about, long_about = None)]
-struct Input {
-    input_path: PathBuf,
+/// Exit status of a run whose results don't meet the quality thresholds. Errors exit with 1.
+const QUALITY_FAILURE: u8 = 2;
 
-    #[arg(short)]
-    /// Return _only_ the average, with no other text. Useful for passing onto another programs or storing into a file.
-    short: bool,
+fn main() -> anyhow::Result<ExitCode> {
+    let matches = cli::command().get_matches();
 
-    #[arg(short = 'l')]
-    /// Return additionally the histogram for each of the coordinates. Useful for detecting anomalies.
-    show_histogram: bool,
+    let action = cli::action(&matches)?;
+    let color = match &action {
+        Action::Batch { .. }
+        | Action::Selftest
+        | Action::SummaryDiff { .. }
+        | Action::VerifyProvenance { .. } => ColorChoice::Auto,
+        Action::Run { config, .. } => config.output.color,
+    };
+    colored::control::set_override(color.enabled(
+        std::io::stdout().is_terminal(),
+        std::env::var_os("NO_COLOR").as_deref(),
+    ));
+
+    match action {
+        Action::Batch { job_file } => {
+            gpsavg::batch::run(&job_file)?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Action::Selftest => {
+            let checks = selftest::run();
+            for check in &checks {
+                match &check.failure {
+                    None => println!("{} {}", "PASS".green(), check.name),
+                    Some(failure) => println!("{} {}: {failure}", "FAIL".red(), check.name),
+                }
+            }
+            let failed = checks
+                .iter()
+                .filter(|check| check.failure.is_some())
+                .count();
+            if failed > 0 {
+                println!("{failed} of {} checks failed", checks.len());
+                return Ok(ExitCode::FAILURE);
+            }
+            println!("All {} checks passed", checks.len());
+            Ok(ExitCode::SUCCESS)
+        }
+        Action::SummaryDiff { old, new, gates } => {
+            let (old, new) = (SavedSummary::read(&old)?, SavedSummary::read(&new)?);
+            for line in diff(&old, &new, &gates) {
+                println!("{line}");
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Action::VerifyProvenance {
+            results,
+            input_paths,
+        } => {
+            let mismatches = Provenance::read(&results)?.verify(&input_paths)?;
+            if mismatches.is_empty() {
+                println!(
+                    "{} the input files and the accepted sentences match",
+                    "VERIFIED".green()
+                );
+                return Ok(ExitCode::SUCCESS);
+            }
+            for mismatch in mismatches {
+                println!("{} {mismatch}", "MISMATCH".red());
+            }
+            Ok(ExitCode::FAILURE)
+        }
+        Action::Run {
+            config,
+            dry_run: true,
+        } => {
+            let plan = config.plan();
+            match config.output.format {
+                OutputFormat::Plain => print!("{plan}"),
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&plan)?),
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Action::Run { config, .. } => {
+            let failed_gates = if config.input.demux {
+                run_demuxed(&config)?
+            } else {
+                run(&config)?
+            };
+            if failed_gates.is_empty() {
+                return Ok(ExitCode::SUCCESS);
+            }
+            for gate in failed_gates {
+                eprintln!("Quality check failed: {gate}");
+            }
+            Ok(ExitCode::from(QUALITY_FAILURE))
+        }
+    }
 }
 
-fn main() -> anyhow::Result<()> {
-    let input = Input::command()
-        .help_template(include_str!("help_template"))
-        .get_matches();
-
-    let input_path = input.get_one::<PathBuf>("input_path").unwrap();
-    let short = input.get_flag("short");
-    let show_histogram = input.get_flag("show_histogram");
-
-    let file = BufReader::new(
-        File::open(input_path)
-            .with_context(|| format!("Failed to read input file at {}", input_path.display()))?,
+/// Computes and prints the statistics of the input files. Returns a description of each quality
+/// threshold the results don't meet.
+fn run(config: &Config) -> anyhow::Result<Vec<String>> {
+    let Config {
+        input_paths,
+        serial,
+        input: input_options,
+        options,
+        reference,
+        gates,
+        jumps,
+        output,
+    } = config;
+    let max_passes = options.max_passes;
+
+    let start = Instant::now();
+    let input_data = match serial {
+        Some(serial) => read_serial(serial, input_options)?,
+        None => read_inputs(input_paths, input_options)?,
+    };
+    let parsed = Instant::now();
+
+    let filtered_fixes = Filtered::new(&input_data.fixes, options)?;
+    let filtered = Instant::now();
+
+    // The provenance reads the input files again, so it is timed as a stage of its own.
+    let provenance = output
+        .provenance
+        .then(|| Provenance::new(input_paths, &filtered_fixes.fixes))
+        .transpose()?;
+    let hashed = Instant::now();
+
+    let summary = Summary::new(&input_data.fixes, filtered_fixes, options);
+    let reference_error = reference.map(|reference| summary.reference_error(reference));
+    let confidence = summary.confidence_interval(options.confidence);
+    let age_s = summary.last_fix_age_s(system_now());
+    let failed_gates = gates.check(
+        &summary,
+        reference_error.map(|error| error.horizontal),
+        age_s,
     );
-
-    let positions = parse_file(file)?;
-
-    let n = positions.len();
-    let avg = positions.iter().copied().sum::<DVec3>() / n as f64;
-    let std_dev = (positions
-        .iter()
-        .copied()
-        .map(|r| (r - avg).powf(2.))
-        .sum::<DVec3>()
-        / (n - 1) as f64)
-        .powf(0.5);
-
-    let (histogram_x, division_val_x) = histogram(positions.clone(), |x| x.x, (avg, std_dev));
-    let (histogram_y, division_val_y) = histogram(positions.clone(), |x| x.y, (avg, std_dev));
-    let (histogram_z, division_val_z) = histogram(positions.clone(), |x| x.z, (avg, std_dev));
-
-    let histogram_val_x = histogram_val(histogram_x);
-    let histogram_val_y = histogram_val(histogram_y);
-    let histogram_val_z = histogram_val(histogram_z);
-
-    let positions_filtered = positions
-        .iter()
-        .filter(|x| {
-            let cutoff: f64 = 3.;
-
-            x.x > avg.x - cutoff * std_dev.x
-                && x.x < avg.x + cutoff * std_dev.x
-                && x.y > avg.y - cutoff * std_dev.y
-                && x.y < avg.y + cutoff * std_dev.y
-                && x.z > avg.z - cutoff * std_dev.z
-                && x.z < avg.z + cutoff * std_dev.z
-        })
-        .copied()
-        .collect::<Vec<DVec3>>();
-
-    let n = positions.len();
-    let n_filtered = positions_filtered.len();
-    let avg_filtered = positions_filtered.iter().copied().sum::<DVec3>() / n_filtered as f64;
-    let std_dev_filtered = (positions_filtered
-        .iter()
-        .copied()
-        .map(|r| (r - avg_filtered).powf(2.))
-        .sum::<DVec3>()
-        / (n_filtered - 1) as f64)
-        .powf(0.5);
-    let std_dev_m = {
-        let (y, x, z) = geodetic2enu(
-            (avg_filtered.x + std_dev_filtered.x).to_radians(),
-            (avg_filtered.y + std_dev_filtered.y).to_radians(),
-            avg_filtered.z + std_dev_filtered.z,
-            avg_filtered.x.to_radians(),
-            avg_filtered.y.to_radians(),
-            avg_filtered.z,
-            map_3d::Ellipsoid::WGS84,
-        );
-        DVec3::from((x, y, z))
+    let events = jumps.map(|jumps| jumps.detect(&input_data.fixes));
+    let has_jumps = events.iter().flatten().any(Event::is_jump);
+    let segments = match (jumps, &events) {
+        (Some(jumps), Some(events)) if jumps.split => Some(
+            segments(&input_data.fixes, events)
+                .into_iter()
+                .map(|fixes| {
+                    let filtered = Filtered::new(fixes, options)?;
+                    Ok((fixes, Summary::new(fixes, filtered, options)))
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?,
+        ),
+        _ => None,
     };
+    let computed = Instant::now();
+
+    let Summary {
+        n,
+        n_filtered,
+        avg: avg_filtered,
+        std_dev: std_dev_filtered,
+        std_dev_m,
+        avg_weighted,
+        ..
+    } = summary;
+
+    let results = match output.format {
+        OutputFormat::Plain => format_position_short(avg_filtered, output.coord_format)?,
+        OutputFormat::Json => serde_json::to_string_pretty(&Report {
+            schema_version: SCHEMA_VERSION,
+            average: avg_filtered.into(),
+            height: input_options.height,
+            weighted_average: avg_weighted.map(Into::into),
+            entries: n,
+            entries_filtered: n_filtered,
+            entries_excluded: (!input_options.exclusions.is_empty()).then_some(input_data.excluded),
+            entries_outside_window: input_options.window.map(|_| input_data.outside_window),
+            entries_without_geoid_separation: (input_options.height == HeightSystem::Ellipsoidal)
+                .then_some(input_data.no_geoid_separation),
+            duplicates_collapsed: input_options.parse.dedup_m.map(|_| input_data.duplicates),
+            stragglers_dropped: input_options
+                .parse
+                .reorder_window_s
+                .map(|_| input_data.stragglers),
+            hemisphere_corrections: input_options
+                .parse
+                .verify_hemisphere
+                .then_some(input_data.hemisphere_corrected),
+            removed_per_pass: &summary.removed_per_pass,
+            std_dev: std_dev_filtered.into(),
+            std_dev_m: std_dev_m.into(),
+            median: summary.median_and_mad.map(|(median, _)| median.into()),
+            mad: summary.median_and_mad.map(|(_, mad)| mad.into()),
+            confidence_interval_m: confidence,
+            histogram_bin_widths: (options.bin_layout == BinLayout::Auto).then(|| {
+                ["latitude", "longitude", "altitude"]
+                    .into_iter()
+                    .zip(&summary.histograms)
+                    .filter_map(|(name, histogram)| Some((name, histogram.bin_width()?)))
+                    .collect()
+            }),
+            repeated_values: ["latitude", "longitude", "altitude"]
+                .into_iter()
+                .zip(summary.repeated)
+                .filter_map(|(name, value)| Some((name, value?)))
+                .collect(),
+            error_m: reference_error,
+            last_fix_time: summary.last_fix_time.map(format_fix_time),
+            last_fix_age_s: age_s,
+            duration_s: duration_s(&input_data.fixes),
+            representations: output
+                .all_formats
+                .then(|| representations(avg_filtered.x, avg_filtered.y)),
+            provenance,
+            sentence_rates: input_data.sentences.rates(),
+            talkers: &input_data.sentences.talkers,
+            warnings: events
+                .as_ref()
+                .map(|events| events.iter().map(ToString::to_string).collect()),
+            segments: segments.as_ref().map(|segments| {
+                segments
+                    .iter()
+                    .map(|(fixes, summary)| SegmentReport {
+                        first_line: fixes[0].line,
+                        last_line: fixes[fixes.len() - 1].line,
+                        average: summary.avg.into(),
+                        entries: summary.n,
+                        entries_filtered: summary.n_filtered,
+                        std_dev_m: summary.std_dev_m.into(),
+                    })
+                    .collect()
+            }),
+        })?,
+    };
+    output.write_results(&results)?;
+    if let Some(path) = &output.histogram_csv {
+        fs::write(path, format_histogram_csv(&summary.histograms))
+            .with_context(|| format!("Failed to write histogram file at {}", path.display()))?;
+    }
 
-    if short {
-        println!("{}, {}, {}", avg_filtered.x, avg_filtered.y, avg_filtered.z);
+    if output.short || (output.format == OutputFormat::Json && output.path.is_none()) {
+        if has_jumps {
+            eprintln!("{}", JUMPS_WARNING.bold().red());
+        }
+        println!("{results}");
+        if output.short && !output.quiet {
+            eprintln!(
+                "{}",
+                format_audit_line(input_options, options, &input_data.fixes, &summary)
+            );
+        }
     } else {
-        println!(
-            "{}",
-            "Values are formatted as (latitude, longitude, altitude) unless specified otherwise.\n"
-                .italic()
-        );
+        print_values_note(input_options.height);
 
+        if let Some(events) = &events {
+            if has_jumps {
+                println!("{}", JUMPS_WARNING.bold().red());
+            }
+            println!("Time gaps and position jumps: {}", events.len());
+            for event in events {
+                println!("  {event}");
+            }
+            println!();
+        }
+
+        let formatted = format_position(avg_filtered, output.coord_format)?;
+        println!("Average: {formatted}");
+        let rounding = output.coord_format.rounding_error_m(avg_filtered);
         let formatted = format!(
-            "({:.4}º, {:.4}º, {:.1}m)",
-            avg_filtered.x, avg_filtered.y, avg_filtered.z
-        )
-        .bold();
-        let formatted_raw = format!(
-            "({}, {}, {})",
-            avg_filtered.x, avg_filtered.y, avg_filtered.z
+            "Rounded by up to (N {:.4}m, E {:.4}m, U {:.4}m); the values in parentheses have full precision.",
+            rounding.x, rounding.y, rounding.z
         )
         .italic();
-        println!("Average: {formatted} {formatted_raw}\n");
+        println!("  {formatted}\n");
+        if output.all_formats {
+            println!("Average in all the formats:");
+            for (coord_format, formatted) in representations(avg_filtered.x, avg_filtered.y) {
+                println!("  {}: {}", coord_format.label(), formatted.bold());
+            }
+            println!();
+        }
+        if let Some(avg_weighted) = avg_weighted {
+            let formatted = format_position(avg_weighted, output.coord_format)?;
+            println!("Weighted average: {formatted}\n");
+        }
 
-        let formatted = format!("({} after filter)", n_filtered).italic();
-        println!("Number of entries: {n} {}", formatted);
+        let count = |count: usize| output.digit_grouping.format(count);
+        let formatted = format!("({} after filter)", count(n_filtered)).italic();
+        println!("Number of entries: {} {}", count(n), formatted);
+        if let (Some(time), Some(age_s)) = (summary.last_fix_time, age_s) {
+            let formatted = format!("({})", format_fix_time(time)).italic();
+            println!(
+                "Age of the last fix: {} {formatted}",
+                format_duration(age_s)
+            );
+        }
+        if !input_options.exclusions.is_empty() {
+            println!("Fixes excluded: {}", count(input_data.excluded));
+        }
+        if input_options.height == HeightSystem::Ellipsoidal {
+            println!(
+                "Fixes without geoid separation: {}",
+                count(input_data.no_geoid_separation)
+            );
+        }
+        if input_options.window.is_some() {
+            let in_window = n + input_data.decimated;
+            let formatted =
+                format!("(of {} read)", count(in_window + input_data.outside_window)).italic();
+            println!("Fixes in time window: {} {formatted}", count(in_window));
+        }
+        if let Some(hdop) = input_options.hdop {
+            print!(
+                "Fixes above the HDOP limit: {}",
+                count(input_data.high_hdop)
+            );
+            if hdop.spike_window_s.is_some() {
+                print!(
+                    " {}",
+                    format!(
+                        "(and {} after HDOP spikes)",
+                        count(input_data.after_hdop_spike)
+                    )
+                    .italic()
+                );
+            }
+            println!();
+        }
+        if input_options.parse.policy.skips_lines() {
+            println!("Invalid lines skipped: {}", count(input_data.rejected));
+        }
+        if input_options.parse.reorder_window_s.is_some() {
+            println!(
+                "Sentences dropped out of order: {}",
+                count(input_data.stragglers)
+            );
+        }
+        if input_data.hemisphere_corrected > 0 {
+            let formatted = format!(
+                "Warning: the signs of the coordinates of {} GGA sentences disagreed with their hemisphere letters, and were corrected to match them",
+                count(input_data.hemisphere_corrected)
+            )
+            .yellow();
+            println!("{formatted}");
+        }
+        if input_options.parse.dedup_m.is_some() {
+            println!(
+                "Duplicate fixes collapsed: {}",
+                count(input_data.duplicates)
+            );
+        }
+        let skipped = input_data
+            .sentences
+            .skipped()
+            .map(|(sentence_type, skipped)| format!("{sentence_type} {}", count(skipped)))
+            .collect::<Vec<_>>();
+        if !skipped.is_empty() {
+            println!("Sentences skipped: {}", skipped.join(", "));
+        }
+        if !input_data.sentences.talkers.is_empty() {
+            let formatted = input_data
+                .sentences
+                .talkers
+                .iter()
+                .map(|(talker, sentences)| format!("{talker} {}", count(*sentences)))
+                .collect::<Vec<_>>()
+                .join(", ");
+            println!("Talkers: {formatted}");
+        }
+        if let Some(rates) = input_data.sentences.rates() {
+            let formatted = rates
+                .iter()
+                .map(|(sentence_type, rate)| format!("{sentence_type} {rate:.2}/s"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            println!("Sentence rates: {formatted}");
+            for (sentence_type, ratio) in input_data.sentences.mismatches() {
+                let formatted = format!(
+                    "{sentence_type} sentences arrive at {ratio:.2} times the rate of GGA ones; check the receiver's output settings"
+                )
+                .yellow();
+                println!("  {formatted}");
+            }
+        }
+        if input_options.every > 1 || input_options.max_samples.is_some() {
+            let formatted = match mean_interval_s(&input_data.fixes) {
+                Some(interval) => format!(
+                    "(of {}, one every {interval:.2}s)",
+                    count(n + input_data.decimated)
+                ),
+                None => format!("(of {})", count(n + input_data.decimated)),
+            }
+            .italic();
+            println!("Samples kept: {} {formatted}", count(n));
+        }
+        if max_passes > 1 {
+            let removed = summary
+                .removed_per_pass
+                .iter()
+                .map(|removed| count(*removed))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let formatted = format!("(removed {removed})").italic();
+            println!(
+                "Filter passes: {} {formatted}",
+                summary.removed_per_pass.len()
+            );
+        }
         let formatted = format!(
             "({:.6}º, {:.6}º, {:.3}m)",
             std_dev_filtered.x, std_dev_filtered.y, std_dev_filtered.z
@@ -135,134 +444,539 @@ fn main() -> anyhow::Result<()> {
         let formatted_m =
             format!("Horizontally: ~({:.2}m, {:.2}m)", std_dev_m.x, std_dev_m.y).italic();
         println!("Standard deviation: {formatted} {formatted_m}");
-        if show_histogram {
-            let formatted = {
-                let mut formatted = String::from_str(
-                    "  Latitude (º)\t\t\t\t  Longitude (º)\t\t\t\t  Altitude(m)\n",
-                )
-                .unwrap();
-                let iter = division_val_x
+        for (name, value) in ["Latitude", "Longitude", "Altitude"]
+            .iter()
+            .zip(summary.repeated)
+        {
+            if let Some(value) = value {
+                let formatted = format!("{name}: all the filtered fixes have the same value, {value}, so outliers aren't filtered on it and it has no histogram").italic();
+                println!("  {formatted}");
+            }
+        }
+        let formatted = format!(
+            "(N {:.3}m, E {:.3}m, U {:.3}m)",
+            confidence.north, confidence.east, confidence.up
+        );
+        let formatted_h = format!("Horizontally: within {:.3}m", confidence.horizontal).italic();
+        println!(
+            "{}% confidence interval of the average: ±{formatted} {formatted_h}",
+            confidence.level
+        );
+        if let Some(reference) = reference {
+            let formatted = format_position(*reference, output.coord_format)?;
+            println!("Reference: {formatted}");
+        }
+        if let Some(error) = reference_error {
+            let formatted = format!(
+                "(E {:.3}m, N {:.3}m, U {:.3}m)",
+                error.east, error.north, error.up
+            );
+            let formatted_distance = format!(
+                "Horizontally: {:.3}m, 3D: {:.3}m",
+                error.horizontal, error.distance
+            )
+            .italic();
+            println!("Error from reference: {formatted} {formatted_distance}");
+        }
+        if let Some((median, mad)) = summary.median_and_mad {
+            let formatted = format_position(median, output.coord_format)?;
+            println!("Median: {formatted}");
+            println!(
+                "Median absolute deviation: ({:.6}º, {:.6}º, {:.3}m)",
+                mad.x, mad.y, mad.z
+            );
+        }
+        for (idx, (fixes, summary)) in segments.iter().flatten().enumerate() {
+            let formatted = format!(
+                "(lines {} to {})",
+                fixes[0].line,
+                fixes[fixes.len() - 1].line
+            )
+            .italic();
+            println!("\nSegment {}: {formatted}", idx + 1);
+            let formatted = format_position(summary.avg, output.coord_format)?;
+            println!("  Average: {formatted}");
+            let formatted = format!("({} after filter)", count(summary.n_filtered)).italic();
+            println!("  Number of entries: {} {formatted}", count(summary.n));
+            println!(
+                "  Standard deviation: ~({:.2}m, {:.2}m, {:.2}m)",
+                summary.std_dev_m.x, summary.std_dev_m.y, summary.std_dev_m.z
+            );
+        }
+        if output.show_histogram {
+            let [histogram_x, histogram_y, histogram_z] = &summary.histograms;
+            let axes = [
+                AxisHistogram {
+                    name: "Latitude (º)",
+                    histogram: histogram_x,
+                },
+                AxisHistogram {
+                    name: "Longitude (º)",
+                    histogram: histogram_y,
+                },
+                AxisHistogram {
+                    name: match input_options.height {
+                        HeightSystem::Orthometric => "Altitude (m)",
+                        HeightSystem::Ellipsoidal => "Ellipsoidal height (m)",
+                    },
+                    histogram: histogram_z,
+                },
+            ];
+            if options.bin_layout == BinLayout::Auto {
+                let widths = summary
+                    .histograms
                     .iter()
-                    .zip(division_val_y)
-                    .zip(division_val_z)
-                    .zip(histogram_val_x)
-                    .zip(histogram_val_y)
-                    .zip(histogram_val_z)
-                    .map(
-                        |(
-                            (((((inf_x, sup_x), (inf_y, sup_y)), (inf_z, sup_z)), hist_x), hist_y),
-                            hist_z,
-                        )| {
-                            (
-                                inf_x, sup_x, inf_y, sup_y, inf_z, sup_z, hist_x, hist_y, hist_z,
-                            )
-                        },
-                    );
-                for (inf_x, sup_x, inf_y, sup_y, inf_z, sup_z, hist_x, hist_y, hist_z) in iter {
-                    formatted.push_str(
-                        format!(
-                            "{hist_x}\t({:.6} , {:.6})\t\t{hist_y}\t({:.6} , {:.6})\t\t{hist_z}\t({:.6} , {:.6})\n",
-                            inf_x, sup_x, inf_y, sup_y, inf_z, sup_z
-                        )
-                        .as_str(),
-                    );
-                }
-                formatted
+                    .zip(["º", "º", "m"])
+                    .map(|(histogram, unit)| {
+                        histogram
+                            .bin_width()
+                            .map_or("-".to_owned(), |width| format!("{width:.6}{unit}"))
+                    })
+                    .collect::<Vec<_>>();
+                println!("Bin widths: ({})", widths.join(", "));
+            }
+            let formatted = if std::io::stdout().is_terminal() {
+                format_histogram_bars(axes)
+            } else {
+                format_histogram_table(axes)
             };
             println!("Histogram values:\n {} ", formatted);
         }
+        if output.explain {
+            // Position jumps are worth explaining even if they weren't asked for.
+            let events = events
+                .clone()
+                .unwrap_or_else(|| JumpDetection::default().detect(&input_data.fixes));
+            let findings = Findings {
+                confidence,
+                duration_s: duration_s(&input_data.fixes),
+                events: &events,
+                hemisphere_corrected: input_data.hemisphere_corrected,
+                failed_gates: &failed_gates,
+            };
+            println!("\nExplanation:");
+            for line in explain(&summary, &findings) {
+                println!("  {line}");
+            }
+        }
     }
 
-    Ok(())
+    let printed = Instant::now();
+
+    let timing = output.timing.then(|| {
+        Timing::new(
+            [start, parsed, filtered, hashed, computed, printed],
+            input_data.lines,
+            input_data.bytes,
+        )
+    });
+    if let Some(timing) = &timing {
+        eprintln!("{timing}");
+    }
+    if output.meta_json {
+        let meta = Meta {
+            input_paths,
+            lines: input_data.lines,
+            bytes: input_data.bytes,
+            unassociated_sentences: input_data.unassociated,
+            rejected_lines: input_data.rejected,
+            entries: n,
+            entries_filtered: n_filtered,
+            timing,
+        };
+        eprintln!("{}", serde_json::to_string(&meta)?);
+    }
+
+    Ok(failed_gates)
 }
 
-pub fn parse_file(file: BufReader<File>) -> anyhow::Result<Vec<DVec3>> {
-    file.lines()
-        .enumerate()
-        .map(|(line_num, line)| -> anyhow::Result<Option<DVec3>> {
-            let line = line.with_context(|| {
-                format!("Failed to read line {} of the input file", line_num + 1)
-            })?;
+/// Computes and prints the statistics of each receiver of multiplexed input files, along with the
+/// baseline between their averages when there are two. Returns a description of each quality
+/// threshold the results of any receiver don't meet.
+fn run_demuxed(config: &Config) -> anyhow::Result<Vec<String>> {
+    let Config {
+        input_paths,
+        input: input_options,
+        options,
+        reference,
+        gates,
+        output,
+        ..
+    } = config;
+
+    let streams = read_demuxed_inputs(input_paths, input_options)?;
+    if streams.is_empty() {
+        return Err(anyhow!("No tagged lines found in the input"));
+    }
+    let now = system_now();
+    let mut receivers = Vec::with_capacity(streams.len());
+    let mut failed_gates = Vec::new();
+    for (tag, input_data) in streams {
+        let filtered = Filtered::new(&input_data.fixes, options)
+            .with_context(|| format!("Failed to compute the statistics of receiver {tag}"))?;
+        let summary = Summary::new(&input_data.fixes, filtered, options);
+        let reference_error = reference.map(|reference| summary.reference_error(reference));
+        let failed = gates.check(
+            &summary,
+            reference_error.map(|error| error.horizontal),
+            summary.last_fix_age_s(now),
+        );
+        failed_gates.extend(
+            failed
+                .into_iter()
+                .map(|gate| format!("receiver {tag}: {gate}")),
+        );
+        receivers.push((tag, summary, reference_error));
+    }
+    // Offset of the second receiver from the first one.
+    let baseline = match receivers.as_slice() {
+        [(_, first, _), (_, second, _)] => Some(second.reference_error(first.avg)),
+        _ => None,
+    };
+    let tags = receivers
+        .iter()
+        .map(|(tag, ..)| tag.as_str())
+        .collect::<Vec<_>>();
 
-            if line.starts_with("$PAAG") {
-                return Ok(None);
-            }
+    let results = match output.format {
+        OutputFormat::Plain => receivers
+            .iter()
+            .map(|(tag, summary, _)| {
+                Ok(format!(
+                    "{tag}: {}",
+                    format_position_short(summary.avg, output.coord_format)?
+                ))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?
+            .join("\n"),
+        OutputFormat::Json => serde_json::to_string_pretty(&DemuxReport {
+            height: input_options.height,
+            tags: tags.clone(),
+            receivers: receivers
+                .iter()
+                .map(|(tag, summary, reference_error)| {
+                    let report = ReceiverReport {
+                        average: summary.avg.into(),
+                        entries: summary.n,
+                        entries_filtered: summary.n_filtered,
+                        std_dev_m: summary.std_dev_m.into(),
+                        confidence_interval_m: summary.confidence_interval(options.confidence),
+                        error_m: *reference_error,
+                        last_fix_age_s: summary.last_fix_age_s(now),
+                    };
+                    (tag.as_str(), report)
+                })
+                .collect(),
+            baseline_m: baseline,
+        })?,
+    };
+    output.write_results(&results)?;
 
-            let pos = parse_line(&line)
-                .map_err(|err| anyhow!(err.to_string()))
-                .with_context(|| {
-                    format!("Failed to parse line {} of the input file", line_num + 1)
-                })?;
+    if output.short || (output.format == OutputFormat::Json && output.path.is_none()) {
+        println!("{results}");
+        return Ok(failed_gates);
+    }
 
-            Ok(pos)
-        })
-        .filter_map(|maybe_pos| -> Option<anyhow::Result<DVec3>> { maybe_pos.transpose() })
-        .collect::<anyhow::Result<Vec<_>>>()
+    print_values_note(input_options.height);
+    println!("Receivers: {}", tags.join(", "));
+    let count = |count: usize| output.digit_grouping.format(count);
+    for (tag, summary, reference_error) in &receivers {
+        println!("\nReceiver {tag}:");
+        let formatted = format_position(summary.avg, output.coord_format)?;
+        println!("  Average: {formatted}");
+        let formatted = format!("({} after filter)", count(summary.n_filtered)).italic();
+        println!("  Number of entries: {} {formatted}", count(summary.n));
+        let formatted =
+            format!("({:.2}m, {:.2}m)", summary.std_dev_m.x, summary.std_dev_m.y).italic();
+        println!("  Horizontal standard deviation: ~{formatted}");
+        let confidence = summary.confidence_interval(options.confidence);
+        println!(
+            "  {}% confidence interval of the average: within {:.3}m horizontally",
+            confidence.level, confidence.horizontal
+        );
+        if let Some(error) = reference_error {
+            println!(
+                "  Error from reference: {:.3}m horizontally, {:.3}m in 3D",
+                error.horizontal, error.distance
+            );
+        }
+        if let Some(age_s) = summary.last_fix_age_s(now) {
+            println!("  Age of the last fix: {}", format_duration(age_s));
+        }
+    }
+    if let (Some(baseline), [first, second]) = (baseline, tags.as_slice()) {
+        let formatted = format!(
+            "(E {:.3}m, N {:.3}m, U {:.3}m)",
+            baseline.east, baseline.north, baseline.up
+        );
+        let formatted_distance = format!(
+            "Horizontally: {:.3}m, 3D: {:.3}m",
+            baseline.horizontal, baseline.distance
+        )
+        .italic();
+        println!("\nBaseline from {first} to {second}: {formatted} {formatted_distance}");
+    }
+    Ok(failed_gates)
 }
 
-fn parse_line<'a>(line: &'a str) -> Result<Option<DVec3>, nmea::Error<'a>> {
-    // https://www.sparkfun.com/datasheets/GPS/NMEA%20Reference%20Manual-Rev2.1-Dec07.pdf
+/// Formats the line printed to stderr along with the short output, which sums up the run in a
+/// stable format like `# gpsavg: 2846 fixes (2791 after sigma=3.0, hdop<=2.0), 14m32s, σ_h=0.34m`.
+fn format_audit_line(
+    input_options: &InputOptions,
+    options: &Options,
+    fixes: &[Fix],
+    summary: &Summary,
+) -> String {
+    let mut filters = vec![if options.robust {
+        format!("mad={:?}", options.cutoff)
+    } else {
+        format!("sigma={:?}", options.cutoff)
+    }];
+    if options.max_passes > 1 {
+        filters.push(format!("iterations<={}", options.max_passes));
+    }
+    if options.weighted {
+        filters.push("weighted".to_owned());
+    }
+    if !input_options.exclusions.is_empty() {
+        filters.push("exclusions".to_owned());
+    }
+    if let Some(window) = input_options.window {
+        let format = |time: Option<NaiveTime>| {
+            time.map_or(String::new(), |time| time.format("%H:%M:%S%.f").to_string())
+        };
+        filters.push(format!(
+            "window={}..{}",
+            format(window.from),
+            format(window.to)
+        ));
+    }
+    if let Some(hdop) = input_options.hdop {
+        filters.push(format!("hdop<={:?}", hdop.max_hdop));
+        if let Some(spike_window_s) = hdop.spike_window_s {
+            filters.push(format!("hdop-spike={spike_window_s:?}s"));
+        }
+    }
+    if let Some(dedup_m) = input_options.parse.dedup_m {
+        filters.push(format!("dedup={dedup_m:?}m"));
+    }
+    if input_options.every > 1 {
+        filters.push(format!("every={}", input_options.every));
+    }
+    if let Some(max_samples) = input_options.max_samples {
+        filters.push(format!("max-samples={max_samples}"));
+    }
+    let duration = duration_s(fixes).map_or("-".to_owned(), |duration| {
+        format_duration(duration).replace(' ', "")
+    });
+    format!(
+        "# gpsavg: {} fixes ({} after {}), {duration}, σ_h={:.2}m",
+        summary.n,
+        summary.n_filtered,
+        filters.join(", "),
+        summary.std_dev_horizontal_m()
+    )
+}
 
-    let nmea_line: NmeaSentence<'a> = parse_nmea_sentence(line)?;
-    let gga_data: GgaData = match parse_gga(nmea_line) {
-        Ok(gga_data) => gga_data,
-        Err(nmea::Error::WrongSentenceHeader { .. }) => return Ok(None),
-        Err(err) => Err(err)?,
-    };
+/// Formats the UTC time of a fix like `2026-10-14T12:00:05Z`.
+fn format_fix_time(time: NaiveDateTime) -> String {
+    time.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()
+}
 
-    let (Some(lat), Some(lon), Some(ele)) =
-        (gga_data.latitude, gga_data.longitude, gga_data.altitude)
-    else {
-        return Ok(None);
+/// Prints the note on how the values of the long output are formatted, which names the height
+/// system of the altitudes.
+fn print_values_note(height: HeightSystem) {
+    let altitude = match height {
+        HeightSystem::Orthometric => "altitude",
+        HeightSystem::Ellipsoidal => "ellipsoidal height",
     };
-    Ok(Some(DVec3 {
-        x: lat,
-        y: lon,
-        z: ele as f64,
-    }))
+    let formatted = format!(
+        "Values are formatted as (latitude, longitude, {altitude}) unless specified otherwise.\n"
+    );
+    println!("{}", formatted.italic());
 }
 
-fn histogram(
-    mut positions: Vec<DVec3>,
-    r_variable: fn(&DVec3) -> f64,
-    (avg, std_dev): (DVec3, DVec3),
-) -> (Vec<(i32, DVec3)>, Vec<(f64, f64)>) {
-    let cutoff: i32 = 3; // measured in standard deviations
-    let div: i32 = 6;
-    let mut range = (-(cutoff * div)..(cutoff * div))
-        .map(|i| (i as f64) / (div as f64) * r_variable(&std_dev) + r_variable(&avg))
-        .enumerate()
-        .peekable();
-    positions.sort_by(|a, b| r_variable(a).total_cmp(&r_variable(b)));
-
-    let division_values = range
-        .clone()
-        .map(|(_, x)| x)
-        .zip(range.clone().map(|(_, x)| x).skip(1))
-        .collect::<Vec<_>>();
+/// Formats a position for the long output, in the given format and followed by the raw values in
+/// decimal degrees. The coordinates are rounded half to even.
+fn format_position(position: DVec3, coord_format: CoordFormat) -> anyhow::Result<String> {
+    let formatted = format!(
+        "({}, {}m)",
+        coord_format.format(position.x, position.y)?,
+        format_rounded(position.z, ALTITUDE_DECIMALS)
+    );
+    let formatted_raw = format!("({}, {}, {})", position.x, position.y, position.z);
+    Ok(format!("{} {}", formatted.bold(), formatted_raw.italic()))
+}
 
-    let mut histogram: Vec<(i32, DVec3)> = Vec::new();
+/// Formats a position for the short output, in the given format.
+fn format_position_short(position: DVec3, coord_format: CoordFormat) -> anyhow::Result<String> {
+    Ok(match coord_format {
+        CoordFormat::Dd => format!("{}, {}, {}", position.x, position.y, position.z),
+        CoordFormat::Dms => format!("{}, {}", format_dms(position.x, position.y), position.z),
+        CoordFormat::Utm => {
+            let utm = to_utm(position.x, position.y)?;
+            format!(
+                "{}{}, {}, {}, {}",
+                utm.zone,
+                if utm.north { 'N' } else { 'S' },
+                format_rounded(utm.easting, UTM_DECIMALS),
+                format_rounded(utm.northing, UTM_DECIMALS),
+                position.z
+            )
+        }
+    })
+}
 
-    for pos in positions {
-        while let Some((idx, val)) = range.peek() {
-            if r_variable(&pos) < *val {
-                histogram.push((*idx as i32, pos));
-                break;
-            } else {
-                range.next();
-            }
+/// Results of a run, printed with `--format json`.
+#[derive(serde::Serialize)]
+struct Report<'a> {
+    /// Version of the fields, for `summary-diff`.
+    schema_version: u32,
+    average: Position,
+    /// Height system of the altitudes.
+    height: HeightSystem,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    weighted_average: Option<Position>,
+    entries: usize,
+    entries_filtered: usize,
+    /// Fixes in the ranges given by `--exclude-lines` and `--exclude-time`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    entries_excluded: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    entries_outside_window: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    entries_without_geoid_separation: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duplicates_collapsed: Option<usize>,
+    /// Sentences dropped for arriving after newer ones were put in order, with `--reorder-window`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stragglers_dropped: Option<usize>,
+    /// GGA sentences whose coordinates were corrected to match their hemisphere letters.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hemisphere_corrections: Option<usize>,
+    removed_per_pass: &'a [usize],
+    std_dev: Position,
+    std_dev_m: Enu,
+    confidence_interval_m: ConfidenceInterval,
+    /// Width of the bins of each coordinate with a histogram, chosen with `--bins auto`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    histogram_bin_widths: Option<BTreeMap<&'static str, f64>>,
+    /// Coordinates with the same value in all the filtered fixes.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    repeated_values: BTreeMap<&'static str, f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    median: Option<Position>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mad: Option<Position>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error_m: Option<ReferenceError>,
+    /// UTC date and time of the last filtered fix, and its age relative to the current time.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_fix_time: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_fix_age_s: Option<f64>,
+    /// Seconds from the first fix to the last one, if all of them have times.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duration_s: Option<f64>,
+    /// Horizontal coordinates of the average in every format, with `--all-formats`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    representations: Option<BTreeMap<CoordFormat, String>>,
+    /// Hashes of the input files and of the accepted sentences, with `--provenance`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    provenance: Option<Provenance>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sentence_rates: Option<BTreeMap<String, f64>>,
+    /// Number of standard sentences from each talker.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    talkers: &'a BTreeMap<String, usize>,
+    /// Time gaps and position jumps, with `--detect-jumps`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    warnings: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    segments: Option<Vec<SegmentReport>>,
+}
+
+/// Results of a segment between position jumps, with `--split-on-jump`.
+#[derive(serde::Serialize)]
+struct SegmentReport {
+    first_line: usize,
+    last_line: usize,
+    average: Position,
+    entries: usize,
+    entries_filtered: usize,
+    std_dev_m: Enu,
+}
+
+/// Results of a run with `--demux-prefix`, printed with `--format json`.
+#[derive(serde::Serialize)]
+struct DemuxReport<'a> {
+    /// Height system of the altitudes.
+    height: HeightSystem,
+    tags: Vec<&'a str>,
+    receivers: BTreeMap<&'a str, ReceiverReport>,
+    /// Offset of the average of the second receiver from the first one, when there are two.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    baseline_m: Option<ReferenceError>,
+}
+
+#[derive(serde::Serialize)]
+struct ReceiverReport {
+    average: Position,
+    entries: usize,
+    entries_filtered: usize,
+    std_dev_m: Enu,
+    confidence_interval_m: ConfidenceInterval,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error_m: Option<ReferenceError>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_fix_age_s: Option<f64>,
+}
+
+#[derive(serde::Serialize)]
+struct Position {
+    latitude: f64,
+    longitude: f64,
+    altitude: f64,
+}
+
+impl From<DVec3> for Position {
+    fn from(position: DVec3) -> Self {
+        Self {
+            latitude: position.x,
+            longitude: position.y,
+            altitude: position.z,
         }
     }
-
-    histogram.retain(|(i, _)| *i != 0); //  Removes lower bound of data (atypical data)
-    (histogram, division_values)
 }
 
-fn histogram_val(histogram: Vec<(i32, DVec3)>) -> Vec<i32> {
-    let mut histogram_val = vec![0; histogram.len()];
+#[derive(serde::Serialize)]
+struct Enu {
+    east: f64,
+    north: f64,
+    up: f64,
+}
 
-    for idx in histogram.iter().map(|(i, _)| *i as usize) {
-        histogram_val[idx] += 1;
+impl From<DVec3> for Enu {
+    /// Takes the offsets as (north, east, up), like [`Summary::std_dev_m`].
+    fn from(offsets: DVec3) -> Self {
+        Self {
+            east: offsets.y,
+            north: offsets.x,
+            up: offsets.z,
+        }
     }
-    histogram_val
+}
+
+/// Information about a run, printed to stderr with `--meta-json`.
+#[derive(serde::Serialize)]
+struct Meta<'a> {
+    input_paths: &'a [PathBuf],
+    lines: usize,
+    bytes: u64,
+    unassociated_sentences: usize,
+    rejected_lines: usize,
+    entries: usize,
+    entries_filtered: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timing: Option<Timing>,
 }
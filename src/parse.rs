@@ -0,0 +1,1257 @@
+//! Parsing of the input files into fixes.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufReader, Read},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+use chrono::NaiveTime;
+use flate2::bufread::MultiGzDecoder;
+use glam::DVec3;
+use nmea::{
+    parse_nmea_sentence,
+    sentences::{parse_gga, parse_gsa, parse_rmc, GgaData},
+    NmeaSentence, SentenceType,
+};
+
+use crate::{
+    epoch::{self, Dop, Epoch, EpochAssembler, Gga, Reorderer, Rmc, Sentence},
+    stats::enu_offsets,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InputFormat {
+    /// NMEA 0183 sentences, of which only GGA sentences are used.
+    Nmea,
+    /// One position per line, as `latitude longitude altitude` separated by spaces or commas.
+    Plain,
+}
+
+/// Vertical reference of the altitudes of the fixes.
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HeightSystem {
+    /// Height above the geoid, as given by GGA sentences.
+    Orthometric,
+    /// Height above the WGS84 ellipsoid: the height above the geoid plus the geoid separation of
+    /// the GGA sentence.
+    Ellipsoidal,
+}
+
+/// How the input files are read.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InputOptions {
+    pub format: InputFormat,
+    /// Column (starting at 1) of the plain input holding the weight of each position.
+    pub weight_column: Option<usize>,
+    /// Each line is prefixed with the tag of the stream it belongs to, like `A:`, and the fixes of
+    /// each tag are kept apart.
+    pub demux: bool,
+    pub parse: ParseOptions,
+    /// Fixes in these ranges of lines or times are dropped before anything else.
+    pub exclusions: Exclusions,
+    /// Fixes without a geoid separation are dropped for ellipsoidal heights.
+    pub height: HeightSystem,
+    /// Only fixes with a time inside this window are kept.
+    pub window: Option<TimeWindow>,
+    /// Fixes with a high HDOP, or shortly after one, are dropped.
+    pub hdop: Option<HdopFilter>,
+    /// Only every `every`-th fix is kept, starting with the first one.
+    pub every: usize,
+    /// The fixes are subsampled uniformly down to this number, if there are more.
+    pub max_samples: Option<usize>,
+}
+
+impl Default for InputOptions {
+    fn default() -> Self {
+        Self {
+            format: InputFormat::Nmea,
+            weight_column: None,
+            demux: false,
+            parse: ParseOptions::default(),
+            exclusions: Exclusions::default(),
+            height: HeightSystem::Orthometric,
+            window: None,
+            hdop: None,
+            every: 1,
+            max_samples: None,
+        }
+    }
+}
+
+/// Fixes read from all the input files, along with some information about the files.
+pub struct InputData {
+    pub fixes: Vec<Fix>,
+    pub lines: usize,
+    pub bytes: u64,
+    /// Number of auxiliary sentences which couldn't be associated with any GGA fix.
+    pub unassociated: usize,
+    /// Number of fixes dropped for being in the excluded ranges.
+    pub excluded: usize,
+    /// Number of fixes dropped for having no geoid separation, for ellipsoidal heights.
+    pub no_geoid_separation: usize,
+    /// Number of GGA sentences whose coordinates were corrected to match their hemisphere letters.
+    pub hemisphere_corrected: usize,
+    /// Number of fixes dropped for being outside the time window.
+    pub outside_window: usize,
+    /// Number of fixes dropped for having a HDOP above the limit.
+    pub high_hdop: usize,
+    /// Number of fixes dropped for being shortly after one with a HDOP above the limit.
+    pub after_hdop_spike: usize,
+    /// Number of fixes dropped by `every` and `max_samples`.
+    pub decimated: usize,
+    /// Number of invalid lines skipped in lenient mode.
+    pub rejected: usize,
+    /// Number of fixes collapsed for repeating the position of the previous one.
+    pub duplicates: usize,
+    /// Number of sentences dropped for arriving after newer ones were reordered.
+    pub stragglers: usize,
+    pub sentences: SentenceStats,
+}
+
+/// Reads and combines the fixes of all the input files.
+pub fn read_inputs(input_paths: &[PathBuf], options: &InputOptions) -> anyhow::Result<InputData> {
+    check_input_options(options, OptionNames::Arguments)?;
+
+    let mut parsed_files = Vec::with_capacity(input_paths.len());
+    let mut bytes = 0;
+    for input_path in input_paths {
+        let (reader, file_bytes) = open_input_file(input_path)?;
+        bytes += file_bytes;
+        let parsed_file = parse_input(reader, options)
+            .with_context(|| format!("Failed to parse input file at {}", input_path.display()))?;
+        parsed_files.push(parsed_file);
+    }
+    Ok(InputData::new(parsed_files, bytes, options))
+}
+
+/// Reads the input files like [`read_inputs`], with each line prefixed with the tag of the stream
+/// it belongs to, and combines the fixes of each tag separately. The tags are found in the files,
+/// and the streams of each of them are parsed as if they were separate files.
+pub fn read_demuxed_inputs(
+    input_paths: &[PathBuf],
+    options: &InputOptions,
+) -> anyhow::Result<BTreeMap<String, InputData>> {
+    check_input_options(options, OptionNames::Arguments)?;
+
+    let mut parsed_files = BTreeMap::<String, (Vec<ParsedFile>, u64)>::new();
+    for input_path in input_paths {
+        let (reader, _) = open_input_file(input_path)?;
+        let streams = demux_lines(reader, options.parse.policy.other == Strictness::Lenient)
+            .with_context(|| format!("Failed to parse input file at {}", input_path.display()))?;
+        for (tag, stream) in streams {
+            let parsed_file = parse_input(stream.text.as_bytes(), options)
+                .map(|parsed_file| stream.renumber(parsed_file))
+                .map_err(|err| stream.renumber_error(err))
+                .with_context(|| {
+                    format!(
+                        "Failed to parse the stream {tag} of input file at {}",
+                        input_path.display()
+                    )
+                })?;
+            let (files, bytes) = parsed_files.entry(tag).or_default();
+            files.push(parsed_file);
+            *bytes += stream.text.len() as u64;
+        }
+    }
+    Ok(parsed_files
+        .into_iter()
+        .map(|(tag, (files, bytes))| (tag, InputData::new(files, bytes, options)))
+        .collect())
+}
+
+/// Lines of a stream of a multiplexed file, without their tag.
+#[derive(Debug, Default)]
+struct Stream {
+    text: String,
+    /// Line of the file each line of the stream was read from, starting at 1.
+    lines: Vec<usize>,
+}
+
+impl Stream {
+    fn file_line(&self, line: usize) -> usize {
+        self.lines
+            .get(line.wrapping_sub(1))
+            .copied()
+            .unwrap_or(line)
+    }
+
+    /// Replaces the lines of the stream of the fixes with those of the file.
+    fn renumber(&self, mut parsed_file: ParsedFile) -> ParsedFile {
+        for fix in &mut parsed_file.fixes {
+            fix.line = self.file_line(fix.line);
+            if let Some(epoch) = &mut fix.epoch {
+                epoch.line = self.file_line(epoch.line);
+            }
+        }
+        parsed_file
+    }
+
+    fn renumber_error(&self, err: ParseError) -> ParseError {
+        match err {
+            ParseError::Io { line, source } => ParseError::Io {
+                line: self.file_line(line),
+                source,
+            },
+            ParseError::InvalidSentence { line, message } => ParseError::InvalidSentence {
+                line: self.file_line(line),
+                message,
+            },
+            ParseError::InvalidPosition { line, message } => ParseError::InvalidPosition {
+                line: self.file_line(line),
+                message,
+            },
+        }
+    }
+}
+
+/// Returns the tag of a line prefixed with one and a colon, like `A:$GPGGA,...`, along with the
+/// rest of the line. Tags are made of letters, digits, `-` and `_`.
+pub fn split_tag(line: &str) -> Option<(&str, &str)> {
+    let (tag, rest) = line.split_once(':')?;
+    let is_tag = !tag.is_empty()
+        && tag
+            .chars()
+            .all(|char| char.is_ascii_alphanumeric() || char == '-' || char == '_');
+    is_tag.then_some((tag, rest))
+}
+
+/// Separates the lines of each tag of a multiplexed file. Empty lines are ignored, and so are
+/// lines without a tag when other lines are lenient.
+fn demux_lines(file: impl BufRead, lenient: bool) -> Result<BTreeMap<String, Stream>, ParseError> {
+    let mut streams = BTreeMap::<String, Stream>::new();
+    for (line_num, line) in file.lines().enumerate() {
+        let line = line.map_err(|source| ParseError::Io {
+            line: line_num + 1,
+            source,
+        })?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Some((tag, rest)) = split_tag(&line) else {
+            if lenient {
+                continue;
+            }
+            return Err(ParseError::InvalidSentence {
+                line: line_num + 1,
+                message: "the line has no tag like `A:`".to_owned(),
+            });
+        };
+        let stream = streams.entry(tag.to_owned()).or_default();
+        stream.text.push_str(rest);
+        stream.text.push('\n');
+        stream.lines.push(line_num + 1);
+    }
+    Ok(streams)
+}
+
+/// Parses the contents of an input file in the format of `options`.
+fn parse_input(reader: impl BufRead, options: &InputOptions) -> Result<ParsedFile, ParseError> {
+    match options.format {
+        InputFormat::Nmea => parse_file(reader, &options.parse),
+        InputFormat::Plain => parse_plain_file(reader, options.weight_column, &options.parse),
+    }
+}
+
+/// Opens an input file, decompressing it if needed. Returns a reader of its contents, along with
+/// the size of the file.
+pub(crate) fn open_input_file(input_path: &Path) -> anyhow::Result<(Box<dyn BufRead>, u64)> {
+    let file = File::open(input_path)
+        .with_context(|| format!("Failed to read input file at {}", input_path.display()))?;
+    let bytes = file.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+    let reader = open_input(input_path, file)
+        .with_context(|| format!("Failed to read input file at {}", input_path.display()))?;
+    Ok((reader, bytes))
+}
+
+/// How [`check_input_options`] names the options in its errors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OptionNames {
+    /// As the command line arguments, like `--max-hdop`.
+    Arguments,
+    /// As the settings of a batch job, like `max_hdop`.
+    JobSettings,
+}
+
+impl OptionNames {
+    /// Returns the name of the option given in the command line as `--{argument}`.
+    fn name(self, argument: &str) -> String {
+        match self {
+            OptionNames::Arguments => format!("--{argument}"),
+            OptionNames::JobSettings => argument.replace('-', "_"),
+        }
+    }
+
+    /// Returns the option given in the command line as `--{argument}`, set to `value`.
+    fn set(self, argument: &str, value: &str) -> String {
+        match self {
+            OptionNames::Arguments => format!("--{argument} {value}"),
+            OptionNames::JobSettings => format!("{} = \"{value}\"", self.name(argument)),
+        }
+    }
+}
+
+/// Checks the values of the input options, and the options which only apply to some input formats.
+/// The options are named as `names` in the errors.
+pub fn check_input_options(options: &InputOptions, names: OptionNames) -> anyhow::Result<()> {
+    let nmea = options.format == InputFormat::Nmea;
+    let requires_nmea =
+        |option: String| anyhow!("{option} requires {}", names.set("input-format", "nmea"));
+    if options.weight_column == Some(0) {
+        return Err(anyhow!(
+            "{} must be at least 1, since columns start at 1",
+            names.name("weight-column")
+        ));
+    }
+    if options.weight_column.is_some() && options.format != InputFormat::Plain {
+        return Err(anyhow!(
+            "{} requires {}",
+            names.name("weight-column"),
+            names.set("input-format", "plain")
+        ));
+    }
+    let staleness = options.parse.staleness;
+    if staleness.is_nan() || staleness < 0. {
+        return Err(anyhow!(
+            "{} must be a non-negative number of seconds",
+            names.name("staleness")
+        ));
+    }
+    if options.window.is_some() && !nmea {
+        return Err(anyhow!(
+            "{}, {}, {} and {} require {}",
+            names.name("from"),
+            names.name("to"),
+            names.name("start"),
+            names.name("end"),
+            names.set("input-format", "nmea")
+        ));
+    }
+    if !options.exclusions.times.is_empty() && !nmea {
+        return Err(requires_nmea(names.name("exclude-time")));
+    }
+    if options.height == HeightSystem::Ellipsoidal && !nmea {
+        return Err(requires_nmea(names.set("height", "ellipsoidal")));
+    }
+    if let Some(hdop) = options.hdop {
+        if !nmea {
+            return Err(requires_nmea(names.name("max-hdop")));
+        }
+        if hdop.max_hdop.is_nan() || hdop.max_hdop <= 0. {
+            return Err(anyhow!("{} must be positive", names.name("max-hdop")));
+        }
+        if hdop
+            .spike_window_s
+            .is_some_and(|window| window.is_nan() || window < 0.)
+        {
+            return Err(anyhow!(
+                "{} must be a non-negative number of seconds",
+                names.name("hdop-spike-window")
+            ));
+        }
+    }
+    if let Some(dedup_m) = options.parse.dedup_m {
+        if !nmea {
+            return Err(requires_nmea(names.name("dedup")));
+        }
+        if dedup_m.is_nan() || dedup_m < 0. {
+            return Err(anyhow!(
+                "{} must be a non-negative number of meters",
+                names.name("dedup-epsilon")
+            ));
+        }
+    }
+    if let Some(window) = options.parse.reorder_window_s {
+        if !nmea {
+            return Err(requires_nmea(names.name("reorder-window")));
+        }
+        if window.is_nan() || window < 0. {
+            return Err(anyhow!(
+                "{} must be a non-negative number of seconds",
+                names.name("reorder-window")
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// First bytes of gzip files.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Returns a reader of the contents of an input file, decompressing them if the file has a `.gz`
+/// extension or starts like a gzip file.
+pub(crate) fn open_input<'a>(
+    path: &Path,
+    file: impl Read + 'a,
+) -> io::Result<Box<dyn BufRead + 'a>> {
+    let mut reader = BufReader::new(file);
+    let is_gzip = path.extension().is_some_and(|extension| extension == "gz")
+        || reader.fill_buf()?.starts_with(&GZIP_MAGIC);
+    Ok(if is_gzip {
+        // Files may have several gzip members, like when appending to them with `gzip -c >>`.
+        Box::new(BufReader::new(MultiGzDecoder::new(reader)))
+    } else {
+        Box::new(reader)
+    })
+}
+
+impl InputData {
+    /// Combines the fixes of the parsed files, of `bytes` bytes in total, drops those in the
+    /// excluded ranges of `options`, converts their altitudes into the height system of `options`,
+    /// and drops those left out by the time window, the HDOP filter and the decimation of
+    /// `options`, in that order.
+    pub fn new(parsed_files: Vec<ParsedFile>, bytes: u64, options: &InputOptions) -> Self {
+        let mut data = InputData {
+            fixes: Vec::new(),
+            lines: 0,
+            bytes,
+            unassociated: 0,
+            excluded: 0,
+            no_geoid_separation: 0,
+            hemisphere_corrected: 0,
+            outside_window: 0,
+            high_hdop: 0,
+            after_hdop_spike: 0,
+            decimated: 0,
+            rejected: 0,
+            duplicates: 0,
+            stragglers: 0,
+            sentences: SentenceStats::default(),
+        };
+        for (file, parsed_file) in parsed_files.into_iter().enumerate() {
+            data.fixes
+                .extend(parsed_file.fixes.into_iter().map(|fix| Fix { file, ..fix }));
+            data.lines += parsed_file.lines;
+            data.unassociated += parsed_file.unassociated;
+            data.hemisphere_corrected += parsed_file.hemisphere_corrected;
+            data.rejected += parsed_file.rejected;
+            data.duplicates += parsed_file.duplicates;
+            data.stragglers += parsed_file.stragglers;
+            data.sentences.merge(&parsed_file.sentences);
+        }
+
+        if !options.exclusions.is_empty() {
+            let read = data.fixes.len();
+            data.fixes.retain(|fix| !options.exclusions.excludes(fix));
+            data.excluded = read - data.fixes.len();
+        }
+
+        if options.height == HeightSystem::Ellipsoidal {
+            let read = data.fixes.len();
+            data.fixes = std::mem::take(&mut data.fixes)
+                .into_iter()
+                .filter_map(Fix::with_ellipsoidal_height)
+                .collect();
+            data.no_geoid_separation = read - data.fixes.len();
+        }
+
+        if let Some(window) = options.window {
+            let read = data.fixes.len();
+            data.fixes
+                .retain(|fix| fix.time().is_some_and(|time| window.contains(time)));
+            data.outside_window = read - data.fixes.len();
+        }
+
+        if let Some(hdop) = options.hdop {
+            let (fixes, high_hdop, after_hdop_spike) = hdop.apply(std::mem::take(&mut data.fixes));
+            data.fixes = fixes;
+            data.high_hdop = high_hdop;
+            data.after_hdop_spike = after_hdop_spike;
+        }
+
+        let selected = data.fixes.len();
+        data.fixes = decimate(
+            std::mem::take(&mut data.fixes),
+            options.every,
+            options.max_samples,
+        );
+        data.decimated = selected - data.fixes.len();
+        data
+    }
+}
+
+/// Ranges of lines and of UTC times whose fixes are dropped, like a part of the log known to be
+/// bad. Both ends of the ranges are included.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Exclusions {
+    /// Ranges of lines of each input file, starting at 1.
+    pub lines: Vec<RangeInclusive<usize>>,
+    /// Ranges of times, which wrap around midnight when they start later than they end.
+    pub times: Vec<TimeWindow>,
+}
+
+impl Exclusions {
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty() && self.times.is_empty()
+    }
+
+    /// Whether the fix is in any of the ranges. Fixes without a time are only excluded by line.
+    pub fn excludes(&self, fix: &Fix) -> bool {
+        self.lines.iter().any(|lines| lines.contains(&fix.line))
+            || fix
+                .time()
+                .is_some_and(|time| self.times.iter().any(|times| times.contains(time)))
+    }
+}
+
+/// Merges the overlapping and adjacent ranges, and sorts them.
+pub fn merge_ranges(mut ranges: Vec<RangeInclusive<usize>>) -> Vec<RangeInclusive<usize>> {
+    ranges.sort_by_key(|range| (*range.start(), *range.end()));
+    let mut merged: Vec<RangeInclusive<usize>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if *range.start() <= last.end().saturating_add(1) => {
+                *last = *last.start()..=(*last.end()).max(*range.end());
+            }
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/// Limit on the HDOP of the fixes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HdopFilter {
+    pub max_hdop: f64,
+    /// Fixes up to this number of seconds after one with a HDOP above the limit are dropped too,
+    /// since the position takes a while to recover from a HDOP spike.
+    pub spike_window_s: Option<f64>,
+}
+
+impl HdopFilter {
+    /// Drops the fixes with a HDOP above the limit, and those inside the spike window after one of
+    /// them. Fixes without a HDOP are kept. Returns the kept fixes, along with the number of fixes
+    /// dropped for their HDOP and for being inside a spike window.
+    pub fn apply(&self, fixes: Vec<Fix>) -> (Vec<Fix>, usize, usize) {
+        let (mut high_hdop, mut after_spike) = (0, 0);
+        let mut last_spike = None;
+        let mut kept = Vec::with_capacity(fixes.len());
+        for fix in fixes {
+            if fix.hdop().is_some_and(|hdop| hdop as f64 > self.max_hdop) {
+                high_hdop += 1;
+                last_spike = fix.time().or(last_spike);
+                continue;
+            }
+            let in_spike_window = match (self.spike_window_s, last_spike, fix.time()) {
+                (Some(window), Some(spike), Some(time)) => {
+                    (0. ..=window).contains(&epoch::seconds_between(spike, time))
+                }
+                _ => false,
+            };
+            if in_spike_window {
+                after_spike += 1;
+            } else {
+                kept.push(fix);
+            }
+        }
+        (kept, high_hdop, after_spike)
+    }
+}
+
+/// Keeps every `every`-th fix, and then subsamples the rest uniformly down to `max_samples`
+/// fixes if there are more.
+pub fn decimate(fixes: Vec<Fix>, every: usize, max_samples: Option<usize>) -> Vec<Fix> {
+    let fixes = if every > 1 {
+        fixes.into_iter().step_by(every).collect()
+    } else {
+        fixes
+    };
+    match max_samples {
+        Some(max_samples) if max_samples < fixes.len() => (0..max_samples)
+            .map(|idx| fixes[idx * fixes.len() / max_samples])
+            .collect(),
+        _ => fixes,
+    }
+}
+
+/// A position read from the input file, along with its weight for the weighted average.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fix {
+    pub position: DVec3,
+    pub weight: f64,
+    /// Line of the input file the fix was read from, starting at 1.
+    pub line: usize,
+    /// Index of the input file the fix was read from, among the files combined.
+    pub file: usize,
+    /// The GGA sentence of the fix and the sentences associated to it, for NMEA input.
+    pub epoch: Option<Epoch>,
+}
+
+impl Fix {
+    /// Returns the fix of the epoch, if its GGA sentence has a position.
+    pub fn from_epoch(epoch: Epoch) -> Option<Self> {
+        Some(Fix {
+            position: epoch.gga.position?,
+            weight: 1.,
+            line: epoch.line,
+            file: 0,
+            epoch: Some(epoch),
+        })
+    }
+
+    /// HDOP of the fix, from its GGA sentence or otherwise from the GSA sentence associated to it.
+    pub fn hdop(&self) -> Option<f32> {
+        let epoch = self.epoch?;
+        epoch.gga.hdop.or(epoch.dop.and_then(|dop| dop.hdop))
+    }
+
+    /// Returns the fix with its altitude converted into a height above the ellipsoid, if its GGA
+    /// sentence has the geoid separation.
+    pub fn with_ellipsoidal_height(self) -> Option<Self> {
+        let separation = self.epoch?.gga.geoid_separation?;
+        let mut fix = self;
+        fix.position.z += separation;
+        Some(fix)
+    }
+
+    /// UTC time of the fix, for NMEA input.
+    pub fn time(&self) -> Option<NaiveTime> {
+        self.epoch.and_then(|epoch| epoch.gga.time)
+    }
+}
+
+/// Range of UTC fix times, both ends included. When `from` is later than `to`, the window wraps
+/// around midnight.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimeWindow {
+    pub from: Option<NaiveTime>,
+    pub to: Option<NaiveTime>,
+}
+
+impl TimeWindow {
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        match (self.from, self.to) {
+            (Some(from), Some(to)) if from > to => time >= from || time <= to,
+            (from, to) => from.is_none_or(|from| time >= from) && to.is_none_or(|to| time <= to),
+        }
+    }
+}
+
+/// Options for parsing the input files.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseOptions {
+    /// Maximum time difference in seconds between a GGA fix and a timestamped sentence associated
+    /// to it, used when there is no sentence with the same time.
+    pub staleness: f64,
+    /// How the lines which can't be parsed are handled, for each class of sentences.
+    pub policy: LinePolicy,
+    /// Check the signs of the coordinates of GGA sentences against their hemisphere letters, and
+    /// correct them when they disagree.
+    pub verify_hemisphere: bool,
+    /// Collapse runs of consecutive fixes closer than this number of meters to the first one of
+    /// the run, in every direction, into that first fix.
+    pub dedup_m: Option<f64>,
+    /// Put back in order the sentences up to this number of seconds older than the newest one,
+    /// for sources which deliver them in reordered bursts, before associating them with the fixes.
+    pub reorder_window_s: Option<f64>,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            staleness: 1.,
+            policy: LinePolicy::default(),
+            verify_hemisphere: true,
+            dedup_m: None,
+            reorder_window_s: None,
+        }
+    }
+}
+
+/// Classes of NMEA sentences with their own [`Strictness`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum SentenceClass {
+    /// GGA sentences, which hold the positions. Lines of plain input are handled like them.
+    Gga,
+    /// Every other sentence, along with the lines which aren't NMEA sentences at all.
+    Other,
+}
+
+impl SentenceClass {
+    fn of_line(line: &str) -> Self {
+        match sentence_type(line) {
+            Some("GGA") => SentenceClass::Gga,
+            _ => SentenceClass::Other,
+        }
+    }
+}
+
+/// How the lines of a class of sentences which can't be parsed are handled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Strictness {
+    /// The parsing fails, but checksums aren't verified.
+    #[default]
+    Normal,
+    /// The parsing fails, and so it does for lines whose checksum doesn't match.
+    Strict,
+    /// The lines are skipped and counted as rejected.
+    Lenient,
+}
+
+/// Strictness of each class of sentences, consulted for every line of the input files.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LinePolicy {
+    pub gga: Strictness,
+    pub other: Strictness,
+}
+
+impl LinePolicy {
+    /// Policy skipping the invalid lines of every class.
+    pub fn lenient() -> Self {
+        Self {
+            gga: Strictness::Lenient,
+            other: Strictness::Lenient,
+        }
+    }
+
+    pub fn get(&self, class: SentenceClass) -> Strictness {
+        match class {
+            SentenceClass::Gga => self.gga,
+            SentenceClass::Other => self.other,
+        }
+    }
+
+    pub fn set(&mut self, class: SentenceClass, strictness: Strictness) {
+        match class {
+            SentenceClass::Gga => self.gga = strictness,
+            SentenceClass::Other => self.other = strictness,
+        }
+    }
+
+    /// Whether the invalid lines of some class are skipped, so the rejected lines are worth
+    /// reporting.
+    pub fn skips_lines(&self) -> bool {
+        self.gga == Strictness::Lenient || self.other == Strictness::Lenient
+    }
+}
+
+/// Default distance in meters below which consecutive fixes are collapsed with `--dedup`. Fixes
+/// logged with 5 decimals of arcminutes are about 2cm apart at least.
+pub const DEFAULT_DEDUP_M: f64 = 1e-3;
+
+/// Default number of seconds sentences read from a serial port are kept to put them back in
+/// order, since devices may send them in reordered bursts.
+pub const DEFAULT_REORDER_WINDOW_S: f64 = 2.;
+
+/// Fixes read from an input file, along with the number of lines it contains.
+pub struct ParsedFile {
+    pub fixes: Vec<Fix>,
+    pub lines: usize,
+    /// Number of auxiliary sentences which couldn't be associated with any GGA fix.
+    pub unassociated: usize,
+    /// Number of lines skipped for being invalid, in lenient mode.
+    pub rejected: usize,
+    /// Number of GGA sentences whose coordinates were corrected to match their hemisphere letters.
+    pub hemisphere_corrected: usize,
+    /// Number of fixes collapsed for repeating the position of the previous one.
+    pub duplicates: usize,
+    /// Number of sentences dropped for arriving after newer ones were reordered.
+    pub stragglers: usize,
+    pub sentences: SentenceStats,
+}
+
+/// Number of sentences of each type in the input, and the time they span.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SentenceStats {
+    /// Number of sentences of each type, identified without the talker, like `GGA`. Proprietary
+    /// sentences are identified by their whole address, like `PAAG`.
+    pub counts: BTreeMap<String, usize>,
+    /// Number of standard sentences from each talker, like `GP` for GPS or `NQ` for NavIC and
+    /// QZSS.
+    pub talkers: BTreeMap<String, usize>,
+    /// Seconds from the first to the last timestamp of the sentences, summed over all the files.
+    pub duration_s: f64,
+}
+
+impl SentenceStats {
+    /// Ratio between the rates of two sentence types beyond which they are reported as a mismatch.
+    pub const MISMATCH_RATIO: f64 = 1.5;
+
+    /// Sentence types used to compute the fixes. The rest are skipped.
+    pub const USED: [&'static str; 4] = ["GGA", "GSA", "GST", "RMC"];
+
+    /// Sentence types which usually come in groups, so their rate is not compared with the one of
+    /// GGA sentences.
+    const GROUPED: [&'static str; 2] = ["GSA", "GSV"];
+
+    pub fn merge(&mut self, other: &SentenceStats) {
+        for (sentence_type, count) in &other.counts {
+            *self.counts.entry(sentence_type.clone()).or_default() += count;
+        }
+        for (talker, count) in &other.talkers {
+            *self.talkers.entry(talker.clone()).or_default() += count;
+        }
+        self.duration_s += other.duration_s;
+    }
+
+    /// Number of sentences of each type which are skipped for not being used, like the
+    /// proprietary ones.
+    pub fn skipped(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.counts
+            .iter()
+            .filter(|(sentence_type, _)| !Self::USED.contains(&sentence_type.as_str()))
+            .map(|(sentence_type, count)| (sentence_type.as_str(), *count))
+    }
+
+    /// Number of sentences per second of each type, if the sentences span some time.
+    pub fn rates(&self) -> Option<BTreeMap<String, f64>> {
+        (self.duration_s > 0.).then(|| {
+            self.counts
+                .iter()
+                .map(|(sentence_type, count)| {
+                    (sentence_type.clone(), *count as f64 / self.duration_s)
+                })
+                .collect()
+        })
+    }
+
+    /// Returns the standard sentence types whose rate differs from the one of GGA sentences by
+    /// more than [`Self::MISMATCH_RATIO`], along with the ratio between both rates.
+    pub fn mismatches(&self) -> Vec<(String, f64)> {
+        let Some(&gga) = self.counts.get("GGA") else {
+            return Vec::new();
+        };
+        self.counts
+            .iter()
+            .filter(|(sentence_type, _)| {
+                !sentence_type.starts_with('P')
+                    && sentence_type.as_str() != "GGA"
+                    && !Self::GROUPED.contains(&sentence_type.as_str())
+            })
+            .map(|(sentence_type, count)| (sentence_type.clone(), *count as f64 / gga as f64))
+            .filter(|(_, ratio)| {
+                *ratio > Self::MISMATCH_RATIO || *ratio < 1. / Self::MISMATCH_RATIO
+            })
+            .collect()
+    }
+}
+
+/// Returns the type of an NMEA sentence, like `GGA` for `$GPGGA,...`. Proprietary sentences keep
+/// their whole address.
+fn sentence_type(line: &str) -> Option<&str> {
+    let address = line.strip_prefix('$')?.split(',').next()?;
+    if address.starts_with('P') {
+        Some(address)
+    } else {
+        address.get(2..)
+    }
+}
+
+/// Returns the talker of a standard NMEA sentence, like `GP` for `$GPGGA,...`. Any two
+/// characters are accepted, since receivers keep adding talkers for new constellations.
+fn talker(line: &str) -> Option<&str> {
+    let address = line.strip_prefix('$')?.split(',').next()?;
+    if address.starts_with('P') {
+        None
+    } else {
+        address.get(..2)
+    }
+}
+
+/// Error found while parsing an input file.
+#[derive(Debug)]
+pub enum ParseError {
+    /// The line couldn't be read from the file. These errors are reported even in lenient mode.
+    Io { line: usize, source: std::io::Error },
+    /// The line isn't an NMEA sentence, or it is a malformed GGA sentence.
+    InvalidSentence { line: usize, message: String },
+    /// The line of plain input doesn't hold a valid position.
+    InvalidPosition { line: usize, message: String },
+}
+
+impl ParseError {
+    /// Line of the input file where the error was found, starting at 1.
+    pub fn line(&self) -> usize {
+        match self {
+            ParseError::Io { line, .. }
+            | ParseError::InvalidSentence { line, .. }
+            | ParseError::InvalidPosition { line, .. } => *line,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Io { line, source } => {
+                write!(f, "Failed to read line {line} of the input file: {source}")
+            }
+            ParseError::InvalidSentence { line, message }
+            | ParseError::InvalidPosition { line, message } => {
+                write!(
+                    f,
+                    "Failed to parse line {line} of the input file: {message}"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ParseError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Parses a file of NMEA sentences. Empty lines and proprietary sentences (those starting with
+/// `$P`) are ignored.
+pub fn parse_file(file: impl BufRead, options: &ParseOptions) -> Result<ParsedFile, ParseError> {
+    let mut lines = 0;
+    let mut rejected = 0;
+    let mut hemisphere_corrected = 0;
+    let mut assembler = EpochAssembler::new(options.staleness);
+    let mut reorderer = options.reorder_window_s.map(Reorderer::new);
+    let mut fixes = Vec::new();
+    let mut sentences = SentenceStats::default();
+    // Latest timestamp of the sentences, to measure the duration of the file.
+    let mut latest: Option<NaiveTime> = None;
+
+    for (line_num, line) in file.lines().enumerate() {
+        lines += 1;
+        let line = line.map_err(|source| ParseError::Io {
+            line: line_num + 1,
+            source,
+        })?;
+
+        if let Some(sentence_type) = sentence_type(&line) {
+            *sentences
+                .counts
+                .entry(sentence_type.to_owned())
+                .or_default() += 1;
+        }
+        if let Some(talker) = talker(&line) {
+            *sentences.talkers.entry(talker.to_owned()).or_default() += 1;
+        }
+        if line.trim().is_empty() || line.starts_with("$P") {
+            continue;
+        }
+
+        let strictness = options.policy.get(SentenceClass::of_line(&line));
+        let sentence = match parse_checked_line(&line, strictness, options.verify_hemisphere) {
+            Ok(sentence) => sentence,
+            Err(_) if strictness == Strictness::Lenient => {
+                rejected += 1;
+                continue;
+            }
+            Err(err) => {
+                return Err(ParseError::InvalidSentence {
+                    line: line_num + 1,
+                    message: err.to_string(),
+                })
+            }
+        };
+
+        if let Some(Sentence::Gga(gga)) = sentence {
+            hemisphere_corrected += usize::from(gga.hemisphere_corrected);
+        }
+        if let Some(time) = sentence.and_then(|sentence| sentence.time()) {
+            // Sentences may arrive slightly out of order, so only move forward.
+            let elapsed = latest.map_or(0., |latest| epoch::seconds_between(latest, time));
+            if latest.is_none() || elapsed > 0. {
+                sentences.duration_s += elapsed;
+                latest = Some(time);
+            }
+        }
+
+        let Some(sentence) = sentence else {
+            continue;
+        };
+        let ready = match &mut reorderer {
+            Some(reorderer) => reorderer.push(sentence, line_num + 1),
+            None => vec![(sentence, line_num + 1)],
+        };
+        for (sentence, line) in ready {
+            fixes.extend(assembler.push(sentence, line).and_then(Fix::from_epoch));
+        }
+    }
+    for (sentence, line) in reorderer.iter_mut().flat_map(Reorderer::finish) {
+        fixes.extend(assembler.push(sentence, line).and_then(Fix::from_epoch));
+    }
+    fixes.extend(assembler.finish().and_then(Fix::from_epoch));
+
+    let read = fixes.len();
+    if let Some(dedup_m) = options.dedup_m {
+        fixes = collapse_duplicates(fixes, dedup_m);
+    }
+
+    Ok(ParsedFile {
+        duplicates: read - fixes.len(),
+        fixes,
+        lines,
+        unassociated: assembler.dropped(),
+        rejected,
+        hemisphere_corrected,
+        stragglers: reorderer.as_ref().map_or(0, Reorderer::stragglers),
+        sentences,
+    })
+}
+
+/// Collapses each run of consecutive fixes closer than `epsilon_m` meters to the first one of the
+/// run, in every direction, into that first fix. Stationary receivers may repeat the same position
+/// many times, which would make the standard deviation look smaller than it is.
+pub fn collapse_duplicates(fixes: Vec<Fix>, epsilon_m: f64) -> Vec<Fix> {
+    let mut kept: Vec<Fix> = Vec::with_capacity(fixes.len());
+    for fix in fixes {
+        let is_duplicate = kept.last().is_some_and(|first| {
+            let offset = enu_offsets(&[fix.position], first.position)[0];
+            offset.abs().max_element() <= epsilon_m
+        });
+        if !is_duplicate {
+            kept.push(fix);
+        }
+    }
+    kept
+}
+
+/// Parses a file with one position per line, given as `latitude longitude altitude` and
+/// separated by spaces and/or commas. Empty lines and lines starting with `#` are ignored. If
+/// `weight_column` is given, the weight of each position is read from that column (starting at 1),
+/// and must be positive.
+pub fn parse_plain_file(
+    file: impl BufRead,
+    weight_column: Option<usize>,
+    options: &ParseOptions,
+) -> Result<ParsedFile, ParseError> {
+    let mut lines = 0;
+    let mut rejected = 0;
+    let mut fixes = Vec::new();
+
+    for (line_num, line) in file.lines().enumerate() {
+        lines += 1;
+        let line = line.map_err(|source| ParseError::Io {
+            line: line_num + 1,
+            source,
+        })?;
+
+        match parse_plain_line(&line, weight_column) {
+            Ok(fix) => fixes.extend(fix.map(|fix| Fix {
+                line: line_num + 1,
+                ..fix
+            })),
+            Err(_) if options.policy.gga == Strictness::Lenient => rejected += 1,
+            Err(err) => {
+                return Err(ParseError::InvalidPosition {
+                    line: line_num + 1,
+                    message: format!("{err:#}"),
+                })
+            }
+        }
+    }
+
+    Ok(ParsedFile {
+        fixes,
+        lines,
+        unassociated: 0,
+        rejected,
+        hemisphere_corrected: 0,
+        duplicates: 0,
+        stragglers: 0,
+        sentences: SentenceStats::default(),
+    })
+}
+
+pub fn parse_plain_line(line: &str, weight_column: Option<usize>) -> anyhow::Result<Option<Fix>> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+
+    let columns = line
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|column| !column.is_empty())
+        .collect::<Vec<_>>();
+    let column = |idx: usize, name: &str| -> anyhow::Result<f64> {
+        let value = columns
+            .get(idx)
+            .ok_or_else(|| anyhow!("Missing {name} (column {})", idx + 1))?;
+        value
+            .parse::<f64>()
+            .with_context(|| format!("Invalid {name} (column {}): {value:?}", idx + 1))
+    };
+
+    let position = DVec3::new(
+        column(0, "latitude")?,
+        column(1, "longitude")?,
+        column(2, "altitude")?,
+    );
+    let weight = match weight_column {
+        Some(weight_column) => {
+            let weight = column(weight_column - 1, "weight")?;
+            if !(weight.is_finite() && weight > 0.) {
+                return Err(anyhow!("Weights must be positive, found {weight}"));
+            }
+            weight
+        }
+        None => 1.,
+    };
+
+    Ok(Some(Fix {
+        position,
+        weight,
+        line: 0,
+        file: 0,
+        epoch: None,
+    }))
+}
+
+/// Parses a position given as `latitude,longitude,altitude`, in degrees and meters.
+pub fn parse_position(text: &str) -> anyhow::Result<DVec3> {
+    let values = text.split(',').map(str::trim).collect::<Vec<_>>();
+    let [latitude, longitude, altitude] = values[..] else {
+        return Err(anyhow!(
+            "Expected latitude, longitude and altitude separated by commas, found {} values",
+            values.len()
+        ));
+    };
+    let value = |value: &str, name: &str| -> anyhow::Result<f64> {
+        value
+            .parse::<f64>()
+            .ok()
+            .filter(|value| value.is_finite())
+            .ok_or_else(|| anyhow!("Invalid {name}: {value:?}"))
+    };
+
+    check_position(DVec3::new(
+        value(latitude, "latitude")?,
+        value(longitude, "longitude")?,
+        value(altitude, "altitude")?,
+    ))
+}
+
+/// Checks that a position in degrees and meters is finite, with its latitude and longitude in
+/// range.
+pub fn check_position(position: DVec3) -> anyhow::Result<DVec3> {
+    if !position.is_finite() {
+        return Err(anyhow!("Positions must be finite, found {position}"));
+    }
+    if position.x.abs() > 90. {
+        return Err(anyhow!("Latitudes must be between -90 and 90 degrees"));
+    }
+    if position.y.abs() > 180. {
+        return Err(anyhow!("Longitudes must be between -180 and 180 degrees"));
+    }
+    Ok(position)
+}
+
+/// Parses a line like [`parse_line`], verifying its checksum first for strict classes.
+fn parse_checked_line(
+    line: &str,
+    strictness: Strictness,
+    verify_hemisphere: bool,
+) -> Result<Option<Sentence>, nmea::Error<'_>> {
+    if strictness == Strictness::Strict {
+        let sentence = parse_nmea_sentence(line)?;
+        let calculated = sentence.calc_checksum();
+        if calculated != sentence.checksum {
+            return Err(nmea::Error::ChecksumMismatch {
+                calculated,
+                found: sentence.checksum,
+            });
+        }
+    }
+    parse_line(line, verify_hemisphere)
+}
+
+/// Parses a line of NMEA input into the sentence it holds, if it is relevant to the fixes. With
+/// `verify_hemisphere`, the signs of the coordinates of GGA sentences are checked against the
+/// hemisphere letters of the raw sentence and corrected to match them.
+pub fn parse_line<'a>(
+    line: &'a str,
+    verify_hemisphere: bool,
+) -> Result<Option<Sentence>, nmea::Error<'a>> {
+    // https://www.sparkfun.com/datasheets/GPS/NMEA%20Reference%20Manual-Rev2.1-Dec07.pdf
+
+    let nmea_line: NmeaSentence<'a> = parse_nmea_sentence(line)?;
+    // Only errors on GGA sentences are reported, since the rest are optional.
+    let sentence = match nmea_line.message_id {
+        SentenceType::GGA => {
+            let data = nmea_line.data;
+            let mut gga_data: GgaData = parse_gga(nmea_line)?;
+            let hemisphere_corrected = verify_hemisphere
+                && correct_hemispheres(data, &mut gga_data.latitude, &mut gga_data.longitude);
+            // The nmea crate parses the altitude and the geoid separation as f32s, which lose the
+            // last digits of precise receivers, or of heights in the tens of kilometers, so they
+            // are re-read from the raw fields. The coordinates are parsed as f64s.
+            let altitude = gga_data
+                .altitude
+                .map(|ele| raw_field(data, 8).unwrap_or(ele as f64));
+            let geoid_separation = gga_data
+                .geoid_separation
+                .map(|separation| raw_field(data, 10).unwrap_or(separation as f64));
+            let position = match (gga_data.latitude, gga_data.longitude, altitude) {
+                (Some(lat), Some(lon), Some(ele)) => Some(DVec3 {
+                    x: lat,
+                    y: lon,
+                    z: ele,
+                }),
+                _ => None,
+            };
+            Sentence::Gga(Gga {
+                time: gga_data.fix_time,
+                position,
+                fix_type: gga_data.fix_type,
+                satellites: gga_data.fix_satellites,
+                hdop: gga_data.hdop,
+                geoid_separation,
+                hemisphere_corrected,
+            })
+        }
+        SentenceType::GSA => match parse_gsa(nmea_line) {
+            Ok(gsa_data) => Sentence::Gsa(Dop {
+                pdop: gsa_data.pdop,
+                hdop: gsa_data.hdop,
+                vdop: gsa_data.vdop,
+            }),
+            Err(_) => return Ok(None),
+        },
+        SentenceType::GST => match epoch::parse_gst(nmea_line.data) {
+            Some(gst) => Sentence::Gst(gst),
+            None => return Ok(None),
+        },
+        SentenceType::RMC => match parse_rmc(nmea_line) {
+            Ok(rmc_data) => Sentence::Rmc(Rmc {
+                time: rmc_data.fix_time,
+                date: rmc_data.fix_date,
+                speed_knots: rmc_data.speed_over_ground,
+            }),
+            Err(_) => return Ok(None),
+        },
+        _ => return Ok(None),
+    };
+    Ok(Some(sentence))
+}
+
+/// Parses a numeric field of the data of a sentence as an f64, given its index.
+fn raw_field(data: &str, index: usize) -> Option<f64> {
+    data.split(',').nth(index)?.parse().ok()
+}
+
+/// Makes the signs of the latitude and longitude parsed from the fields of a GGA sentence match
+/// its hemisphere letters, re-read from the raw fields. Returns whether any of them changed.
+fn correct_hemispheres(
+    data: &str,
+    latitude: &mut Option<f64>,
+    longitude: &mut Option<f64>,
+) -> bool {
+    let fields = data.split(',').collect::<Vec<_>>();
+    let mut corrected = false;
+    for (value, field, negative) in [(latitude, 2, "S"), (longitude, 4, "W")] {
+        let (Some(value), Some(letter)) = (value.as_mut(), fields.get(field)) else {
+            continue;
+        };
+        if *value != 0. && (*value < 0.) != (*letter == negative) {
+            *value = -*value;
+            corrected = true;
+        }
+    }
+    corrected
+}
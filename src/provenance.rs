@@ -0,0 +1,200 @@
+//! Provenance of the results, with `--provenance`: the hashes of the input files and of the
+//! sentences of the fixes the average was computed from, so that anyone holding the raw logs can
+//! check which sentences produced it, with `gpsavg verify-provenance`.
+//!
+//! The fixes of each file are recorded as ranges of lines, both ends included. The GGA sentences
+//! with a position in the ranges are exactly the accepted ones, so the ranges stay short even
+//! though other sentences come between them. The files are read again after the run has parsed
+//! them, as streams, hashing their contents in the same pass as their lines.
+
+use std::{
+    collections::BTreeSet,
+    fs::{self, File},
+    io::{self, BufRead, Read},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    epoch::Sentence,
+    parse::{open_input, parse_line},
+    Fix,
+};
+
+/// Provenance of the results of a run.
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Provenance {
+    pub files: Vec<FileProvenance>,
+    /// SHA-256 of the accepted sentences of all the files in order, each followed by a newline.
+    pub accepted_sha256: String,
+}
+
+/// Provenance of the fixes read from an input file.
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct FileProvenance {
+    /// Path of the file, as given.
+    pub path: String,
+    /// SHA-256 of the contents of the file, compressed if it is.
+    pub sha256: String,
+    /// Ranges of lines holding the sentences of the accepted fixes.
+    pub accepted_lines: Vec<[usize; 2]>,
+}
+
+impl Provenance {
+    /// Hashes the input files and the sentences of the `accepted` fixes, which were read from
+    /// them.
+    pub fn new(input_paths: &[PathBuf], accepted: &[Fix]) -> anyhow::Result<Self> {
+        let mut accepted_sha256 = Sha256::new();
+        let mut files = Vec::with_capacity(input_paths.len());
+        for (file, input_path) in input_paths.iter().enumerate() {
+            let lines = accepted
+                .iter()
+                .filter(|fix| fix.file == file)
+                .map(|fix| fix.line)
+                .collect::<BTreeSet<_>>();
+            let mut accepted_lines = Vec::<[usize; 2]>::new();
+            // Whether the last GGA sentence with a position was accepted.
+            let mut extending = false;
+            let sha256 = for_each_fix_line(input_path, |line_num, line| {
+                if !lines.contains(&line_num) {
+                    extending = false;
+                    return;
+                }
+                accepted_sha256.update(line.as_bytes());
+                accepted_sha256.update(b"\n");
+                match accepted_lines.last_mut() {
+                    Some(range) if extending => range[1] = line_num,
+                    _ => accepted_lines.push([line_num, line_num]),
+                }
+                extending = true;
+            })?;
+            files.push(FileProvenance {
+                path: input_path.display().to_string(),
+                sha256,
+                accepted_lines,
+            });
+        }
+        Ok(Self {
+            files,
+            accepted_sha256: format!("{:x}", accepted_sha256.finalize()),
+        })
+    }
+
+    /// Reads the provenance from results saved with `--format json --provenance`.
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        #[derive(serde::Deserialize)]
+        struct Results {
+            provenance: Option<Provenance>,
+        }
+
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read results file at {}", path.display()))?;
+        let results: Results = serde_json::from_str(&contents)
+            .with_context(|| format!("Failed to parse results file at {}", path.display()))?;
+        results.provenance.ok_or_else(|| {
+            anyhow!(
+                "The results file at {} has no provenance; save it with --provenance",
+                path.display()
+            )
+        })
+    }
+
+    /// Hashes the input files and their accepted sentences again, and returns a description of
+    /// each hash which doesn't match. The files must be given in the same order as in the run.
+    pub fn verify(&self, input_paths: &[PathBuf]) -> anyhow::Result<Vec<String>> {
+        if input_paths.len() != self.files.len() {
+            return Err(anyhow!(
+                "The results were computed from {} input files, but {} were given",
+                self.files.len(),
+                input_paths.len()
+            ));
+        }
+        let mut mismatches = Vec::new();
+        let mut accepted_sha256 = Sha256::new();
+        for (file, input_path) in self.files.iter().zip(input_paths) {
+            let mut ranges = file.accepted_lines.iter().peekable();
+            let sha256 = for_each_fix_line(input_path, |line_num, line| {
+                while ranges.next_if(|range| range[1] < line_num).is_some() {}
+                if ranges.peek().is_some_and(|range| range[0] <= line_num) {
+                    accepted_sha256.update(line.as_bytes());
+                    accepted_sha256.update(b"\n");
+                }
+            })?;
+            if sha256 != file.sha256 {
+                mismatches.push(format!(
+                    "{} doesn't match the SHA-256 of {}",
+                    input_path.display(),
+                    file.path
+                ));
+            }
+        }
+        if format!("{:x}", accepted_sha256.finalize()) != self.accepted_sha256 {
+            mismatches.push(
+                "The accepted sentences don't match the SHA-256 of the ones the results were computed from"
+                    .to_owned(),
+            );
+        }
+        Ok(mismatches)
+    }
+}
+
+/// Calls `f` with the number and the text of each line of an NMEA file holding a GGA sentence with
+/// a position, which are the lines fixes can be read from. Returns the SHA-256 of the contents of
+/// the file, as hexadecimal.
+fn for_each_fix_line(path: &Path, mut f: impl FnMut(usize, &str)) -> anyhow::Result<String> {
+    let context = || format!("Failed to read input file at {}", path.display());
+    let mut file = HashingReader::new(File::open(path).with_context(context)?);
+    let reader = open_input(path, &mut file).with_context(context)?;
+    for (line_num, line) in reader.lines().enumerate() {
+        let line = line.with_context(context)?;
+        let line = line.trim_end();
+        // Correcting the hemisphere doesn't change whether the line is a fix.
+        if matches!(
+            parse_line(line, false),
+            Ok(Some(Sentence::Gga(gga))) if gga.position.is_some()
+        ) {
+            f(line_num + 1, line);
+        }
+    }
+    // The decompression may stop before the end of the file.
+    io::copy(&mut file, &mut io::sink()).with_context(context)?;
+    Ok(file.finish_hex())
+}
+
+/// Returns the SHA-256 of everything read from `reader`, as hexadecimal.
+#[cfg(test)]
+pub(crate) fn hash_reader(reader: impl Read) -> io::Result<String> {
+    let mut reader = HashingReader::new(reader);
+    io::copy(&mut reader, &mut io::sink())?;
+    Ok(reader.finish_hex())
+}
+
+/// Reader which hashes the bytes read through it.
+struct HashingReader<R> {
+    inner: R,
+    sha256: Sha256,
+}
+
+impl<R: Read> HashingReader<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            sha256: Sha256::new(),
+        }
+    }
+
+    /// Returns the SHA-256 of the bytes read, as hexadecimal.
+    fn finish_hex(self) -> String {
+        format!("{:x}", self.sha256.finalize())
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buffer)?;
+        self.sha256.update(&buffer[..read]);
+        Ok(read)
+    }
+}
@@ -0,0 +1,166 @@
+//! Checks of the binary against embedded golden data, run with `gpsavg selftest` where the test
+//! suite can't be, like on an offline field laptop, and to tell whether a binary behaves.
+//!
+//! The golden data is the test asset `golden`, with 300 synthetic GGA sentences of which 3 are
+//! outliers. The expected averages and standard deviations in degrees were computed independently
+//! of this crate, and the rest of the expected values were checked by hand. The test suite runs the checks too.
+
+use glam::DVec3;
+
+use crate::{
+    coords::{format_dms, to_utm},
+    parse::{parse_file, ParseOptions, SentenceClass, Strictness},
+    stats::enu_offsets,
+    Filtered, Options, Summary,
+};
+
+/// The golden NMEA sentences.
+pub const GOLDEN_NMEA: &str = include_str!("../tests/assets/golden");
+/// Number of fixes in the golden data.
+pub const GOLDEN_FIXES: usize = 300;
+/// Lines of the golden data with the outliers.
+pub const GOLDEN_OUTLIER_LINES: [usize; 3] = [51, 151, 251];
+/// Average of the golden fixes, without the outliers.
+pub const GOLDEN_AVERAGE: DVec3 =
+    DVec3::new(37.374790195286195, -6.000736147025814, 39.95117845117845);
+/// Standard deviation of the golden fixes, without the outliers.
+pub const GOLDEN_STD_DEV: DVec3 = DVec3::new(
+    1.6855769106730052e-05,
+    2.0870064186541e-05,
+    1.972121651740533,
+);
+/// Standard deviation of the golden fixes in meters, as (north, east, up), without the outliers.
+pub const GOLDEN_STD_DEV_M: DVec3 =
+    DVec3::new(1.8707438430798475, 1.848533290789512, 1.9721216801477008);
+/// Average of the golden fixes formatted as degrees, minutes and seconds.
+pub const GOLDEN_DMS: &str = "37°22'29.245\"N 6°00'02.650\"W";
+/// Average of the golden fixes formatted in UTM.
+pub const GOLDEN_UTM: &str = "29N 765579.376mE 4140672.111mN";
+
+/// Tolerance of the comparisons with the golden values, in degrees or meters.
+const TOLERANCE: f64 = 1e-9;
+
+/// Outcome of one of the checks.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Check {
+    pub name: &'static str,
+    /// Description of what went wrong, if the check failed.
+    pub failure: Option<String>,
+}
+
+/// Runs every check against the golden data, and returns their outcomes in order. Later checks
+/// are skipped, as failed, if the golden data can't be parsed.
+pub fn run() -> Vec<Check> {
+    let mut checks = Vec::new();
+    let mut check = |name: &'static str, result: Result<(), String>| {
+        checks.push(Check {
+            name,
+            failure: result.err(),
+        });
+    };
+
+    let parsed = match parse_file(GOLDEN_NMEA.as_bytes(), &ParseOptions::default()) {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            check("parsing", Err(err.to_string()));
+            return checks;
+        }
+    };
+    check(
+        "parsing",
+        expect_eq("fixes", parsed.fixes.len(), GOLDEN_FIXES).and(expect_eq(
+            "skipped lines",
+            parsed.rejected,
+            0,
+        )),
+    );
+
+    // The first sentence with a wrong digit, which doesn't match its checksum.
+    let mut corrupted = GOLDEN_NMEA.replacen("3722.", "3723.", 1);
+    corrupted.truncate(corrupted.find('\n').unwrap_or(corrupted.len()));
+    let mut strict = ParseOptions::default();
+    strict.policy.set(SentenceClass::Gga, Strictness::Strict);
+    check(
+        "checksums",
+        match parse_file(corrupted.as_bytes(), &strict) {
+            Ok(_) => Err("a sentence with a wrong checksum was accepted".to_owned()),
+            Err(_) => Ok(()),
+        },
+    );
+
+    let options = Options::default();
+    let filtered = match Filtered::new(&parsed.fixes, &options) {
+        Ok(filtered) => filtered,
+        Err(err) => {
+            check("outlier filter", Err(err.to_string()));
+            return checks;
+        }
+    };
+    let rejected = filtered
+        .rejected
+        .iter()
+        .map(|fix| fix.line)
+        .collect::<Vec<_>>();
+    check(
+        "outlier filter",
+        expect_eq(
+            "lines of the outliers",
+            rejected,
+            GOLDEN_OUTLIER_LINES.to_vec(),
+        ),
+    );
+
+    let summary = Summary::new(&parsed.fixes, filtered, &options);
+    check(
+        "average",
+        expect_close("average", summary.avg, GOLDEN_AVERAGE),
+    );
+    check(
+        "standard deviation",
+        expect_close("standard deviation", summary.std_dev, GOLDEN_STD_DEV),
+    );
+    let offset = enu_offsets(&[GOLDEN_AVERAGE + DVec3::new(0., 0., 1.)], GOLDEN_AVERAGE)[0];
+    check(
+        "conversion to meters",
+        expect_close(
+            "standard deviation in meters",
+            summary.std_dev_m,
+            GOLDEN_STD_DEV_M,
+        )
+        .and(expect_close("offset 1m up", offset, DVec3::new(0., 0., 1.))),
+    );
+
+    check(
+        "formatting",
+        expect_eq(
+            "degrees, minutes and seconds",
+            format_dms(summary.avg.x, summary.avg.y),
+            GOLDEN_DMS.to_owned(),
+        )
+        .and(match to_utm(summary.avg.x, summary.avg.y) {
+            Ok(utm) => expect_eq("UTM", utm.to_string(), GOLDEN_UTM.to_owned()),
+            Err(err) => Err(format!("UTM: {err}")),
+        }),
+    );
+    checks
+}
+
+fn expect_eq<T: PartialEq + std::fmt::Debug>(
+    what: &str,
+    found: T,
+    expected: T,
+) -> Result<(), String> {
+    if found == expected {
+        Ok(())
+    } else {
+        Err(format!("{what}: expected {expected:?}, found {found:?}"))
+    }
+}
+
+fn expect_close(what: &str, found: DVec3, expected: DVec3) -> Result<(), String> {
+    if (found - expected).abs().max_element() <= TOLERANCE {
+        Ok(())
+    } else {
+        Err(format!("{what}: expected {expected:?}, found {found:?}"))
+    }
+}
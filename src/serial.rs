@@ -0,0 +1,208 @@
+//! Capture of NMEA sentences from a GPS device connected to a serial port.
+
+#[cfg(feature = "serial")]
+use std::{
+    io::{BufRead, BufReader, ErrorKind},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "serial")]
+use anyhow::Context;
+
+#[cfg(feature = "serial")]
+use crate::{age::format_duration, parse::parse_file};
+use crate::{
+    epoch::Sentence, parse::parse_line, stats::StreamingStandardError, InputData, InputOptions,
+};
+
+/// Reads from the serial port time out after this long, so that the stop conditions of the capture
+/// are checked even if the device is silent.
+#[cfg(feature = "serial")]
+const READ_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Which serial port the sentences are read from, and when the capture stops. Without a duration,
+/// a count or a target standard error, the capture only stops with Ctrl-C.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SerialOptions {
+    /// Path of the port, like `/dev/ttyUSB0` or `COM3`.
+    pub port: String,
+    pub baud_rate: u32,
+    /// Stop after this number of seconds.
+    pub duration_s: Option<f64>,
+    /// Stop after reading this number of GGA sentences with a position.
+    pub count: Option<usize>,
+    /// Stop once the horizontal standard error of the mean, in meters and corrected for the
+    /// autocorrelation of the fixes, is below this target.
+    pub stop_at_se_m: Option<f64>,
+    /// Don't stop for the target standard error before this number of seconds.
+    pub min_duration_s: Option<f64>,
+    /// Print the number of fixes read so far to stderr.
+    pub progress: bool,
+}
+
+/// Why a capture stopped, besides Ctrl-C or the port closing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StopReason {
+    Duration,
+    Count,
+    StandardError,
+}
+
+/// Fixes read so far by a capture, to decide when it stops.
+#[derive(Clone, Debug)]
+pub struct Capture<'a> {
+    options: &'a SerialOptions,
+    fixes: usize,
+    standard_error: StreamingStandardError,
+}
+
+impl<'a> Capture<'a> {
+    pub fn new(options: &'a SerialOptions) -> Self {
+        Self {
+            options,
+            fixes: 0,
+            standard_error: StreamingStandardError::default(),
+        }
+    }
+
+    /// Records a line read from the port, and returns whether it is a GGA sentence with a
+    /// position.
+    pub fn record(&mut self, line: &str) -> bool {
+        // Correcting the hemisphere doesn't change whether the line is a fix, nor the spread of
+        // the fixes.
+        let Ok(Some(Sentence::Gga(gga))) = parse_line(line.trim_end(), false) else {
+            return false;
+        };
+        let Some(position) = gga.position else {
+            return false;
+        };
+        self.fixes += 1;
+        if self.options.stop_at_se_m.is_some() {
+            self.standard_error.add(position);
+        }
+        true
+    }
+
+    pub fn fixes(&self) -> usize {
+        self.fixes
+    }
+
+    /// Horizontal standard error in meters of the mean of the fixes read so far, if there is a
+    /// target for it.
+    pub fn standard_error_m(&self) -> Option<f64> {
+        self.options
+            .stop_at_se_m
+            .map(|_| self.standard_error.horizontal_m())
+    }
+
+    /// Returns why the capture should stop after `elapsed_s` seconds, if it should. The duration
+    /// stops it even if the target standard error was never reached.
+    pub fn stop_reason(&self, elapsed_s: f64) -> Option<StopReason> {
+        if self
+            .options
+            .duration_s
+            .is_some_and(|duration_s| elapsed_s >= duration_s)
+        {
+            return Some(StopReason::Duration);
+        }
+        if self.options.count.is_some_and(|count| self.fixes >= count) {
+            return Some(StopReason::Count);
+        }
+        let reached = self
+            .options
+            .stop_at_se_m
+            .is_some_and(|target_m| self.standard_error.horizontal_m() < target_m);
+        let long_enough = self
+            .options
+            .min_duration_s
+            .is_none_or(|min_duration_s| elapsed_s >= min_duration_s);
+        (reached && long_enough).then_some(StopReason::StandardError)
+    }
+}
+
+/// Reads NMEA sentences from the serial port until the capture stops, and then parses them and
+/// filters the fixes like [`read_inputs`](crate::read_inputs) does with a file.
+#[cfg(feature = "serial")]
+pub fn read_serial(options: &SerialOptions, input: &InputOptions) -> anyhow::Result<InputData> {
+    let captured = capture(options)?;
+    let parsed = parse_file(captured.as_slice(), &input.parse)
+        .with_context(|| format!("Failed to parse the sentences read from {}", options.port))?;
+    Ok(InputData::new(vec![parsed], captured.len() as u64, input))
+}
+
+#[cfg(not(feature = "serial"))]
+pub fn read_serial(_options: &SerialOptions, _input: &InputOptions) -> anyhow::Result<InputData> {
+    Err(anyhow::anyhow!(
+        "This build of gpsavg has no serial port support; rebuild it with the `serial` feature"
+    ))
+}
+
+/// Reads whole lines from the serial port until the duration or the count of `options` is
+/// reached, or Ctrl-C is pressed, and returns them.
+#[cfg(feature = "serial")]
+fn capture(options: &SerialOptions) -> anyhow::Result<Vec<u8>> {
+    let port = serialport::new(&options.port, options.baud_rate)
+        .timeout(READ_TIMEOUT)
+        .open()
+        .with_context(|| format!("Failed to open serial port {}", options.port))?;
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let handler_stop = Arc::clone(&stop);
+    ctrlc::set_handler(move || handler_stop.store(true, Ordering::SeqCst))
+        .context("Failed to set the Ctrl-C handler")?;
+
+    let start = Instant::now();
+    let mut reader = BufReader::new(port);
+    let mut captured = Vec::new();
+    let mut line = Vec::new();
+    let mut state = Capture::new(options);
+    let mut reason = None;
+    while !stop.load(Ordering::SeqCst) {
+        reason = state.stop_reason(start.elapsed().as_secs_f64());
+        if reason.is_some() {
+            break;
+        }
+        match reader.read_until(b'\n', &mut line) {
+            // The port was closed.
+            Ok(0) => break,
+            Ok(_) => {}
+            // A partial line stays in `line` until the rest of it arrives.
+            Err(err) if err.kind() == ErrorKind::TimedOut => continue,
+            Err(err) => {
+                return Err(err).with_context(|| format!("Failed to read from {}", options.port))
+            }
+        }
+
+        let is_fix = std::str::from_utf8(&line).is_ok_and(|text| state.record(text));
+        if is_fix && options.progress {
+            match state.standard_error_m() {
+                Some(standard_error_m) => eprint!(
+                    "\rFixes read: {}, standard error {standard_error_m:.3}m",
+                    state.fixes()
+                ),
+                None => eprint!("\rFixes read: {}", state.fixes()),
+            }
+        }
+        captured.append(&mut line);
+    }
+    if options.progress {
+        eprintln!();
+    }
+    if let (Some(target_m), Some(standard_error_m)) =
+        (options.stop_at_se_m, state.standard_error_m())
+    {
+        let elapsed = format_duration(start.elapsed().as_secs_f64());
+        if reason == Some(StopReason::StandardError) {
+            eprintln!("Standard error of {standard_error_m:.3}m reached after {elapsed}");
+        } else {
+            eprintln!(
+                "Standard error target of {target_m}m not reached after {elapsed}; it is {standard_error_m:.3}m"
+            );
+        }
+    }
+    Ok(captured)
+}
@@ -0,0 +1,578 @@
+//! Statistics of the positions and filtering of outliers.
+
+use std::convert::Infallible;
+
+use glam::DVec3;
+use map_3d::geodetic2enu;
+
+use crate::{epoch::seconds_between, parse::Fix};
+
+/// Scale factor from the median absolute deviation to the standard deviation of normally
+/// distributed data.
+pub const MAD_TO_STD_DEV: f64 = 1.4826;
+
+/// Maximum number of passes of the outlier filter with `--iterate`.
+pub const MAX_FILTER_PASSES: u32 = 20;
+
+/// Spreads below this fraction of the magnitude of the center (or below this value, for centers
+/// under 1) are considered zero, since the spread of identical values isn't always exactly zero
+/// after rounding.
+pub const ZERO_SPREAD_RATIO: f64 = 1e-12;
+
+/// Returns whether a coordinate has no spread around its center, like when all the fixes are
+/// identical, so that it can't be filtered nor histogrammed.
+pub fn is_zero_spread(center: f64, spread: f64) -> bool {
+    spread <= center.abs().max(1.) * ZERO_SPREAD_RATIO
+}
+
+/// Returns the square of each coordinate. The statistics of the positions are computed on each
+/// axis independently, which these helpers spell out instead of leaving it to the operators of
+/// [`DVec3`].
+pub fn sq_elements(value: DVec3) -> DVec3 {
+    DVec3::new(value.x * value.x, value.y * value.y, value.z * value.z)
+}
+
+/// Returns the square root of each coordinate, like [`sq_elements`].
+pub fn sqrt_elements(value: DVec3) -> DVec3 {
+    DVec3::new(value.x.sqrt(), value.y.sqrt(), value.z.sqrt())
+}
+
+/// Confidence level of the intervals of the average.
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum ConfidenceLevel {
+    #[value(name = "90")]
+    P90,
+    #[value(name = "95")]
+    P95,
+    #[value(name = "99")]
+    P99,
+}
+
+impl ConfidenceLevel {
+    pub fn percent(self) -> u32 {
+        match self {
+            ConfidenceLevel::P90 => 90,
+            ConfidenceLevel::P95 => 95,
+            ConfidenceLevel::P99 => 99,
+        }
+    }
+
+    /// Number of standard errors at each side of the mean of a coordinate which contain its true
+    /// value with this confidence, assuming normally distributed errors and many samples.
+    pub fn z_score(self) -> f64 {
+        match self {
+            ConfidenceLevel::P90 => 1.6449,
+            ConfidenceLevel::P95 => 1.9600,
+            ConfidenceLevel::P99 => 2.5758,
+        }
+    }
+
+    /// Radius, in standard errors of each horizontal coordinate, of the circle around the mean
+    /// which contains the true horizontal position with this confidence. It assumes the same
+    /// normally distributed errors on both coordinates, so that the horizontal error follows a
+    /// Rayleigh distribution.
+    pub fn radius_factor(self) -> f64 {
+        (-2. * (1. - self.percent() as f64 / 100.).ln()).sqrt()
+    }
+}
+
+/// Sum of positions with Neumaier's compensated summation, on each coordinate. It keeps track of
+/// the rounding error of each addition, which adds up when summing millions of nearly equal
+/// coordinates.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CompensatedSum {
+    sum: DVec3,
+    compensation: DVec3,
+}
+
+impl CompensatedSum {
+    pub fn add(&mut self, value: DVec3) {
+        let sum = self.sum + value;
+        // The low digits of the smaller addend are the ones lost.
+        let error = DVec3::select(
+            self.sum.abs().cmpge(value.abs()),
+            (self.sum - sum) + value,
+            (value - sum) + self.sum,
+        );
+        self.compensation += error;
+        self.sum = sum;
+    }
+
+    pub fn total(&self) -> DVec3 {
+        self.sum + self.compensation
+    }
+}
+
+impl std::iter::Sum<DVec3> for CompensatedSum {
+    fn sum<I: Iterator<Item = DVec3>>(iter: I) -> Self {
+        let mut sum = Self::default();
+        iter.for_each(|value| sum.add(value));
+        sum
+    }
+}
+
+/// Returns the sum of the values with Neumaier's compensated summation, like [`CompensatedSum`].
+pub fn compensated_sum(values: impl IntoIterator<Item = f64>) -> f64 {
+    let (mut sum, mut compensation) = (0f64, 0f64);
+    for value in values {
+        let new_sum = sum + value;
+        compensation += if sum.abs() >= value.abs() {
+            (sum - new_sum) + value
+        } else {
+            (value - new_sum) + sum
+        };
+        sum = new_sum;
+    }
+    sum + compensation
+}
+
+/// Mean and variance of positions on each coordinate, computed in a single pass with Welford's
+/// online algorithm. Unlike summing the squares of the positions, it doesn't lose precision when
+/// the positions are far from zero but close to each other, which coordinates always are. The mean
+/// is accumulated with compensated summation, to be as precise as a [`CompensatedSum`].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Welford {
+    count: usize,
+    mean: CompensatedSum,
+    /// Sum of the squared differences from the mean.
+    m2: CompensatedSum,
+}
+
+impl Welford {
+    pub fn add(&mut self, value: DVec3) {
+        self.count += 1;
+        let delta = value - self.mean.total();
+        self.mean.add(delta / self.count as f64);
+        self.m2.add(delta * (value - self.mean.total()));
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Mean of the positions, or NaN if there are none.
+    pub fn mean(&self) -> DVec3 {
+        if self.count == 0 {
+            return DVec3::NAN;
+        }
+        self.mean.total()
+    }
+
+    /// Sample variance of the positions, which is zero for a single position.
+    pub fn variance(&self) -> DVec3 {
+        if self.count < 2 {
+            return DVec3::ZERO;
+        }
+        self.m2.total() / (self.count - 1) as f64
+    }
+
+    pub fn std_dev(&self) -> DVec3 {
+        sqrt_elements(self.variance())
+    }
+
+    /// Combines the statistics of two sets of positions, as if all of them had been added to the
+    /// same accumulator, with the pairwise formula of Chan et al.
+    pub fn merge(&self, other: &Welford) -> Welford {
+        if self.count == 0 || other.count == 0 {
+            return if self.count == 0 { *other } else { *self };
+        }
+        let count = self.count + other.count;
+        let delta = other.mean.total() - self.mean.total();
+        let mut merged = *self;
+        merged.count = count;
+        merged.mean.add(delta * (other.count as f64 / count as f64));
+        merged.m2.add(other.m2.total());
+        merged
+            .m2
+            .add(sq_elements(delta) * (self.count as f64 * other.count as f64 / count as f64));
+        merged
+    }
+
+    /// Returns the statistics of the positions moved by `offset`, which have the same variance.
+    pub fn shifted(&self, offset: DVec3) -> Welford {
+        let mut shifted = *self;
+        shifted.mean.add(offset);
+        shifted
+    }
+}
+
+impl std::iter::Sum<DVec3> for Welford {
+    fn sum<I: Iterator<Item = DVec3>>(iter: I) -> Self {
+        let mut stats = Self::default();
+        iter.for_each(|value| stats.add(value));
+        stats
+    }
+}
+
+/// Returns the component-wise average and standard deviation of the positions, in a single pass
+/// with [`Welford`]. The standard deviation of a single position is zero.
+pub fn mean_and_std_dev(positions: &[DVec3]) -> (DVec3, DVec3) {
+    let stats = positions.iter().copied().sum::<Welford>();
+    (stats.mean(), stats.std_dev())
+}
+
+/// Returns the center and spread of the positions used to filter outliers: the average and
+/// standard deviation, or the median and the scaled median absolute deviation if `robust`.
+pub fn center_and_spread(positions: &[DVec3], robust: bool) -> (DVec3, DVec3) {
+    if robust {
+        let (median, mad) = median_and_mad(positions);
+        (median, mad * MAD_TO_STD_DEV)
+    } else {
+        mean_and_std_dev(positions)
+    }
+}
+
+/// Filters outliers repeatedly, recomputing the center and spread of the remaining fixes before
+/// each pass, until a pass removes no fixes or `max_passes` passes are done. Returns the remaining
+/// fixes and the number of fixes removed in each pass.
+pub fn filter_outliers_iteratively(
+    fixes: &[Fix],
+    cutoff: f64,
+    robust: bool,
+    max_passes: u32,
+) -> (Vec<Fix>, Vec<usize>) {
+    let mut remaining = fixes.to_vec();
+    let mut removed_per_pass = Vec::new();
+
+    for _ in 0..max_passes {
+        let positions = remaining.iter().map(|fix| fix.position).collect::<Vec<_>>();
+        let kept = filter_outliers(&remaining, center_and_spread(&positions, robust), cutoff);
+
+        let removed = remaining.len() - kept.len();
+        removed_per_pass.push(removed);
+        remaining = kept;
+        if removed == 0 {
+            break;
+        }
+    }
+    (remaining, removed_per_pass)
+}
+
+/// Returns the fixes closer than `cutoff` times the spread to the center, on every coordinate.
+/// Coordinates with no spread aren't filtered.
+pub fn filter_outliers(fixes: &[Fix], (center, spread): (DVec3, DVec3), cutoff: f64) -> Vec<Fix> {
+    let within = |x: f64, center: f64, spread: f64| {
+        is_zero_spread(center, spread)
+            || (x > center - cutoff * spread && x < center + cutoff * spread)
+    };
+    fixes
+        .iter()
+        .filter(|fix| {
+            let x = fix.position;
+
+            within(x.x, center.x, spread.x)
+                && within(x.y, center.y, spread.y)
+                && within(x.z, center.z, spread.z)
+        })
+        .copied()
+        .collect()
+}
+
+/// Returns the average of the positions of the fixes, weighted by the weight of each one.
+pub fn weighted_mean(fixes: &[Fix]) -> DVec3 {
+    let total_weight = compensated_sum(fixes.iter().map(|fix| fix.weight));
+    fixes
+        .iter()
+        .map(|fix| fix.position * fix.weight)
+        .sum::<CompensatedSum>()
+        .total()
+        / total_weight
+}
+
+/// Returns the median of the given values, sorting them in the process. For an even number of
+/// values, the average of the two middle ones is returned.
+pub fn median(values: &mut [f64]) -> f64 {
+    values.sort_by(|a, b| a.total_cmp(b));
+
+    let mid = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[mid - 1] + values[mid]) / 2.
+    } else {
+        values[mid]
+    }
+}
+
+/// Number of values above which [`median_and_mad`] finds the medians with
+/// [`median_by_selection`], instead of sorting a copy of each coordinate.
+pub const SELECTION_THRESHOLD: usize = 100_000;
+/// Bins of the histogram built by each pass of [`median_by_selection`].
+const SELECTION_BINS: usize = 4096;
+/// Number of values left in the bin holding the median below which [`median_by_selection`]
+/// collects and sorts them, instead of building another histogram of them.
+const MAX_CANDIDATES: usize = 4096;
+
+/// Returns the median of some values like [`median`], without holding them in memory. `read` must
+/// call its argument with each of the values, reading them again from the start each time it is
+/// called, like a seekable input rewound before each pass. The first pass finds the number and the
+/// range of the values, the next one builds a fine histogram of them, and the last one keeps only
+/// the values in the bin holding the median, building a histogram of that bin instead while it
+/// holds more than [`MAX_CANDIDATES`] of them. The memory used is bounded by [`SELECTION_BINS`]
+/// and [`MAX_CANDIDATES`], whatever the number of values.
+///
+/// The values are binned by their order in [`f64::total_cmp`], so the result is exactly the one
+/// of sorting them, even with NaNs and infinities. For no values, NaN is returned.
+pub fn median_by_selection<E>(
+    mut read: impl FnMut(&mut dyn FnMut(f64)) -> Result<(), E>,
+) -> Result<f64, E> {
+    let (mut count, mut low, mut high) = (0usize, u64::MAX, u64::MIN);
+    read(&mut |value| {
+        let key = order_key(value);
+        count += 1;
+        low = low.min(key);
+        high = high.max(key);
+    })?;
+    let mid = count / 2;
+    Ok(match count {
+        0 => f64::NAN,
+        _ if count.is_multiple_of(2) => {
+            (select_nth(&mut read, count, mid - 1, [low, high])?
+                + select_nth(&mut read, count, mid, [low, high])?)
+                / 2.
+        }
+        _ => select_nth(&mut read, count, mid, [low, high])?,
+    })
+}
+
+/// Returns the `k`-th smallest of the `count` values read by `read`, starting at 0, given the
+/// range of their keys. See [`median_by_selection`].
+fn select_nth<E>(
+    read: &mut impl FnMut(&mut dyn FnMut(f64)) -> Result<(), E>,
+    count: usize,
+    mut k: usize,
+    [mut low, mut high]: [u64; 2],
+) -> Result<f64, E> {
+    // Number of values with a key in `low..=high`, which hold the `k`-th smallest of them.
+    let mut in_range = count;
+    loop {
+        if low == high {
+            return Ok(from_order_key(low));
+        }
+        if in_range <= MAX_CANDIDATES {
+            let mut candidates = Vec::with_capacity(in_range);
+            read(&mut |value| {
+                if (low..=high).contains(&order_key(value)) {
+                    candidates.push(value);
+                }
+            })?;
+            return Ok(*candidates.select_nth_unstable_by(k, f64::total_cmp).1);
+        }
+
+        // The bins are monotonic in the keys, so the values in each bin are exactly those between
+        // its smallest and largest key, which become the next range. The smallest and the largest
+        // keys of the range fall in different bins, so each bin holds fewer values than the range.
+        let width = u128::from(high - low) + 1;
+        let bin = |key: u64| (u128::from(key - low) * SELECTION_BINS as u128 / width) as usize;
+        let mut counts = vec![0; SELECTION_BINS];
+        let mut bounds = vec![[u64::MAX, u64::MIN]; SELECTION_BINS];
+        read(&mut |value| {
+            let key = order_key(value);
+            if (low..=high).contains(&key) {
+                let idx = bin(key);
+                counts[idx] += 1;
+                bounds[idx] = [bounds[idx][0].min(key), bounds[idx][1].max(key)];
+            }
+        })?;
+        let mut idx = 0;
+        while k >= counts[idx] {
+            k -= counts[idx];
+            idx += 1;
+        }
+        in_range = counts[idx];
+        [low, high] = bounds[idx];
+    }
+}
+
+/// Maps a value to an integer, keeping the order of [`f64::total_cmp`].
+fn order_key(value: f64) -> u64 {
+    let bits = value.to_bits();
+    if bits >> 63 == 1 {
+        !bits
+    } else {
+        bits | 1 << 63
+    }
+}
+
+/// Inverse of [`order_key`].
+fn from_order_key(key: u64) -> f64 {
+    f64::from_bits(if key >> 63 == 1 {
+        key & !(1 << 63)
+    } else {
+        !key
+    })
+}
+
+/// Returns the `q` quantile of the given sorted values, interpolating linearly between the two
+/// closest ones.
+pub fn quantile(sorted: &[f64], q: f64) -> f64 {
+    let position = q * (sorted.len() - 1) as f64;
+    let (below, fraction) = (position.floor() as usize, position.fract());
+    match sorted.get(below + 1) {
+        Some(above) => sorted[below] + fraction * (above - sorted[below]),
+        None => sorted[below],
+    }
+}
+
+/// Returns the interquartile range of the given values, the distance between the first and the
+/// third quartiles, sorting them in the process.
+pub fn iqr(values: &mut [f64]) -> f64 {
+    values.sort_by(|a, b| a.total_cmp(b));
+    quantile(values, 0.75) - quantile(values, 0.25)
+}
+
+/// Returns the component-wise median and median absolute deviation of the positions. With more
+/// than [`SELECTION_THRESHOLD`] positions, they are found with [`median_by_selection`].
+pub fn median_and_mad(positions: &[DVec3]) -> (DVec3, DVec3) {
+    let axis = |r_variable: fn(&DVec3) -> f64| {
+        if positions.len() > SELECTION_THRESHOLD {
+            let Ok(center) = median_by_selection(|f| {
+                positions.iter().for_each(|x| f(r_variable(x)));
+                Ok::<_, Infallible>(())
+            });
+            let Ok(mad) = median_by_selection(|f| {
+                positions
+                    .iter()
+                    .for_each(|x| f((r_variable(x) - center).abs()));
+                Ok::<_, Infallible>(())
+            });
+            return (center, mad);
+        }
+        let mut values = positions.iter().map(r_variable).collect::<Vec<_>>();
+        let center = median(&mut values);
+        values.iter_mut().for_each(|x| *x = (*x - center).abs());
+        (center, median(&mut values))
+    };
+
+    let (median_x, mad_x) = axis(|x| x.x);
+    let (median_y, mad_y) = axis(|x| x.y);
+    let (median_z, mad_z) = axis(|x| x.z);
+    (
+        DVec3::new(median_x, median_y, median_z),
+        DVec3::new(mad_x, mad_y, mad_z),
+    )
+}
+
+/// Returns the time in seconds from the first fix to the last one, if there are at least two and
+/// all of them have a time. Each step between consecutive fixes may cross midnight.
+pub fn duration_s(fixes: &[Fix]) -> Option<f64> {
+    let times = fixes.iter().map(Fix::time).collect::<Option<Vec<_>>>()?;
+    if times.len() < 2 {
+        return None;
+    }
+    Some(compensated_sum(
+        times
+            .windows(2)
+            .map(|pair| seconds_between(pair[0], pair[1])),
+    ))
+}
+
+/// Returns the average time in seconds between consecutive fixes, if there are at least two and
+/// all of them have a time.
+pub fn mean_interval_s(fixes: &[Fix]) -> Option<f64> {
+    Some(duration_s(fixes)? / (fixes.len() - 1) as f64)
+}
+
+/// Returns the offsets in meters of the positions from `origin`, on the plane tangent to the
+/// ellipsoid at `origin`, as (north, east, up) to match the order of the coordinates.
+pub fn enu_offsets(positions: &[DVec3], origin: DVec3) -> Vec<DVec3> {
+    positions
+        .iter()
+        .map(|position| {
+            let (east, north, up) = geodetic2enu(
+                position.x.to_radians(),
+                position.y.to_radians(),
+                position.z,
+                origin.x.to_radians(),
+                origin.y.to_radians(),
+                origin.z,
+                map_3d::Ellipsoid::WGS84,
+            );
+            DVec3::new(north, east, up)
+        })
+        .collect()
+}
+
+/// Returns the standard deviation in meters of the positions, as (north, east, up), computed from
+/// their offsets from `origin`, which is usually their average.
+pub fn std_dev_m(positions: &[DVec3], origin: DVec3) -> DVec3 {
+    mean_and_std_dev(&enu_offsets(positions, origin)).1
+}
+
+/// Returns the effective number of independent samples among `n` consecutive ones with a lag-1
+/// autocorrelation of `rho`, as for a first-order autoregressive process: n·(1 - ρ)/(1 + ρ).
+/// Negative or undefined autocorrelations are taken as zero, so that it never exceeds `n`.
+pub fn effective_sample_size(n: usize, rho: f64) -> f64 {
+    let rho = if rho.is_nan() { 0. } else { rho.clamp(0., 1.) };
+    n as f64 * (1. - rho) / (1. + rho)
+}
+
+/// Standard error of the mean of a stream of positions, corrected for the autocorrelation of
+/// consecutive ones, which GPS fixes always have. It is updated in constant time and memory per
+/// position, from the offsets in meters of the positions from the first one.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct StreamingStandardError {
+    origin: Option<DVec3>,
+    moments: Welford,
+    sum: DVec3,
+    /// Sum of the products of consecutive offsets, for the lag-1 autocovariance.
+    lagged: DVec3,
+    first: DVec3,
+    last: DVec3,
+}
+
+impl StreamingStandardError {
+    pub fn add(&mut self, position: DVec3) {
+        let origin = *self.origin.get_or_insert(position);
+        let offset = enu_offsets(&[position], origin)[0];
+        if self.moments.count() == 0 {
+            self.first = offset;
+        } else {
+            self.lagged += offset * self.last;
+        }
+        self.moments.add(offset);
+        self.sum += offset;
+        self.last = offset;
+    }
+
+    pub fn count(&self) -> usize {
+        self.moments.count()
+    }
+
+    /// Lag-1 autocorrelation of the offsets on each axis, as (north, east, up), or NaN on the axes
+    /// without spread.
+    pub fn autocorrelation(&self) -> DVec3 {
+        let n = self.count();
+        if n < 2 {
+            return DVec3::NAN;
+        }
+        let mean = self.moments.mean();
+        // Sum of (x_i - mean)(x_{i-1} - mean) over the consecutive pairs, expanded.
+        let autocovariance = self.lagged - mean * (2. * self.sum - self.first - self.last)
+            + (n - 1) as f64 * sq_elements(mean);
+        autocovariance / (self.moments.variance() * (n - 1) as f64)
+    }
+
+    /// Standard error of the mean in meters on each axis, as (north, east, up), with the effective
+    /// sample size of each axis. Infinite with fewer than 3 positions, too few to estimate the
+    /// autocorrelation.
+    pub fn standard_error_m(&self) -> DVec3 {
+        let n = self.count();
+        if n < 3 {
+            return DVec3::INFINITY;
+        }
+        let rho = self.autocorrelation();
+        let variance = self.moments.variance();
+        let axis = |variance: f64, rho: f64| (variance / effective_sample_size(n, rho)).sqrt();
+        DVec3::new(
+            axis(variance.x, rho.x),
+            axis(variance.y, rho.y),
+            axis(variance.z, rho.z),
+        )
+    }
+
+    /// Horizontal standard error of the mean in meters, combined like
+    /// [`Summary::std_dev_horizontal_m`](crate::Summary::std_dev_horizontal_m).
+    pub fn horizontal_m(&self) -> f64 {
+        let standard_error = self.standard_error_m();
+        standard_error.x.hypot(standard_error.y)
+    }
+}
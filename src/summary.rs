@@ -0,0 +1,395 @@
+//! Statistics of a set of fixes, after filtering outliers.
+
+use anyhow::anyhow;
+use chrono::NaiveDateTime;
+use glam::DVec3;
+use map_3d::geodetic2enu;
+
+use crate::{
+    age::{age_s, last_fix_time},
+    histogram::{BinLayout, Histogram},
+    parse::Fix,
+    stats::{
+        center_and_spread, compensated_sum, enu_offsets, filter_outliers_iteratively,
+        is_zero_spread, median_and_mad, weighted_mean, ConfidenceLevel, Welford,
+    },
+};
+
+/// Options of the outlier filter and the statistics computed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Options {
+    /// Fixes further than `cutoff` times the spread from the center on any coordinate are
+    /// rejected as outliers.
+    pub cutoff: f64,
+    /// Use the median and the median absolute deviation as center and spread, instead of the
+    /// average and the standard deviation.
+    pub robust: bool,
+    /// Maximum number of passes of the outlier filter.
+    pub max_passes: u32,
+    pub bin_layout: BinLayout,
+    /// Compute the average of the filtered fixes weighted by the weight of each one.
+    pub weighted: bool,
+    /// Count the fixes rejected as outliers in the histograms too, separately from the filtered
+    /// ones.
+    pub hist_include_rejected: bool,
+    /// Confidence level of the confidence interval of the average.
+    pub confidence: ConfidenceLevel,
+    /// Sort the fixes before filtering them, so that every sum is computed in the same order and
+    /// the results don't depend on the order of the fixes, not even in the last digit.
+    pub deterministic: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            cutoff: 3.,
+            robust: false,
+            max_passes: 1,
+            bin_layout: BinLayout::Sigma {
+                span: 3.,
+                divisions: 6,
+            },
+            weighted: false,
+            hist_include_rejected: false,
+            confidence: ConfidenceLevel::P95,
+            deterministic: false,
+        }
+    }
+}
+
+/// Fixes remaining after filtering outliers.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Filtered {
+    pub fixes: Vec<Fix>,
+    /// Fixes rejected as outliers.
+    pub rejected: Vec<Fix>,
+    /// Center and spread of all the fixes, used in the first pass of the filter.
+    pub center: DVec3,
+    pub spread: DVec3,
+    pub removed_per_pass: Vec<usize>,
+}
+
+impl Filtered {
+    /// Filters the outliers of `fixes`. Fails if there are no fixes, or if all of them are
+    /// outliers.
+    pub fn new(fixes: &[Fix], options: &Options) -> anyhow::Result<Self> {
+        if fixes.is_empty() {
+            return Err(anyhow!("No valid GPS fixes found in the input"));
+        }
+        let sorted;
+        let fixes = if options.deterministic {
+            sorted = canonical_order(fixes);
+            &sorted
+        } else {
+            fixes
+        };
+        let positions = fixes.iter().map(|fix| fix.position).collect::<Vec<_>>();
+        let (center, spread) = center_and_spread(&positions, options.robust);
+        let (kept, removed_per_pass) =
+            filter_outliers_iteratively(fixes, options.cutoff, options.robust, options.max_passes);
+        if kept.is_empty() {
+            return Err(anyhow!(
+                "All the fixes were filtered as outliers; try a larger --sigma"
+            ));
+        }
+
+        Ok(Self {
+            rejected: rejected_fixes(fixes, &kept),
+            fixes: kept,
+            center,
+            spread,
+            removed_per_pass,
+        })
+    }
+}
+
+/// Returns the fixes missing from `kept`, which must be in the same order as in `fixes`.
+fn rejected_fixes(fixes: &[Fix], kept: &[Fix]) -> Vec<Fix> {
+    let mut kept = kept.iter().peekable();
+    fixes
+        .iter()
+        .filter(|fix| kept.next_if_eq(fix).is_none())
+        .copied()
+        .collect()
+}
+
+/// Returns the fixes sorted by their position and weight, which are all the statistics depend on.
+/// Any permutation of the same fixes gives the same sorted fixes, as far as the statistics can
+/// tell.
+pub fn canonical_order(fixes: &[Fix]) -> Vec<Fix> {
+    let mut sorted = fixes.to_vec();
+    sorted.sort_by(|a, b| {
+        (a.position.x.total_cmp(&b.position.x))
+            .then(a.position.y.total_cmp(&b.position.y))
+            .then(a.position.z.total_cmp(&b.position.z))
+            .then(a.weight.total_cmp(&b.weight))
+    });
+    sorted
+}
+
+/// Returns the average and standard deviation of the filtered fixes from their moments, along with
+/// the value of each coordinate repeated in all of them. The average of a repeated value may
+/// differ from it after rounding, so it is replaced by `value(axis)`, the value of any fix.
+fn averages(moments: &Welford, value: impl Fn(usize) -> f64) -> (DVec3, DVec3, [Option<f64>; 3]) {
+    let (mut avg, mut std_dev) = (moments.mean(), moments.std_dev());
+    let repeated =
+        [0, 1, 2].map(|axis| is_zero_spread(avg[axis], std_dev[axis]).then(|| value(axis)));
+    for (axis, value) in repeated.iter().enumerate() {
+        if let Some(value) = value {
+            avg[axis] = *value;
+            std_dev[axis] = 0.;
+        }
+    }
+    (avg, std_dev, repeated)
+}
+
+/// Statistics of a set of fixes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Summary {
+    pub n: usize,
+    pub n_filtered: usize,
+    pub removed_per_pass: Vec<usize>,
+    /// Average of the filtered fixes.
+    pub avg: DVec3,
+    /// Standard deviation of the filtered fixes.
+    pub std_dev: DVec3,
+    /// Standard deviation of the filtered fixes in meters, as (north, east, up) to match the order
+    /// of the coordinates. It is computed from their offsets from the average in meters.
+    pub std_dev_m: DVec3,
+    pub avg_weighted: Option<DVec3>,
+    /// Median and median absolute deviation of all the fixes, in robust mode.
+    pub median_and_mad: Option<(DVec3, DVec3)>,
+    /// Value of the latitude, longitude and altitude when it is the same in all the filtered
+    /// fixes, as with a frozen receiver output.
+    pub repeated: [Option<f64>; 3],
+    /// Histograms of the latitude, longitude and altitude of the filtered fixes, along with the
+    /// rejected ones if requested. They have no bins for the coordinates with a repeated value,
+    /// whose bins would have no width.
+    pub histograms: [Histogram; 3],
+    /// UTC date and time of the last filtered fix, if its date can be resolved from the RMC
+    /// sentences.
+    pub last_fix_time: Option<NaiveDateTime>,
+    /// Accumulated mean and squared differences of the filtered fixes, and of their offsets in
+    /// meters from the average, which [`Summary::merge`] combines.
+    pub moments: Welford,
+    pub moments_m: Welford,
+    /// Component-wise minimum and maximum of the filtered fixes.
+    pub min: DVec3,
+    pub max: DVec3,
+    /// Sum of the weights of the filtered fixes.
+    pub total_weight: f64,
+}
+
+impl Summary {
+    pub fn new(fixes: &[Fix], filtered: Filtered, options: &Options) -> Self {
+        let positions = fixes.iter().map(|fix| fix.position).collect::<Vec<_>>();
+        let positions_filtered = filtered
+            .fixes
+            .iter()
+            .map(|fix| fix.position)
+            .collect::<Vec<_>>();
+
+        let moments = positions_filtered.iter().copied().sum::<Welford>();
+        let (avg_filtered, std_dev_filtered, repeated) =
+            averages(&moments, |axis| positions_filtered[0][axis]);
+        let moments_m = enu_offsets(&positions_filtered, avg_filtered)
+            .into_iter()
+            .sum::<Welford>();
+
+        let positions_rejected = options.hist_include_rejected.then(|| {
+            filtered
+                .rejected
+                .iter()
+                .map(|fix| fix.position)
+                .collect::<Vec<_>>()
+        });
+        let (center, spread) = (filtered.center, filtered.spread);
+        let histogram = |axis: usize, r_variable: fn(&DVec3) -> f64| {
+            if repeated[axis].is_some() {
+                return Histogram::default();
+            }
+            let edges = options
+                .bin_layout
+                .edges(&positions_filtered, r_variable, (center, spread));
+            let histogram = Histogram::new(&positions_filtered, r_variable, edges);
+            match &positions_rejected {
+                Some(positions_rejected) => histogram.with_rejected(positions_rejected, r_variable),
+                None => histogram,
+            }
+        };
+
+        Self {
+            n: fixes.len(),
+            n_filtered: filtered.fixes.len(),
+            removed_per_pass: filtered.removed_per_pass.clone(),
+            avg: avg_filtered,
+            std_dev: std_dev_filtered,
+            std_dev_m: moments_m.std_dev(),
+            avg_weighted: options.weighted.then(|| weighted_mean(&filtered.fixes)),
+            median_and_mad: options.robust.then(|| median_and_mad(&positions)),
+            repeated,
+            histograms: [
+                histogram(0, |x| x.x),
+                histogram(1, |x| x.y),
+                histogram(2, |x| x.z),
+            ],
+            last_fix_time: last_fix_time(&filtered.fixes),
+            moments,
+            moments_m,
+            min: positions_filtered
+                .iter()
+                .copied()
+                .fold(DVec3::INFINITY, DVec3::min),
+            max: positions_filtered
+                .iter()
+                .copied()
+                .fold(DVec3::NEG_INFINITY, DVec3::max),
+            total_weight: compensated_sum(filtered.fixes.iter().map(|fix| fix.weight)),
+        }
+    }
+
+    /// Combines the summaries of two sets of fixes, like chunks of a log processed on different
+    /// machines, into the summary of all of them. Outliers are filtered on each set separately, so
+    /// the result only matches the summary of the whole data when the filter removes the same
+    /// fixes. Fails if any summary has a median, which can't be merged, or if the bin edges of
+    /// their histograms differ, as they do unless both use the same [`BinLayout::Fixed`].
+    pub fn merge(&self, other: &Summary) -> anyhow::Result<Summary> {
+        if self.median_and_mad.is_some() || other.median_and_mad.is_some() {
+            return Err(anyhow!("The medians of the summaries can't be merged"));
+        }
+        let moments = self.moments.merge(&other.moments);
+        let (avg, std_dev, repeated) = averages(&moments, |axis| {
+            self.repeated[axis].unwrap_or(self.avg[axis])
+        });
+        // The offsets in meters of each summary are from its own average.
+        let moments_m = self
+            .moments_m
+            .merge(
+                &other
+                    .moments_m
+                    .shifted(enu_offsets(&[other.avg], self.avg)[0]),
+            )
+            .shifted(enu_offsets(&[self.avg], avg)[0]);
+        let total_weight = self.total_weight + other.total_weight;
+        let avg_weighted = match (self.avg_weighted, other.avg_weighted) {
+            (Some(avg_weighted), Some(other_avg_weighted)) => Some(
+                (avg_weighted * self.total_weight + other_avg_weighted * other.total_weight)
+                    / total_weight,
+            ),
+            (None, None) => None,
+            _ => return Err(anyhow!("Only one of the summaries has a weighted average")),
+        };
+        let passes = self
+            .removed_per_pass
+            .len()
+            .max(other.removed_per_pass.len());
+        let removed = |summary: &Summary, pass: usize| {
+            summary.removed_per_pass.get(pass).copied().unwrap_or(0)
+        };
+        let [histogram_x, histogram_y, histogram_z] =
+            [0, 1, 2].map(|axis| self.histograms[axis].merge(&other.histograms[axis]));
+
+        Ok(Self {
+            n: self.n + other.n,
+            n_filtered: self.n_filtered + other.n_filtered,
+            removed_per_pass: (0..passes)
+                .map(|pass| removed(self, pass) + removed(other, pass))
+                .collect(),
+            avg,
+            std_dev,
+            std_dev_m: moments_m.std_dev(),
+            avg_weighted,
+            median_and_mad: None,
+            repeated,
+            histograms: [histogram_x?, histogram_y?, histogram_z?],
+            last_fix_time: self.last_fix_time.max(other.last_fix_time),
+            moments,
+            moments_m,
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+            total_weight,
+        })
+    }
+
+    /// Seconds from the last filtered fix to `now`, if the date of the fix can be resolved.
+    pub fn last_fix_age_s(&self, now: NaiveDateTime) -> Option<f64> {
+        self.last_fix_time.map(|time| age_s(time, now))
+    }
+
+    /// Horizontal standard deviation in meters, combining the east and north components.
+    pub fn std_dev_horizontal_m(&self) -> f64 {
+        self.std_dev_m.x.hypot(self.std_dev_m.y)
+    }
+
+    /// Confidence interval of the average in meters, from the standard error of each coordinate:
+    /// the standard deviation divided by the square root of the number of filtered fixes. It
+    /// assumes independent fixes, which consecutive fixes usually aren't, so it is optimistic
+    /// unless they are decimated.
+    pub fn confidence_interval(&self, level: ConfidenceLevel) -> ConfidenceInterval {
+        let std_err = self.std_dev_m / (self.n_filtered as f64).sqrt();
+        let half_width = std_err * level.z_score();
+        // Root mean square of the horizontal standard errors.
+        let std_err_h = (std_err.x.powi(2) + std_err.y.powi(2)) / 2.;
+        ConfidenceInterval {
+            level: level.percent(),
+            east: half_width.y,
+            north: half_width.x,
+            up: half_width.z,
+            horizontal: std_err_h.sqrt() * level.radius_factor(),
+        }
+    }
+
+    /// Offset of the average from `reference`, given as (latitude, longitude, altitude).
+    pub fn reference_error(&self, reference: DVec3) -> ReferenceError {
+        let (east, north, up) = geodetic2enu(
+            self.avg.x.to_radians(),
+            self.avg.y.to_radians(),
+            self.avg.z,
+            reference.x.to_radians(),
+            reference.y.to_radians(),
+            reference.z,
+            map_3d::Ellipsoid::WGS84,
+        );
+        ReferenceError {
+            east,
+            north,
+            up,
+            horizontal: east.hypot(north),
+            distance: DVec3::new(east, north, up).length(),
+        }
+    }
+}
+
+/// Merges the summaries with [`Summary::merge`], failing if there are none.
+impl FromIterator<Summary> for anyhow::Result<Summary> {
+    fn from_iter<I: IntoIterator<Item = Summary>>(iter: I) -> Self {
+        let mut summaries = iter.into_iter();
+        let first = summaries
+            .next()
+            .ok_or_else(|| anyhow!("There are no summaries to merge"))?;
+        summaries.try_fold(first, |merged, summary| merged.merge(&summary))
+    }
+}
+
+/// Half widths of the confidence interval of the average in meters, on each axis, and radius of
+/// the horizontal confidence circle.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct ConfidenceInterval {
+    /// Confidence level, in percent.
+    pub level: u32,
+    pub east: f64,
+    pub north: f64,
+    pub up: f64,
+    pub horizontal: f64,
+}
+
+/// Offset of the average from a reference position, in meters.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct ReferenceError {
+    pub east: f64,
+    pub north: f64,
+    pub up: f64,
+    pub horizontal: f64,
+    /// Distance in 3D space.
+    pub distance: f64,
+}
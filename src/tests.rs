@@ -1,42 +1,3122 @@
-use std::{fs::File, io::BufReader};
+use std::{
+    convert::Infallible,
+    ffi::OsStr,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Cursor, Seek},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
 
 use anyhow::Context;
+use chrono::{NaiveDate, NaiveTime};
+use glam::DVec3;
 
-use crate::parse_file;
+use crate::{
+    age::{format_duration, last_fix_time, parse_duration},
+    batch::{format_results, parse_job_file, run_jobs, Gates},
+    cli::{self, Action},
+    config::{ColorChoice, DigitGrouping, Estimator, Filter},
+    coords::{
+        format_dms, format_rounded, from_utm, parse_dms_angle, representations, to_utm, CoordFormat,
+    },
+    diff::{diff, SavedSummary, SCHEMA_VERSION},
+    epoch::{Dop, Epoch, EpochAssembler, Gga, Reorderer, Rmc, Sentence},
+    explain::{explain, Findings},
+    histogram::{
+        divisions, format_histogram_bars, format_histogram_csv, format_histogram_table, histogram,
+        histogram_bar, AxisHistogram, BinLayout, Histogram, MAX_AUTO_BINS,
+    },
+    jumps::{segments, Event, EventKind, JumpDetection, DEFAULT_MAX_GAP_S},
+    parse::{
+        decimate, merge_ranges, parse_file, parse_line, parse_plain_file, parse_plain_line,
+        parse_position, read_demuxed_inputs, split_tag, Exclusions, Fix, LinePolicy, ParseError,
+        ParseOptions, ParsedFile, SentenceStats, Strictness, DEFAULT_DEDUP_M,
+    },
+    provenance::{hash_reader, Provenance},
+    read_inputs,
+    selftest::{
+        self, GOLDEN_AVERAGE, GOLDEN_DMS, GOLDEN_FIXES, GOLDEN_OUTLIER_LINES, GOLDEN_STD_DEV,
+        GOLDEN_STD_DEV_M, GOLDEN_UTM,
+    },
+    serial::{Capture, SerialOptions, StopReason},
+    stats::{
+        compensated_sum, effective_sample_size, enu_offsets, filter_outliers,
+        filter_outliers_iteratively, iqr, mean_and_std_dev, mean_interval_s, median,
+        median_and_mad, median_by_selection, quantile, sq_elements, sqrt_elements, std_dev_m,
+        weighted_mean, CompensatedSum, ConfidenceLevel, StreamingStandardError, Welford,
+        MAD_TO_STD_DEV, MAX_FILTER_PASSES, SELECTION_THRESHOLD,
+    },
+    timing::Timing,
+    Filtered, HdopFilter, HeightSystem, InputData, InputFormat, InputOptions, Options, Summary,
+    TimeWindow,
+};
 
 #[test]
 fn read_correct_file() {
     let input_path = "tests/assets/1";
     let file = BufReader::new(
-        File::open(&input_path)
+        File::open(input_path)
             .with_context(|| format!("Failed to read input file at {}", input_path))
             .unwrap(),
     );
 
-    let _positions = parse_file(file).unwrap();
+    let _positions = parse_file(file, &ParseOptions::default()).unwrap();
 }
 
 #[test]
 fn read_blank_file() {
     let input_path = "tests/assets/2";
     let file = BufReader::new(
-        File::open(&input_path)
+        File::open(input_path)
             .with_context(|| format!("Failed to read input file at {}", input_path))
             .unwrap(),
     );
 
-    let _positions = parse_file(file).unwrap();
+    let _positions = parse_file(file, &ParseOptions::default()).unwrap();
+}
+
+fn parse_asset(name: &str, options: &ParseOptions) -> Result<ParsedFile, ParseError> {
+    let file = BufReader::new(File::open(format!("tests/assets/{name}")).unwrap());
+    parse_file(file, options)
 }
 
 #[test]
-#[should_panic]
 fn read_broken_file() {
-    let input_path = "tests/assets/1_broken";
+    let error = parse_asset("1_broken", &ParseOptions::default())
+        .err()
+        .unwrap();
+    assert!(matches!(error, ParseError::InvalidSentence { line: 6, .. }));
+    assert_eq!(error.line(), 6);
+    assert!(error
+        .to_string()
+        .starts_with("Failed to parse line 6 of the input file"));
+}
+
+#[test]
+fn read_broken_file_leniently() {
+    let options = ParseOptions {
+        policy: LinePolicy::lenient(),
+        ..ParseOptions::default()
+    };
+    let parsed = parse_asset("1_broken", &options).unwrap();
+    assert_eq!(parsed.rejected, 1);
+    assert_eq!(parsed.fixes.len(), 1);
+    assert_eq!(parsed.fixes[0].line, 48);
+}
+
+fn policy_from_args(args: &[&str]) -> LinePolicy {
+    let Action::Run { config, .. } =
+        action_from_args(&[&["tests/assets/1"], args].concat()).unwrap()
+    else {
+        panic!("expected a run");
+    };
+    config.input.parse.policy
+}
+
+#[test]
+fn strict_gga_and_lenient_other_sentences() {
+    let options = ParseOptions {
+        policy: policy_from_args(&["--strict-for", "gga", "--lenient-for", "other"]),
+        ..ParseOptions::default()
+    };
+
+    // The truncated GSV sentence fails the parsing unless other sentences are lenient.
+    let error = parse_asset("corrupt_gsv", &ParseOptions::default())
+        .err()
+        .unwrap();
+    assert_eq!(error.line(), 11);
+    let parsed = parse_asset("corrupt_gsv", &options).unwrap();
+    assert_eq!(parsed.rejected, 1);
+    assert_eq!(parsed.fixes.len(), 5);
+
+    // The GGA sentence with a wrong checksum is only caught when GGA sentences are strict.
+    let parsed = parse_asset("corrupt_gga", &ParseOptions::default()).unwrap();
+    assert_eq!(parsed.fixes.len(), 5);
+    let error = parse_asset("corrupt_gga", &options).err().unwrap();
+    assert_eq!(error.line(), 9);
+    assert!(error.to_string().contains("Checksum Mismatch"));
+}
+
+#[test]
+fn line_policy_flags() {
+    assert_eq!(policy_from_args(&[]), LinePolicy::default());
+    assert_eq!(policy_from_args(&["--lenient"]), LinePolicy::lenient());
+    assert_eq!(
+        policy_from_args(&["--lenient", "--strict-for", "gga"]),
+        LinePolicy {
+            gga: Strictness::Strict,
+            other: Strictness::Lenient,
+        }
+    );
+    assert_eq!(
+        policy_from_args(&["--lenient-for", "gga,other"]),
+        LinePolicy::lenient()
+    );
+    assert!(action_from_args(&[
+        "tests/assets/1",
+        "--strict-for",
+        "gga",
+        "--lenient-for",
+        "gga"
+    ])
+    .is_err());
+}
+
+#[test]
+fn read_proprietary_sentences_only() {
+    let parsed = parse_asset("proprietary", &ParseOptions::default()).unwrap();
+    assert_eq!(parsed.lines, 5);
+    assert!(parsed.fixes.is_empty());
+    assert_eq!(parsed.rejected, 0);
+}
+
+#[test]
+fn read_crlf_file() {
+    let parsed = parse_asset("crlf", &ParseOptions::default()).unwrap();
+    assert_eq!(parsed.fixes.len(), 4);
+    assert_eq!(parsed.fixes[2].time(), NaiveTime::from_hms_opt(15, 3, 24));
+}
+
+#[test]
+fn read_fixes_without_altitude() {
+    let parsed = parse_asset("no_altitude", &ParseOptions::default()).unwrap();
+    assert_eq!(parsed.fixes.len(), 1);
+    assert_eq!(parsed.fixes[0].line, 3);
+    assert_eq!(parsed.fixes[0].position.z, 36.1);
+}
+
+#[test]
+fn read_nmea_4_11_with_extended_precision() {
+    let parsed = parse_asset("nmea411", &ParseOptions::default()).unwrap();
+    assert_eq!(parsed.fixes.len(), 10);
+    assert_eq!(parsed.rejected, 0);
+    // Each fix moves by 1.3e-6 and 2.1e-6 minutes of arc and by 1mm, far below what an f32
+    // resolves.
+    for (idx, fix) in parsed.fixes.iter().enumerate() {
+        let i = idx as f64;
+        let expected = DVec3::new(
+            37. + (22.4873312 + 1.3e-6 * i) / 60.,
+            -(6. + (0.0441412 + 2.1e-6 * i) / 60.),
+            40.123 + 0.001 * i,
+        );
+        assert!(
+            (fix.position - expected).abs().max_element() < 1e-12,
+            "{} {expected}",
+            fix.position
+        );
+    }
+    let talkers = parsed
+        .sentences
+        .talkers
+        .iter()
+        .map(|(talker, count)| (talker.as_str(), *count))
+        .collect::<Vec<_>>();
+    assert_eq!(talkers, [("GN", 20), ("GQ", 20), ("NQ", 10)]);
+
+    let options = Options::default();
+    let filtered = Filtered::new(&parsed.fixes, &options).unwrap();
+    let summary = Summary::new(&parsed.fixes, filtered, &options);
+    let expected = DVec3::new(
+        37. + (22.4873312 + 1.3e-6 * 4.5) / 60.,
+        -(6. + (0.0441412 + 2.1e-6 * 4.5) / 60.),
+        40.1275,
+    );
+    assert!(
+        (summary.avg - expected).abs().max_element() < 1e-12,
+        "{} {expected}",
+        summary.avg
+    );
+}
+
+#[test]
+fn histogram_default_layout() {
+    let avg = DVec3::new(1., 2., 3.);
+    let std_dev = DVec3::new(1., 0.5, 2.);
+    let layout = BinLayout::Sigma {
+        span: 3.,
+        divisions: 6,
+    };
+
+    let edges = layout.edges(&[], |x| x.y, (avg, std_dev));
+    assert_eq!(edges.len(), 37);
+    assert_eq!(edges.first(), Some(&0.5));
+    assert_eq!(edges.last(), Some(&3.5));
+}
+
+#[test]
+fn histogram_custom_divisions() {
+    let avg = DVec3::ZERO;
+    let std_dev = DVec3::ONE;
+
+    let edges = BinLayout::Sigma {
+        span: 3.,
+        divisions: 1,
+    }
+    .edges(&[], |x| x.x, (avg, std_dev));
+    assert_eq!(edges, vec![-3., -2., -1., 0., 1., 2., 3.]);
+
+    let edges = BinLayout::Sigma {
+        span: 2.,
+        divisions: 10,
+    }
+    .edges(&[], |x| x.x, (avg, std_dev));
+    assert_eq!(divisions(&edges).len(), 40);
+    assert_eq!(edges.last(), Some(&2.));
+}
+
+#[test]
+fn histogram_bins_cover_data_range() {
+    let positions = [0., 1., 2., 3., 4., 10.]
+        .into_iter()
+        .map(|x| DVec3::new(x, 0., 0.))
+        .collect::<Vec<_>>();
+
+    let edges = BinLayout::Count(5).edges(&positions, |x| x.x, (DVec3::ZERO, DVec3::ONE));
+    assert_eq!(edges, vec![0., 2., 4., 6., 8., 10.]);
+    assert_eq!(divisions(&edges).len(), 5);
+
+    let counts = histogram(&positions, |x| x.x, &edges);
+    assert_eq!(counts, vec![2, 2, 1, 0, 1]);
+    assert_eq!(counts.iter().sum::<i32>() as usize, positions.len());
+}
+
+#[test]
+fn quartiles_and_iqr() {
+    assert_eq!(quantile(&[1., 2., 3., 4.], 0.25), 1.75);
+    assert_eq!(quantile(&[1., 2., 3., 4.], 0.5), 2.5);
+    assert_eq!(quantile(&[5.], 0.75), 5.);
+    assert_eq!(iqr(&mut [4., 1., 3., 2.]), 1.5);
+    assert_eq!(iqr(&mut [7., 7., 7.]), 0.);
+}
+
+/// Samples of a standard normal distribution, with the Box–Muller transform, and of a standard
+/// Cauchy distribution, whose tails are heavy enough for it to have no standard deviation.
+fn synthetic_samples(n: usize) -> (Vec<DVec3>, Vec<DVec3>) {
+    let mut state = 0x9e37_79b9_7f4a_7c15_u64;
+    let mut uniform = || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        ((state >> 11) as f64 + 0.5) / (1u64 << 53) as f64
+    };
+    (0..n)
+        .map(|_| {
+            let (u, v) = (uniform(), uniform());
+            let normal = (-2. * u.ln()).sqrt() * (std::f64::consts::TAU * v).cos();
+            let cauchy = (std::f64::consts::PI * (v - 0.5)).tan();
+            (DVec3::splat(normal), DVec3::splat(cauchy))
+        })
+        .unzip()
+}
+
+#[test]
+fn freedman_diaconis_bins() {
+    let (normal, cauchy) = synthetic_samples(1000);
+    let spread = (DVec3::ZERO, DVec3::ONE);
+
+    // About 2·1.35σ·1000^(-1/3) = 0.27σ wide, over a range of about 6.5σ.
+    let edges = BinLayout::Auto.edges(&normal, |x| x.x, spread);
+    let width = edges[1] - edges[0];
+    assert!((0.22..0.32).contains(&width), "{width}");
+    assert!(
+        (15..=40).contains(&divisions(&edges).len()),
+        "{}",
+        edges.len()
+    );
+    let counts = histogram(&normal, |x| x.x, &edges);
+    assert_eq!(counts.iter().sum::<i32>(), 1000);
+
+    // The range of the Cauchy samples is thousands of times their IQR, so the number of bins is
+    // capped, while they still cover all the samples.
+    let edges = BinLayout::Auto.edges(&cauchy, |x| x.x, spread);
+    assert_eq!(divisions(&edges).len(), MAX_AUTO_BINS as usize);
+    let counts = histogram(&cauchy, |x| x.x, &edges);
+    assert_eq!(counts.iter().sum::<i32>(), 1000);
+
+    // Samples concentrated in a single value, with a few apart, have no IQR.
+    let mut concentrated = vec![DVec3::ZERO; 50];
+    concentrated.push(DVec3::ONE);
+    let edges = BinLayout::Auto.edges(&concentrated, |x| x.x, spread);
+    assert_eq!(divisions(&edges).len(), MAX_AUTO_BINS as usize);
+
+    assert_eq!(
+        action_from_args(&["tests/assets/1", "--bins", "auto"])
+            .map(|action| match action {
+                Action::Run { config, .. } => config.options.bin_layout,
+                _ => panic!("expected a run"),
+            })
+            .unwrap(),
+        BinLayout::Auto
+    );
+    assert!(action_from_args(&["tests/assets/1", "--bins", "0"]).is_err());
+    assert!(action_from_args(&["tests/assets/1", "--bins", "many"]).is_err());
+}
+
+#[test]
+fn median_odd_and_even() {
+    assert_eq!(median(&mut [3., 1., 2.]), 2.);
+    assert_eq!(median(&mut [4., 1., 3., 2.]), 2.5);
+    assert_eq!(median(&mut [5.]), 5.);
+}
+
+#[test]
+fn median_by_selection_matches_sorting() {
+    let naive = |values: &[f64]| median(&mut values.to_vec());
+    let selected = |values: &[f64]| {
+        let Ok(median) = median_by_selection(|f| {
+            values.iter().for_each(|value| f(*value));
+            Ok::<_, Infallible>(())
+        });
+        median
+    };
+    let same = |a: f64, b: f64| a.total_cmp(&b).is_eq();
+    for values in [
+        &[3., 1., 2.][..],
+        &[4., 1., 3., 2.],
+        &[5.],
+        &[2., 2., 2., -1.],
+        &[1., f64::NAN, 2.],
+        &[-f64::NAN, 1., f64::INFINITY, 2.],
+        &[-1e308, 1e308, 0.],
+        &[-0., 0., 5e-324],
+    ] {
+        assert!(same(selected(values), naive(values)), "{values:?}");
+    }
+    assert!(selected(&[]).is_nan());
+
+    // Generated values which need several histograms: a narrow cluster with many ties, next to a
+    // few values far away, which put the whole cluster in a single bin of the first one.
+    let mut values = scattered_fixes(150_000)
+        .iter()
+        .map(|fix| fix.position.x)
+        .collect::<Vec<_>>();
+    values[..40_000].fill(37.3748);
+    values.extend([1e6, -1e6, 5e5, f64::NAN]);
+    for len in [values.len(), values.len() - 1, values.len() - 2] {
+        let values = &values[..len];
+        let center = naive(values);
+        assert!(same(selected(values), center));
+        let deviations = values
+            .iter()
+            .map(|value| (value - center).abs())
+            .collect::<Vec<_>>();
+        assert!(same(selected(&deviations), naive(&deviations)));
+    }
+
+    // From a seekable input, rewound before each pass, which is read a few times.
+    let heights = scattered_fixes(20_001)
+        .iter()
+        .map(|fix| fix.position.z)
+        .collect::<Vec<_>>();
+    let mut input = Cursor::new(
+        heights
+            .iter()
+            .map(|height| format!("{height}\n"))
+            .collect::<String>(),
+    );
+    let mut passes = 0;
+    let from_input = median_by_selection(|f| {
+        passes += 1;
+        input.rewind()?;
+        for line in (&mut input).lines() {
+            f(line?.parse().map_err(io::Error::other)?);
+        }
+        Ok::<_, io::Error>(())
+    })
+    .unwrap();
+    assert_eq!(from_input, naive(&heights));
+    assert!(passes <= 4, "{passes}");
+
+    // Above the threshold, the median and the MAD are selected.
+    let positions = scattered_fixes(SELECTION_THRESHOLD + 1)
+        .iter()
+        .map(|fix| fix.position)
+        .collect::<Vec<_>>();
+    let axis = |r_variable: fn(&DVec3) -> f64| {
+        let mut values = positions.iter().map(r_variable).collect::<Vec<_>>();
+        let center = median(&mut values);
+        values.iter_mut().for_each(|x| *x = (*x - center).abs());
+        (center, median(&mut values))
+    };
+    let (center, mad) = median_and_mad(&positions);
+    assert_eq!((center.x, mad.x), axis(|x| x.x));
+    assert_eq!((center.y, mad.y), axis(|x| x.y));
+    assert_eq!((center.z, mad.z), axis(|x| x.z));
+}
+
+#[test]
+fn median_and_mad_ignore_outlier() {
+    let positions = [1., 2., 3., 4., 1000.]
+        .into_iter()
+        .map(|x| DVec3::new(x, -x, 2. * x))
+        .collect::<Vec<_>>();
+
+    let (median, mad) = median_and_mad(&positions);
+    assert_eq!(median, DVec3::new(3., -3., 6.));
+    // Absolute deviations are [2, 1, 0, 1, 997].
+    assert_eq!(mad, DVec3::new(1., 1., 2.));
+}
+
+#[test]
+fn timing_fields() {
+    let start = Instant::now();
+    let instants = [0, 3, 4, 4, 6, 7].map(|ms| start + Duration::from_millis(ms));
+    let timing = serde_json::to_value(Timing::new(instants, 1000, 50_000)).unwrap();
+
+    for field in [
+        "parsing_s",
+        "filtering_s",
+        "provenance_s",
+        "statistics_s",
+        "output_s",
+        "lines_per_s",
+        "mb_per_s",
+    ] {
+        let value = timing[field].as_f64().unwrap();
+        assert!(value >= 0., "{field} is negative");
+    }
+}
+
+#[test]
+fn read_weighted_plain_file() {
+    let input_path = "tests/assets/weighted";
+    let open = || {
+        BufReader::new(
+            File::open(input_path)
+                .with_context(|| format!("Failed to read input file at {}", input_path))
+                .unwrap(),
+        )
+    };
+
+    let weighted = parse_plain_file(open(), Some(4), &ParseOptions::default())
+        .unwrap()
+        .fixes;
+    let unweighted = parse_plain_file(open(), None, &ParseOptions::default())
+        .unwrap()
+        .fixes;
+    assert_eq!(weighted.len(), 9);
+    assert!(unweighted.iter().all(|fix| fix.weight == 1.));
+
+    let cluster = weighted_mean(&weighted[..8]);
+    let offset_weighted = (weighted_mean(&weighted) - cluster).abs();
+    let offset_unweighted = (weighted_mean(&unweighted) - cluster).abs();
+    assert!(offset_weighted.z < 1e-3);
+    assert!(offset_unweighted.z > 0.5);
+    assert!(offset_weighted.x * 100. < offset_unweighted.x);
+}
+
+#[test]
+fn reject_non_positive_weight() {
+    let err = parse_plain_line("1, 2, 3, 0", Some(4)).unwrap_err();
+    assert!(err.to_string().contains("positive"));
+    assert!(parse_plain_line("1, 2, 3, -1.5", Some(4)).is_err());
+    assert!(parse_plain_line("1, 2, 3", Some(4)).is_err());
+}
+
+/// Returns fixes from an evenly spread cluster of 100 points, followed by a wild outlier which
+/// inflates the standard deviation, masking two other outliers.
+fn masked_outlier_fixes() -> Vec<Fix> {
+    let base = DVec3::new(40., -3., 600.);
+    let scale = DVec3::new(1e-5, 1e-5, 1.);
+    let cluster = (0..100).map(|i| {
+        let t = |k: i32| ((i * k) % 100 - 50) as f64 / 50.;
+        base + DVec3::new(t(37), t(53), t(71)) * scale
+    });
+    let outliers = [1000., 50., -50.]
+        .into_iter()
+        .map(|offset| base + DVec3::splat(offset) * scale);
+
+    cluster
+        .chain(outliers)
+        .enumerate()
+        .map(|(line, position)| Fix {
+            position,
+            weight: 1.,
+            line,
+            file: 0,
+            epoch: None,
+        })
+        .collect()
+}
+
+#[test]
+fn robust_filter_removes_masked_outliers() {
+    let fixes = masked_outlier_fixes();
+    let positions = fixes.iter().map(|fix| fix.position).collect::<Vec<_>>();
+
+    let (avg, std_dev) = mean_and_std_dev(&positions);
+    let classic = filter_outliers(&fixes, (avg, std_dev), 3.);
+    assert_eq!(classic.len(), 102);
+
+    let (median, mad) = median_and_mad(&positions);
+    let robust = filter_outliers(&fixes, (median, mad * MAD_TO_STD_DEV), 3.);
+    assert_eq!(robust.len(), 100);
+    assert_eq!(robust, fixes[..100]);
+}
+
+#[test]
+fn iterative_filter_removes_masked_outliers() {
+    let fixes = masked_outlier_fixes();
+
+    let (single_pass, removed) = filter_outliers_iteratively(&fixes, 3., false, 1);
+    assert_eq!(single_pass.len(), 102);
+    assert_eq!(removed, vec![1]);
+
+    let (iterated, removed) = filter_outliers_iteratively(&fixes, 3., false, MAX_FILTER_PASSES);
+    assert_eq!(iterated, fixes[..100]);
+    assert_eq!(removed, vec![1, 2, 0]);
+}
+
+fn time(hms: &str) -> Option<NaiveTime> {
+    Some(NaiveTime::parse_from_str(hms, "%H:%M:%S%.f").unwrap())
+}
+
+fn gga(hms: &str) -> Sentence {
+    Sentence::Gga(Gga {
+        time: time(hms),
+        position: Some(DVec3::ZERO),
+        fix_type: None,
+        satellites: None,
+        hdop: None,
+        geoid_separation: None,
+        hemisphere_corrected: false,
+    })
+}
+
+fn rmc(hms: &str, speed_knots: f32) -> Sentence {
+    Sentence::Rmc(Rmc {
+        time: time(hms),
+        date: None,
+        speed_knots: Some(speed_knots),
+    })
+}
+
+fn gsa(pdop: f32) -> Sentence {
+    Sentence::Gsa(Dop {
+        pdop: Some(pdop),
+        hdop: None,
+        vdop: None,
+    })
+}
+
+/// Feeds the sentences to an assembler, returning the epochs and the number of dropped sentences.
+fn assemble(staleness: f64, sentences: &[Sentence]) -> (Vec<Epoch>, usize) {
+    let mut assembler = EpochAssembler::new(staleness);
+    let mut epochs = sentences
+        .iter()
+        .enumerate()
+        .filter_map(|(line, sentence)| assembler.push(*sentence, line + 1))
+        .collect::<Vec<_>>();
+    epochs.extend(assembler.finish());
+    (epochs, assembler.dropped())
+}
+
+#[test]
+fn epoch_prefers_same_time() {
+    let (epochs, dropped) = assemble(
+        1.,
+        &[
+            gga("12:00:01"),
+            rmc("12:00:00.5", 1.),
+            rmc("12:00:01", 2.),
+            rmc("12:00:00.8", 3.),
+        ],
+    );
+
+    assert_eq!(epochs.len(), 1);
+    assert_eq!(epochs[0].rmc.unwrap().speed_knots, Some(2.));
+    assert_eq!(dropped, 1);
+}
+
+#[test]
+fn epoch_waits_for_newer_sentences() {
+    let (epochs, dropped) = assemble(
+        1.,
+        &[
+            gga("12:00:01"),
+            rmc("12:00:02", 2.),
+            gga("12:00:02"),
+            gga("12:00:03"),
+        ],
+    );
+
+    assert_eq!(epochs.len(), 3);
+    assert_eq!(epochs[0].rmc, None);
+    assert_eq!(epochs[1].rmc.unwrap().speed_knots, Some(2.));
+    assert_eq!(epochs[1].line, 3);
+    assert_eq!(epochs[2].rmc, None);
+    assert_eq!(dropped, 0);
+}
+
+#[test]
+fn epoch_sentences_ahead_of_fixes() {
+    let (epochs, dropped) = assemble(
+        1.,
+        &[
+            rmc("12:00:01", 1.),
+            gga("12:00:01"),
+            rmc("12:00:02", 2.),
+            gga("12:00:01"),
+            rmc("12:00:03", 3.),
+            gga("12:00:02"),
+        ],
+    );
+
+    let speeds = epochs
+        .iter()
+        .map(|epoch| epoch.rmc.map(|rmc| rmc.speed_knots.unwrap()))
+        .collect::<Vec<_>>();
+    assert_eq!(speeds, vec![Some(1.), None, Some(2.)]);
+    assert_eq!(dropped, 1);
+}
+
+#[test]
+fn epoch_staleness_limit() {
+    let sentences = [gga("12:00:10"), rmc("12:00:07", 1.), gga("12:00:11")];
+
+    let (epochs, dropped) = assemble(1., &sentences);
+    assert!(epochs.iter().all(|epoch| epoch.rmc.is_none()));
+    assert_eq!(dropped, 1);
+
+    let (epochs, dropped) = assemble(5., &sentences);
+    assert_eq!(epochs[0].rmc.unwrap().speed_knots, Some(1.));
+    assert_eq!(dropped, 0);
+}
+
+#[test]
+fn epoch_untimestamped_sentences() {
+    let (epochs, dropped) = assemble(
+        1.,
+        &[
+            gsa(1.),
+            gga("12:00:01"),
+            gsa(2.),
+            gga("12:00:02"),
+            gsa(3.),
+            gsa(4.),
+        ],
+    );
+
+    let pdops = epochs
+        .iter()
+        .map(|epoch| epoch.dop.unwrap().pdop.unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(pdops, vec![1., 3.]);
+    assert_eq!(dropped, 2);
+}
+
+#[test]
+fn epoch_across_midnight() {
+    let (epochs, _) = assemble(
+        1.,
+        &[gga("23:59:59.9"), rmc("00:00:00", 1.), gga("00:00:00")],
+    );
+
+    assert_eq!(epochs[0].rmc, None);
+    assert_eq!(epochs[1].rmc.unwrap().speed_knots, Some(1.));
+}
+
+/// Feeds the sentences to a reorderer, returning the lines of the sentences released by each one,
+/// followed by those released at the end, and the number of stragglers.
+fn reorder(window_s: f64, sentences: &[Sentence]) -> (Vec<Vec<usize>>, usize) {
+    let lines = |released: Vec<(Sentence, usize)>| {
+        released
+            .into_iter()
+            .map(|(sentence, line)| {
+                assert_eq!(sentence, sentences[line - 1]);
+                line
+            })
+            .collect::<Vec<_>>()
+    };
+    let mut reorderer = Reorderer::new(window_s);
+    let mut released = sentences
+        .iter()
+        .enumerate()
+        .map(|(idx, sentence)| lines(reorderer.push(*sentence, idx + 1)))
+        .collect::<Vec<_>>();
+    released.push(lines(reorderer.finish()));
+    (released, reorderer.stragglers())
+}
+
+#[test]
+fn reorder_bursts() {
+    let sentences = [
+        gga("10:00:00"),
+        gsa(1.),
+        rmc("10:00:00", 1.),
+        // A burst with the second fix after the third, and the RMC sentences swapped.
+        gga("10:00:02"),
+        gsa(3.),
+        rmc("10:00:01", 2.),
+        gga("10:00:01"),
+        gsa(2.),
+        rmc("10:00:02", 3.),
+        gga("10:00:03"),
+        gsa(4.),
+    ];
+    let (released, stragglers) = reorder(2., &sentences);
+    assert_eq!(stragglers, 0);
+    // Nothing is released until a fix more than 2s newer than the first one arrives.
+    assert!(released[..9].iter().all(Vec::is_empty));
+    assert_eq!(released[9], [1, 2, 3]);
+    assert_eq!(released[11], [6, 7, 8, 4, 5, 9, 10, 11]);
+
+    let ordered = released
+        .concat()
+        .into_iter()
+        .map(|line| sentences[line - 1])
+        .collect::<Vec<_>>();
+    let (epochs, dropped) = assemble(1., &ordered);
+    assert_eq!(dropped, 0);
+    for (idx, epoch) in epochs.iter().enumerate() {
+        let expected = idx as f32 + 1.;
+        assert_eq!(epoch.gga.time, time(&format!("10:00:0{idx}")));
+        assert_eq!(epoch.dop.unwrap().pdop, Some(expected));
+        if idx < 3 {
+            assert_eq!(epoch.rmc.unwrap().speed_knots, Some(expected));
+        }
+    }
+
+    // Without reordering, the RMC sentence of the second fix is attached to the third one.
+    let (epochs, _) = assemble(1., &sentences);
+    assert_eq!(epochs[1].rmc.unwrap().speed_knots, Some(2.));
+    assert_eq!(epochs[2].gga.time, time("10:00:01"));
+    assert_eq!(epochs[2].rmc, None);
+}
+
+#[test]
+fn reorder_drops_stragglers() {
+    let (released, stragglers) = reorder(
+        1.,
+        &[
+            gsa(1.),
+            gga("23:59:59"),
+            gga("00:00:00"),
+            gga("00:00:01"),
+            gga("00:00:02"),
+            // Older than the fix released last, along with the GSA sentence after it.
+            gga("23:59:59.5"),
+            gsa(2.),
+            // Newer than the fix released last, but older than the window.
+            gga("00:00:00.5"),
+            gsa(3.),
+            rmc("00:00:02", 1.),
+            gga("00:00:03"),
+        ],
+    );
+    assert_eq!(stragglers, 2);
+    assert_eq!(
+        released,
+        [
+            vec![1],
+            vec![],
+            vec![],
+            vec![2],
+            vec![3],
+            vec![],
+            vec![],
+            vec![8],
+            vec![9],
+            vec![],
+            vec![4],
+            vec![5, 10, 11],
+        ]
+    );
+}
+
+#[test]
+fn reorder_window_flag() {
+    let window = |args: &[&str]| match action_from_args(args).unwrap() {
+        Action::Run { config, .. } => config.input.parse.reorder_window_s,
+        _ => panic!("expected a run"),
+    };
+    assert_eq!(window(&["tests/assets/1"]), None);
+    assert_eq!(
+        window(&["tests/assets/1", "--reorder-window", "0.5"]),
+        Some(0.5)
+    );
+    assert_eq!(window(&["--serial", "/dev/ttyUSB0"]), Some(2.));
+    assert_eq!(
+        window(&["--serial", "/dev/ttyUSB0", "--reorder-window", "0"]),
+        Some(0.)
+    );
+    assert!(action_from_args(&["tests/assets/1", "--reorder-window", "-1"]).is_err());
+    let error = action_from_args(&[
+        "tests/assets/weighted",
+        "--input-format",
+        "plain",
+        "--reorder-window",
+        "1",
+    ])
+    .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "--reorder-window requires --input-format nmea"
+    );
+}
+
+#[test]
+fn read_associated_sentences() {
+    let input_path = "tests/assets/1";
     let file = BufReader::new(
-        File::open(&input_path)
+        File::open(input_path)
             .with_context(|| format!("Failed to read input file at {}", input_path))
             .unwrap(),
     );
 
-    let _positions = parse_file(file).unwrap();
+    // In this file, the RMC sentence of each second comes before the GGA sentence of the previous
+    // one, and some GGA sentences are repeated.
+    let parsed = parse_file(file, &ParseOptions::default()).unwrap();
+    let epochs = parsed
+        .fixes
+        .iter()
+        .map(|fix| fix.epoch.unwrap())
+        .collect::<Vec<_>>();
+    assert!(epochs
+        .iter()
+        .filter_map(|epoch| epoch.rmc.map(|rmc| (epoch.gga.time, rmc.time)))
+        .all(|(fix_time, rmc_time)| fix_time == rmc_time));
+    assert!(epochs.iter().filter(|epoch| epoch.rmc.is_some()).count() > epochs.len() * 9 / 10);
+}
+
+#[test]
+fn histogram_bar_scaling() {
+    assert_eq!(histogram_bar(10, 10, 40).chars().count(), 40);
+    assert_eq!(histogram_bar(5, 10, 40).chars().count(), 20);
+    assert_eq!(histogram_bar(1, 1000, 40).chars().count(), 1);
+    assert_eq!(histogram_bar(0, 10, 40), "");
+    assert_eq!(histogram_bar(0, 0, 40), "");
+}
+
+#[test]
+fn run_batch_jobs() {
+    let job_path = Path::new("tests/assets/batch.toml");
+    let jobs = parse_job_file(
+        &fs::read_to_string(job_path).unwrap(),
+        job_path.parent().unwrap(),
+    )
+    .unwrap();
+    assert_eq!(jobs.len(), 3);
+    assert_eq!(jobs[0].inputs, [PathBuf::from("tests/assets/1")]);
+    assert_eq!(jobs[1].options.cutoff, 3.);
+    assert!(jobs[2].options.robust && jobs[2].options.weighted);
+
+    // A failed job doesn't stop the ones after it.
+    let results = run_jobs(&jobs);
+    assert_eq!(results.len(), 3);
+    assert!(results[0].passed());
+    assert!(results[1].outcome.is_err());
+    let weighted = results[2].outcome.as_ref().unwrap();
+    assert_eq!(weighted.summary.n, 9);
+    assert_eq!(weighted.failed_gates.len(), 1);
+
+    let table = format_results(&results);
+    assert!(table.contains("recorded") && table.contains("pass"));
+    assert!(table.contains("broken: Failed to parse input file at tests/assets/1_broken"));
+}
+
+#[test]
+fn reject_invalid_job_files() {
+    let base_dir = Path::new("tests/assets");
+    assert!(parse_job_file("", base_dir).is_err());
+    assert!(parse_job_file("[[job]]\nname = \"a\"\n", base_dir).is_err());
+    assert!(parse_job_file("[[job]]\ninputs = [\"1\"]\ncutof = 2.0\n", base_dir).is_err());
+    assert!(parse_job_file("[defaults]\ninputs = [\"1\"]\n[[job]]\n", base_dir).is_err());
+    assert!(parse_job_file(
+        "[[job]]\nname = \"a\"\ninputs = [\"1\"]\n[[job]]\nname = \"a\"\ninputs = [\"2\"]\n",
+        base_dir
+    )
+    .is_err());
+    assert!(parse_job_file("[[job]]\ninputs = [\"1\"]\nweight_column = 4\n", base_dir).is_err());
+    // The input options are checked like those given in the command line.
+    let error = parse_job_file(
+        "[[job]]\ninputs = [\"1\"]\nmax_hdop = 2.0\nhdop_spike_window = -1.0\n",
+        base_dir,
+    )
+    .unwrap_err();
+    assert_eq!(
+        format!("{:#}", error),
+        "Invalid settings for job 'job-1': hdop_spike_window must be a non-negative number of seconds"
+    );
+    let error = |settings: &str| {
+        let contents = format!("[[job]]\nname = \"mark\"\ninputs = [\"1\"]\n{settings}\n");
+        format!("{:#}", parse_job_file(&contents, base_dir).unwrap_err())
+    };
+    assert_eq!(
+        error("reference = [97.0, -6.0, 38.0]"),
+        "Invalid settings for job 'mark': Invalid reference: Latitudes must be between -90 and 90 degrees"
+    );
+    assert_eq!(
+        error("max_std_dev_m = -1.0"),
+        "Invalid settings for job 'mark': max_std_dev_m must be a non-negative number of meters"
+    );
+    assert_eq!(
+        error("weight_column = 4"),
+        "Invalid settings for job 'mark': weight_column requires input_format = \"plain\""
+    );
+}
+
+#[test]
+fn write_batch_output() {
+    let output = std::env::temp_dir().join(format!("gpsavg-batch-{}.txt", std::process::id()));
+    let text = format!(
+        "[[job]]\ninputs = [{:?}]\noutput = {:?}\nreference = [37.3754, -6.0003, 38.0]\nmax_error_m = 0.0\n",
+        fs::canonicalize("tests/assets/1").unwrap(),
+        output,
+    );
+    let jobs = parse_job_file(&text, Path::new("tests/assets")).unwrap();
+    let results = run_jobs(&jobs);
+
+    let outcome = results[0].outcome.as_ref().unwrap();
+    assert!(outcome.error_m.unwrap() > 0.);
+    assert!(!outcome.passed());
+    let written = fs::read_to_string(&output).unwrap();
+    fs::remove_file(&output).unwrap();
+    let avg = outcome.summary.avg;
+    assert_eq!(written, format!("{}, {}, {}\n", avg.x, avg.y, avg.z));
+}
+
+#[test]
+fn time_window_wraps_around_midnight() {
+    let time = |time: &str| NaiveTime::parse_from_str(time, "%H:%M:%S").unwrap();
+    let window = TimeWindow {
+        from: Some(time("23:00:00")),
+        to: Some(time("01:00:00")),
+    };
+    assert!(window.contains(time("23:30:00")));
+    assert!(window.contains(time("01:00:00")));
+    assert!(!window.contains(time("12:00:00")));
+
+    let window = TimeWindow {
+        from: Some(time("12:00:00")),
+        to: None,
+    };
+    assert!(window.contains(time("23:59:59")));
+    assert!(!window.contains(time("11:59:59")));
+}
+
+#[test]
+fn read_time_window() {
+    let time = |time: &str| NaiveTime::parse_from_str(time, "%H:%M:%S").unwrap();
+    let mut options = InputOptions {
+        window: Some(TimeWindow {
+            from: Some(time("15:05:00")),
+            to: Some(time("15:06:00")),
+        }),
+        ..InputOptions::default()
+    };
+    let data = read_inputs(&[PathBuf::from("tests/assets/1")], &options).unwrap();
+    assert_eq!(data.fixes.len() + data.outside_window, 277);
+    assert!(data
+        .fixes
+        .iter()
+        .all(|fix| fix.time().unwrap() >= time("15:05:00")
+            && fix.time().unwrap() <= time("15:06:00")));
+
+    options.format = InputFormat::Plain;
+    assert!(read_inputs(&[PathBuf::from("tests/assets/weighted")], &options).is_err());
+}
+
+#[test]
+fn parse_reference_positions() {
+    assert_eq!(
+        parse_position("37.3754, -6.0003, 38").unwrap(),
+        DVec3::new(37.3754, -6.0003, 38.)
+    );
+    assert!(parse_position("37.3754,-6.0003").is_err());
+    assert!(parse_position("37.3754,-6.0003,38,1").is_err());
+    assert!(parse_position("37.3754,west,38").is_err());
+    assert!(parse_position("97.3754,-6.0003,38").is_err());
+    assert!(parse_position("37.3754,NaN,38").is_err());
+}
+
+#[test]
+fn error_from_reference() {
+    let fixes = [
+        DVec3::new(37.37539, -6.00031, 37.),
+        DVec3::new(37.3754, -6.0003, 38.),
+        DVec3::new(37.37541, -6.00029, 39.),
+    ]
+    .iter()
+    .map(|&position| Fix {
+        position,
+        weight: 1.,
+        line: 0,
+        file: 0,
+        epoch: None,
+    })
+    .collect::<Vec<_>>();
+    let options = Options::default();
+    let summary = Summary::new(&fixes, Filtered::new(&fixes, &options).unwrap(), &options);
+
+    let error = summary.reference_error(DVec3::new(37.3754, -6.0003, 38.));
+    assert!(error.distance.abs() < 1e-6);
+
+    // A thousandth of a degree of latitude is about 111m.
+    let error = summary.reference_error(DVec3::new(37.3744, -6.0003, 40.));
+    assert!((error.north - 111.).abs() < 1.);
+    assert!(error.east.abs() < 1e-3);
+    assert!((error.up + 2.).abs() < 0.01);
+    assert!((error.horizontal - error.north).abs() < 1e-3);
+    assert!(error.distance > error.horizontal);
+}
+
+#[test]
+fn command_line_definition() {
+    // Checks the IDs in `requires` and `conflicts_with`, and the other invariants clap only checks
+    // when the arguments are parsed.
+    cli::command().debug_assert();
+}
+
+fn action_from_args(args: &[&str]) -> anyhow::Result<Action> {
+    let matches = cli::command()
+        .try_get_matches_from(std::iter::once("gpsavg").chain(args.iter().copied()))?;
+    cli::action(&matches)
+}
+
+#[test]
+fn dry_run_plan_defaults() {
+    let Action::Run { config, dry_run } =
+        action_from_args(&["tests/assets/1", "--dry-run"]).unwrap()
+    else {
+        panic!("expected a run");
+    };
+    assert!(dry_run);
+
+    let plan = config.plan();
+    assert_eq!(plan.sources.len(), 1);
+    assert!(plan.sources[0].exists);
+    assert_eq!(plan.sources[0].staleness_s, Some(1.));
+    assert_eq!(
+        plan.filters,
+        [Filter::Outliers {
+            center: "average",
+            cutoff_sigma: 3.,
+            max_passes: 1,
+        }]
+    );
+    assert_eq!(
+        plan.estimators,
+        [
+            Estimator::Average,
+            Estimator::StdDev,
+            Estimator::ConfidenceInterval { level: 95 }
+        ]
+    );
+    assert_eq!(plan.outputs.len(), 1);
+}
+
+#[test]
+fn dry_run_plan_overrides() {
+    let Action::Run { config, .. } = action_from_args(&[
+        "tests/assets/1",
+        "tests/assets/missing",
+        "--dry-run",
+        "--sigma",
+        "2.5",
+        "--robust",
+        "--iterate",
+        "--from",
+        "15:00:00",
+        "--reference",
+        "-33.1,151.2,10",
+        "-l",
+        "--bins",
+        "12",
+        "--format",
+        "json",
+        "--timing",
+    ])
+    .unwrap() else {
+        panic!("expected a run");
+    };
+
+    let plan = config.plan();
+    assert!(!plan.sources[1].exists);
+    assert_eq!(
+        plan.filters,
+        [
+            Filter::TimeWindow {
+                from: Some("15:00:00".to_owned()),
+                to: None,
+            },
+            Filter::Outliers {
+                center: "median",
+                cutoff_sigma: 2.5,
+                max_passes: MAX_FILTER_PASSES,
+            },
+        ]
+    );
+    assert!(plan.estimators.contains(&Estimator::MedianAndMad));
+    assert!(plan.estimators.contains(&Estimator::ReferenceError {
+        reference: [-33.1, 151.2, 10.]
+    }));
+    assert!(plan.estimators.contains(&Estimator::Histogram {
+        bin_layout: BinLayout::Count(12),
+        include_rejected: false,
+    }));
+    assert_eq!(plan.outputs[0].contents, "results as JSON");
+    assert_eq!(plan.outputs[1].contents, "timing");
+
+    let json = serde_json::to_value(&plan).unwrap();
+    assert_eq!(json["filters"][1]["cutoff_sigma"], 2.5);
+}
+
+#[test]
+fn reject_invalid_arguments() {
+    assert!(action_from_args(&["tests/assets/1", "--sigma", "0"]).is_err());
+    assert!(action_from_args(&["tests/assets/1", "--weight-column", "4"]).is_err());
+    assert!(action_from_args(&["tests/assets/1", "--reference", "1,2"]).is_err());
+    assert!(matches!(
+        action_from_args(&["batch", "jobs.toml"]).unwrap(),
+        Action::Batch { .. }
+    ));
+}
+
+#[test]
+fn start_excludes_earlier_fixes() {
+    let Action::Run { config, .. } =
+        action_from_args(&["tests/assets/1", "--start", "150500", "--end", "150600.5"]).unwrap()
+    else {
+        panic!("expected a run");
+    };
+    let time = |time: &str| NaiveTime::parse_from_str(time, "%H:%M:%S%.f").unwrap();
+    assert_eq!(
+        config.input.window,
+        Some(TimeWindow {
+            from: Some(time("15:05:00")),
+            to: Some(time("15:06:00.5")),
+        })
+    );
+
+    let all = read_inputs(&config.input_paths, &InputOptions::default()).unwrap();
+    let windowed = read_inputs(&config.input_paths, &config.input).unwrap();
+    let inside = all
+        .fixes
+        .iter()
+        .filter(|fix| fix.time().unwrap() >= time("15:05:00"))
+        .filter(|fix| fix.time().unwrap() <= time("15:06:00.5"))
+        .map(|fix| fix.position)
+        .collect::<Vec<_>>();
+    assert!(windowed.fixes.len() < all.fixes.len());
+    assert_eq!(windowed.fixes.len(), inside.len());
+    let (avg, _) = mean_and_std_dev(
+        &windowed
+            .fixes
+            .iter()
+            .map(|fix| fix.position)
+            .collect::<Vec<_>>(),
+    );
+    assert_eq!(avg, mean_and_std_dev(&inside).0);
+
+    assert!(action_from_args(&["tests/assets/1", "--start", "15:05:00"]).is_err());
+    assert!(
+        action_from_args(&["tests/assets/1", "--start", "150500", "--from", "15:05:00"]).is_err()
+    );
+}
+
+#[test]
+fn decimate_fixes() {
+    let fixes = (0..10)
+        .map(|idx| Fix {
+            position: DVec3::splat(idx as f64),
+            weight: 1.,
+            line: idx + 1,
+            file: 0,
+            epoch: None,
+        })
+        .collect::<Vec<_>>();
+    let lines = |fixes: Vec<Fix>| fixes.iter().map(|fix| fix.line).collect::<Vec<_>>();
+
+    assert_eq!(decimate(fixes.clone(), 1, None), fixes);
+    assert_eq!(lines(decimate(fixes.clone(), 3, None)), [1, 4, 7, 10]);
+    assert_eq!(decimate(fixes.clone(), 1, Some(100)), fixes);
+    assert_eq!(lines(decimate(fixes.clone(), 1, Some(5))), [1, 3, 5, 7, 9]);
+    assert_eq!(lines(decimate(fixes.clone(), 2, Some(2))), [1, 5]);
+}
+
+#[test]
+fn interval_between_decimated_fixes() {
+    let options = InputOptions {
+        every: 10,
+        ..InputOptions::default()
+    };
+    let data = read_inputs(&[PathBuf::from("tests/assets/1")], &options).unwrap();
+    assert_eq!(data.fixes.len(), 28);
+    assert_eq!(data.decimated, 277 - 28);
+    let interval = mean_interval_s(&data.fixes).unwrap();
+    assert!(interval > 5. && interval < 15.);
+
+    assert_eq!(mean_interval_s(&data.fixes[..1]), None);
+}
+
+#[test]
+fn reject_empty_input() {
+    let data = read_inputs(&[PathBuf::from("tests/assets/2")], &InputOptions::default()).unwrap();
+    assert!(data.fixes.is_empty());
+
+    let error = Filtered::new(&data.fixes, &Options::default()).unwrap_err();
+    assert!(error.to_string().contains("No valid GPS fixes"));
+}
+
+#[test]
+fn single_fix() {
+    let fixes = [Fix {
+        position: DVec3::new(37.3754, -6.0003, 38.),
+        weight: 1.,
+        line: 1,
+        file: 0,
+        epoch: None,
+    }];
+    let (avg, std_dev) = mean_and_std_dev(&[fixes[0].position]);
+    assert_eq!(avg, fixes[0].position);
+    assert_eq!(std_dev, DVec3::ZERO);
+
+    let options = Options::default();
+    let summary = Summary::new(&fixes, Filtered::new(&fixes, &options).unwrap(), &options);
+    assert_eq!(summary.n_filtered, 1);
+    assert_eq!(summary.avg, fixes[0].position);
+    assert_eq!(summary.std_dev_horizontal_m(), 0.);
+}
+
+#[test]
+fn reject_all_filtered() {
+    let fixes = [1., 2., 4.]
+        .iter()
+        .map(|&x| Fix {
+            position: DVec3::splat(x),
+            weight: 1.,
+            line: 0,
+            file: 0,
+            epoch: None,
+        })
+        .collect::<Vec<_>>();
+    let options = Options {
+        cutoff: 0.1,
+        ..Options::default()
+    };
+
+    let error = Filtered::new(&fixes, &options).unwrap_err();
+    assert!(error.to_string().contains("try a larger --sigma"));
+}
+
+#[test]
+fn std_dev_in_meters_at_high_latitude() {
+    // Positions spread along the parallel at 70ºN, where a degree of longitude is about a third of
+    // a degree of latitude.
+    let positions = (-10..=10)
+        .map(|idx| DVec3::new(70. + idx as f64 * 0.001, idx as f64 * 0.05, 100.))
+        .collect::<Vec<_>>();
+    let (avg, std_dev) = mean_and_std_dev(&positions);
+
+    // Linearization of the standard deviation in degrees around the average.
+    let (east, north, up) = map_3d::geodetic2enu(
+        (avg.x + std_dev.x).to_radians(),
+        (avg.y + std_dev.y).to_radians(),
+        avg.z + std_dev.z,
+        avg.x.to_radians(),
+        avg.y.to_radians(),
+        avg.z,
+        map_3d::Ellipsoid::WGS84,
+    );
+    let linearized = DVec3::new(north, east, up);
+
+    let std_dev_m = std_dev_m(&positions, avg);
+    // The linearization overstates the spread in latitude by a few percent, and the curvature of
+    // the parallel even gives a negative spread in altitude.
+    assert!((linearized.x - std_dev_m.x) / std_dev_m.x > 0.02);
+    assert!((linearized.y - std_dev_m.y).abs() / std_dev_m.y < 0.01);
+    assert!(linearized.z < 0.);
+    assert!(std_dev_m.z > 5.);
+}
+
+#[test]
+fn exclude_fixes_after_hdop_spikes() {
+    // The position drifts 20m north while the HDOP spikes, and takes 5 seconds to recover.
+    let truth = DVec3::new(37.3748, -6.0007, 40.);
+    let read = |spike_window_s| {
+        let options = InputOptions {
+            hdop: Some(HdopFilter {
+                max_hdop: 2.,
+                spike_window_s,
+            }),
+            ..InputOptions::default()
+        };
+        let data = read_inputs(&[PathBuf::from("tests/assets/hdop_spike")], &options).unwrap();
+        let positions = data
+            .fixes
+            .iter()
+            .map(|fix| fix.position)
+            .collect::<Vec<_>>();
+        let error = (mean_and_std_dev(&positions).0 - truth).abs();
+        (data, error)
+    };
+
+    let (gated, gated_error) = read(None);
+    assert_eq!(gated.high_hdop, 3);
+    assert_eq!(gated.after_hdop_spike, 0);
+    assert_eq!(gated.fixes.len(), 57);
+
+    let (windowed, windowed_error) = read(Some(5.));
+    assert_eq!(windowed.high_hdop, 3);
+    assert_eq!(windowed.after_hdop_spike, 5);
+    assert_eq!(windowed.fixes.len(), 52);
+    assert!(windowed_error.x * 5. < gated_error.x);
+}
+
+#[test]
+fn format_dms_in_all_quadrants() {
+    assert_eq!(
+        format_dms(41.386762, 2.174108),
+        "41°23'12.343\"N 2°10'26.789\"E"
+    );
+    assert_eq!(
+        format_dms(-33.8568, -70.6483),
+        "33°51'24.480\"S 70°38'53.880\"W"
+    );
+    // Seconds which round up to 60 carry into the minutes.
+    assert_eq!(format_dms(10.9999999, 0.), "11°00'00.000\"N 0°00'00.000\"E");
+
+    for (latitude, longitude) in [
+        (41.3867, 2.1741),
+        (-33.8568, 151.2153),
+        (-22.9068, -43.1729),
+        (37.3748, -6.0007),
+    ] {
+        let formatted = format_dms(latitude, longitude);
+        let (lat, lon) = formatted.split_once(' ').unwrap();
+        assert!((parse_dms_angle(lat).unwrap() - latitude).abs() < 1e-6);
+        assert!((parse_dms_angle(lon).unwrap() - longitude).abs() < 1e-6);
+    }
+    assert!(parse_dms_angle("41°23'12.343\"Q").is_err());
+    assert!(parse_dms_angle("41°63'12.343\"N").is_err());
+}
+
+#[test]
+fn utm_in_all_quadrants() {
+    let utm = to_utm(0., 3.).unwrap();
+    assert_eq!((utm.zone, utm.north), (31, true));
+    assert!((utm.easting - 500e3).abs() < 1e-6 && utm.northing.abs() < 1e-6);
+    // On the central meridian, the northing is the scaled length of the meridian arc.
+    let utm = to_utm(45., -75.).unwrap();
+    assert_eq!(utm.zone, 18);
+    assert!((utm.northing - 4982950.400).abs() < 1e-3);
+
+    for (latitude, longitude, zone, north) in [
+        (41.3867, 2.1741, 31, true),
+        (-33.8568, 151.2153, 56, false),
+        (-22.9068, -43.1729, 23, false),
+        (37.3748, -6.0007, 29, true),
+    ] {
+        let utm = to_utm(latitude, longitude).unwrap();
+        assert_eq!((utm.zone, utm.north), (zone, north));
+        assert!(utm.easting > 100e3 && utm.easting < 900e3);
+        let (lat, lon) = from_utm(utm);
+        assert!((lat - latitude).abs() < 1e-8 && (lon - longitude).abs() < 1e-8);
+    }
+    assert!(to_utm(85., 0.).is_err());
+}
+
+#[test]
+fn flag_mismatched_sentence_rates() {
+    let data = read_inputs(
+        &[PathBuf::from("tests/assets/mixed_rate")],
+        &InputOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(data.fixes.len(), 11);
+    assert!((data.sentences.duration_s - 10.).abs() < 1e-9);
+
+    let rates = data.sentences.rates().unwrap();
+    assert!((rates["GGA"] - 1.).abs() < 0.2);
+    assert!((rates["RMC"] - 10.).abs() < 0.2);
+
+    let mismatches = data.sentences.mismatches();
+    assert_eq!(mismatches.len(), 1);
+    assert_eq!(mismatches[0].0, "RMC");
+    assert!(mismatches[0].1 > SentenceStats::MISMATCH_RATIO);
+
+    // Proprietary sentences are not compared, and GGA and RMC match in the recorded file.
+    let recorded =
+        read_inputs(&[PathBuf::from("tests/assets/1")], &InputOptions::default()).unwrap();
+    assert!(recorded.sentences.counts.contains_key("PAAG"));
+    assert!(recorded.sentences.mismatches().is_empty());
+}
+
+#[test]
+fn serial_capture_arguments() {
+    let Action::Run { config, .. } = action_from_args(&[
+        "--serial",
+        "/dev/ttyUSB0",
+        "--baud",
+        "4800",
+        "--duration",
+        "300",
+        "--progress",
+    ])
+    .unwrap() else {
+        panic!("expected a run");
+    };
+    assert!(config.input_paths.is_empty());
+    assert_eq!(
+        config.serial,
+        Some(SerialOptions {
+            port: "/dev/ttyUSB0".to_owned(),
+            baud_rate: 4800,
+            duration_s: Some(300.),
+            count: None,
+            stop_at_se_m: None,
+            min_duration_s: None,
+            progress: true,
+        })
+    );
+    let plan = config.plan();
+    assert_eq!(plan.sources.len(), 1);
+    assert_eq!(plan.sources[0].baud_rate, Some(4800));
+
+    // Input files and serial ports can't be combined, and file reading is the default.
+    assert!(action_from_args(&["tests/assets/1", "--serial", "/dev/ttyUSB0"]).is_err());
+    assert!(action_from_args(&["tests/assets/1", "--baud", "4800"]).is_err());
+    assert!(action_from_args(&["--serial", "/dev/ttyUSB0", "--duration", "0"]).is_err());
+    let Action::Run { config, .. } = action_from_args(&["tests/assets/1"]).unwrap() else {
+        panic!("expected a run");
+    };
+    assert_eq!(config.serial, None);
+}
+
+#[test]
+fn autocorrelated_standard_error() {
+    let noise = scattered_fixes(5_000)
+        .into_iter()
+        .map(|fix| fix.position - DVec3::new(37.3748, -6.0007, 40.))
+        .collect::<Vec<_>>();
+    let stream = |rho: f64| {
+        let mut offset = DVec3::ZERO;
+        let mut stats = StreamingStandardError::default();
+        let mut positions = Vec::new();
+        for noise in &noise {
+            offset = rho * offset + *noise;
+            let position = DVec3::new(37.3748, -6.0007, 40.) + offset;
+            stats.add(position);
+            positions.push(position);
+        }
+        (stats, positions)
+    };
+
+    // Independent fixes have no autocorrelation, and a standard error of σ/√n.
+    let (independent, positions) = stream(0.);
+    assert!(independent.autocorrelation().abs().max_element() < 0.05);
+    let origin = positions[0];
+    let std_dev = std_dev_m(&positions, origin);
+    let naive = std_dev / (positions.len() as f64).sqrt();
+    assert!(
+        ((independent.standard_error_m() - naive) / naive)
+            .abs()
+            .max_element()
+            < 0.1
+    );
+
+    // The streaming autocorrelation matches the one of all the offsets at once.
+    let (correlated, positions) = stream(0.9);
+    let offsets = enu_offsets(&positions, positions[0]);
+    let (mean, _) = mean_and_std_dev(&offsets);
+    let lagged = offsets
+        .windows(2)
+        .map(|pair| (pair[0] - mean) * (pair[1] - mean))
+        .sum::<DVec3>();
+    let squared = offsets
+        .iter()
+        .map(|offset| (*offset - mean) * (*offset - mean))
+        .sum::<DVec3>();
+    let rho = correlated.autocorrelation();
+    assert!((rho - lagged / squared).abs().max_element() < 1e-9, "{rho}");
+    assert!((rho.x - 0.9).abs() < 0.05, "{rho}");
+    // About 19 consecutive fixes are worth a single independent one.
+    let n_eff = effective_sample_size(positions.len(), rho.x);
+    assert!((n_eff - 5_000. * 0.1 / 1.9).abs() < 100., "{n_eff}");
+    assert!(correlated.horizontal_m() > 3. * independent.horizontal_m());
+
+    assert_eq!(effective_sample_size(100, -0.5), 100.);
+    assert_eq!(effective_sample_size(100, f64::NAN), 100.);
+    assert_eq!(effective_sample_size(100, 1.), 0.);
+    let mut few = StreamingStandardError::default();
+    few.add(DVec3::new(37.3748, -6.0007, 40.));
+    few.add(DVec3::new(37.3749, -6.0007, 40.));
+    assert_eq!(few.horizontal_m(), f64::INFINITY);
+}
+
+/// Formats a GGA sentence with the position, `second` seconds after 12:00:00.
+fn gga_line(second: usize, position: DVec3) -> String {
+    let angle = |angle: f64, degree_digits: usize| {
+        let degrees = angle.abs().trunc();
+        format!(
+            "{degrees:0degree_digits$}{:09.6}",
+            (angle.abs() - degrees) * 60.
+        )
+    };
+    let data = format!(
+        "GPGGA,12{:02}{:02}.00,{},{},{},{},1,09,0.90,{:.1},M,47.2,M,,",
+        second / 60,
+        second % 60,
+        angle(position.x, 2),
+        if position.x < 0. { 'S' } else { 'N' },
+        angle(position.y, 3),
+        if position.y < 0. { 'W' } else { 'E' },
+        position.z
+    );
+    let checksum = data.bytes().fold(0, |checksum, byte| checksum ^ byte);
+    format!("${data}*{checksum:02X}")
+}
+
+#[test]
+fn stop_capture_at_standard_error() {
+    // A scripted stream of fixes at 1 Hz, with a horizontal standard error of about 4.1m/√n.
+    let lines = scattered_fixes(1_000)
+        .iter()
+        .enumerate()
+        .map(|(second, fix)| gga_line(second, fix.position))
+        .collect::<Vec<_>>();
+    let drive = |options: &SerialOptions| {
+        let mut capture = Capture::new(options);
+        for (elapsed, line) in lines.iter().enumerate() {
+            if let Some(reason) = capture.stop_reason(elapsed as f64) {
+                return (reason, elapsed, capture.standard_error_m());
+            }
+            assert!(capture.record(line), "{line}");
+        }
+        panic!("the capture didn't stop");
+    };
+    let serial = |stop_at_se_m, min_duration_s| SerialOptions {
+        port: "/dev/ttyUSB0".to_owned(),
+        baud_rate: 9600,
+        duration_s: Some(600.),
+        count: None,
+        stop_at_se_m,
+        min_duration_s,
+        progress: false,
+    };
+
+    let (reason, elapsed, standard_error_m) = drive(&serial(Some(0.25), None));
+    assert_eq!(reason, StopReason::StandardError);
+    assert!((200..350).contains(&elapsed), "{elapsed}");
+    assert!(standard_error_m.unwrap() < 0.25);
+
+    // The target is already reached, but not the minimum duration.
+    let (reason, elapsed, _) = drive(&serial(Some(0.25), Some(400.)));
+    assert_eq!(reason, StopReason::StandardError);
+    assert_eq!(elapsed, 400);
+
+    // An unreachable target falls back to the duration.
+    let (reason, elapsed, standard_error_m) = drive(&serial(Some(0.01), None));
+    assert_eq!(reason, StopReason::Duration);
+    assert_eq!(elapsed, 600);
+    assert!(standard_error_m.unwrap() > 0.01);
+
+    let (reason, _, standard_error_m) = drive(&serial(None, None));
+    assert_eq!(reason, StopReason::Duration);
+    assert_eq!(standard_error_m, None);
+
+    let Action::Run { config, .. } = action_from_args(&[
+        "--serial",
+        "/dev/ttyUSB0",
+        "--stop-at-se",
+        "0.01",
+        "--min-duration",
+        "600",
+        "--duration",
+        "3600",
+    ])
+    .unwrap() else {
+        panic!("expected a run");
+    };
+    let serial = config.serial.unwrap();
+    assert_eq!(serial.stop_at_se_m, Some(0.01));
+    assert_eq!(serial.min_duration_s, Some(600.));
+    for args in [
+        &["tests/assets/1", "--stop-at-se", "0.01"][..],
+        &["--serial", "/dev/ttyUSB0", "--stop-at-se", "0"],
+        &["--serial", "/dev/ttyUSB0", "--min-duration", "600"],
+        &[
+            "--serial",
+            "/dev/ttyUSB0",
+            "--stop-at-se",
+            "0.01",
+            "--min-duration",
+            "600",
+            "--duration",
+            "60",
+        ],
+    ] {
+        assert!(action_from_args(args).is_err(), "{args:?}");
+    }
+}
+
+#[test]
+fn collapse_stationary_duplicates() {
+    let read = |dedup_m| {
+        let options = InputOptions {
+            parse: ParseOptions {
+                dedup_m,
+                ..ParseOptions::default()
+            },
+            ..InputOptions::default()
+        };
+        let data = read_inputs(&[PathBuf::from("tests/assets/stationary")], &options).unwrap();
+        let positions = data
+            .fixes
+            .iter()
+            .map(|fix| fix.position)
+            .collect::<Vec<_>>();
+        let (avg, _) = mean_and_std_dev(&positions);
+        (data, std_dev_m(&positions, avg))
+    };
+
+    let (all, all_std_dev) = read(None);
+    assert_eq!(all.fixes.len(), 60);
+    assert_eq!(all.duplicates, 0);
+
+    let (deduped, deduped_std_dev) = read(Some(DEFAULT_DEDUP_M));
+    assert_eq!(deduped.fixes.len(), 21);
+    assert_eq!(deduped.duplicates, 39);
+    assert_eq!(deduped.fixes[20].line, 21);
+    assert!(deduped_std_dev.x > all_std_dev.x && deduped_std_dev.y > all_std_dev.y);
+
+    // With a large enough epsilon, the neighbouring fixes collapse too.
+    let (coarse, _) = read(Some(10.));
+    assert_eq!(coarse.fixes.len(), 1);
+}
+
+#[test]
+fn quality_gates_from_arguments() {
+    let Action::Run { config, .. } = action_from_args(&[
+        "tests/assets/1",
+        "--max-stddev-m",
+        "2",
+        "--min-fixes",
+        "300",
+    ])
+    .unwrap() else {
+        panic!("expected a run");
+    };
+    assert_eq!(config.gates.max_std_dev_m, Some(2.));
+    assert_eq!(config.gates.min_fixes, Some(300));
+    assert!(action_from_args(&["tests/assets/1", "--max-stddev-m", "-1"]).is_err());
+
+    let data = read_inputs(&config.input_paths, &config.input).unwrap();
+    let filtered = Filtered::new(&data.fixes, &config.options).unwrap();
+    let summary = Summary::new(&data.fixes, filtered, &config.options);
+    let failed_gates = config.gates.check(&summary, None, None);
+    assert_eq!(failed_gates.len(), 2);
+    assert!(failed_gates[0].starts_with("275 fixes after filter"));
+    assert!(failed_gates[1].starts_with("horizontal standard deviation"));
+
+    let lenient = Gates {
+        max_std_dev_m: Some(20.),
+        min_fixes: Some(100),
+        max_error_m: None,
+        max_age_s: None,
+    };
+    assert!(lenient.check(&summary, None, None).is_empty());
+}
+
+#[test]
+fn group_digits_of_counts() {
+    assert_eq!(DigitGrouping::Comma.format(0), "0");
+    assert_eq!(DigitGrouping::Comma.format(999), "999");
+    assert_eq!(DigitGrouping::Comma.format(1000), "1,000");
+    assert_eq!(DigitGrouping::Comma.format(2846305), "2,846,305");
+    assert_eq!(DigitGrouping::Comma.format(123456789012), "123,456,789,012");
+    assert_eq!(
+        DigitGrouping::Thin.format(2846305),
+        "2\u{2009}846\u{2009}305"
+    );
+    assert_eq!(DigitGrouping::None.format(2846305), "2846305");
+}
+
+/// Fixes scattered pseudo-randomly around a position, spanning several orders of magnitude so that
+/// the order of the sums matters.
+fn scattered_fixes(n: usize) -> Vec<Fix> {
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    let mut next = || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (state >> 11) as f64 / (1u64 << 53) as f64 - 0.5
+    };
+    (0..n)
+        .map(|idx| Fix {
+            position: DVec3::new(
+                37.3748 + next() * 1e-4,
+                -6.0007 + next() * 1e-4,
+                40. + next() * 10f64.powi(idx as i32 % 4),
+            ),
+            weight: 1.,
+            line: idx + 1,
+            file: 0,
+            epoch: None,
+        })
+        .collect()
+}
+
+#[test]
+fn deterministic_statistics_ignore_the_order() {
+    let fixes = scattered_fixes(20_000);
+    // Like reading the same fixes split in several files given in another order.
+    let mut chunks = fixes.chunks(3_000).collect::<Vec<_>>();
+    chunks.reverse();
+    let reordered = chunks.concat();
+    let mut reversed = fixes.clone();
+    reversed.reverse();
+
+    let summarize = |fixes: &[Fix], deterministic| {
+        let options = Options {
+            deterministic,
+            weighted: true,
+            ..Options::default()
+        };
+        Summary::new(fixes, Filtered::new(fixes, &options).unwrap(), &options)
+    };
+    let sequential = summarize(&fixes, false);
+    let deterministic = summarize(&fixes, true);
+    for other in [summarize(&reordered, true), summarize(&reversed, true)] {
+        assert_eq!(other.avg.to_array(), deterministic.avg.to_array());
+        assert_eq!(other.std_dev.to_array(), deterministic.std_dev.to_array());
+        assert_eq!(
+            other.std_dev_m.to_array(),
+            deterministic.std_dev_m.to_array()
+        );
+        assert_eq!(other.avg_weighted, deterministic.avg_weighted);
+        assert_eq!(other.n_filtered, deterministic.n_filtered);
+    }
+
+    // Sorting only changes the rounding of the sums.
+    assert_eq!(sequential.n_filtered, deterministic.n_filtered);
+    assert!((sequential.avg - deterministic.avg).abs().max_element() < 1e-9);
+}
+
+#[test]
+fn read_gzip_compressed_files() {
+    let read = |path: &Path| {
+        read_inputs(&[path.to_owned()], &InputOptions::default())
+            .unwrap()
+            .fixes
+    };
+    let plain = read(Path::new("tests/assets/crlf"));
+    assert_eq!(read(Path::new("tests/assets/crlf.gz")), plain);
+
+    // Gzip files are detected by their first bytes too.
+    let renamed = std::env::temp_dir().join(format!("gpsavg-gzip-{}", std::process::id()));
+    fs::copy("tests/assets/crlf.gz", &renamed).unwrap();
+    let sniffed = read(&renamed);
+    fs::remove_file(&renamed).unwrap();
+    assert_eq!(sniffed, plain);
+}
+
+#[test]
+fn write_results_to_output_file() {
+    let path = std::env::temp_dir().join(format!("gpsavg-output-{}.txt", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let path_arg = path.to_str().unwrap();
+    let output_options = |args: &[&str]| {
+        let args = [&["tests/assets/1", "-o", path_arg], args].concat();
+        let Action::Run { config, .. } = action_from_args(&args).unwrap() else {
+            panic!("expected a run");
+        };
+        config
+    };
+
+    let config = output_options(&["-s", "-l"]);
+    assert_eq!(config.plan().outputs[1].path, path_arg);
+    config.output.write_results("first").unwrap();
+    let error = config.output.write_results("second").unwrap_err();
+    assert!(error.to_string().contains("already exists"));
+    assert_eq!(fs::read_to_string(&path).unwrap(), "first\n");
+
+    let forced = output_options(&["--force", "--format", "json"]);
+    assert_eq!(forced.plan().outputs[0].contents, "results as text");
+    forced.output.write_results("second").unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "second\n");
+    fs::remove_file(&path).unwrap();
+
+    let mut missing_dir = config.output.clone();
+    missing_dir.path = Some(PathBuf::from("tests/assets/missing/results.txt"));
+    let error = missing_dir.write_results("first").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Failed to write output file at tests/assets/missing/results.txt"
+    );
+    assert!(action_from_args(&["tests/assets/1", "--force"]).is_err());
+}
+
+#[test]
+fn compensated_sum_of_nearly_equal_values() {
+    // 40 plus a noise of whole multiples of 2^-40, so that the exact sum is known.
+    let n = 10_000_000;
+    let noise = |idx: usize| ((idx * 7919) % 1000) as f64;
+    let value = |idx: usize| 40. + noise(idx) * 2f64.powi(-40);
+    let exact_noise = (0..n).map(|idx| noise(idx) as u64).sum::<u64>();
+    let exact_mean = 40. + exact_noise as f64 / n as f64 * 2f64.powi(-40);
+
+    let naive_mean = (0..n).map(value).sum::<f64>() / n as f64;
+    assert!((naive_mean - exact_mean).abs() > 1e-10);
+
+    let mean = compensated_sum((0..n).map(value)) / n as f64;
+    assert_eq!(mean, exact_mean);
+    let mean = (0..n)
+        .map(|idx| DVec3::new(value(idx), -value(idx), 0.))
+        .sum::<CompensatedSum>()
+        .total()
+        / n as f64;
+    assert_eq!(mean, DVec3::new(exact_mean, -exact_mean, 0.));
+}
+
+#[test]
+fn confidence_interval_shrinks_with_more_fixes() {
+    let interval = |n: usize, level| {
+        let fixes = scattered_fixes(n);
+        let options = Options::default();
+        let summary = Summary::new(&fixes, Filtered::new(&fixes, &options).unwrap(), &options);
+        summary.confidence_interval(level)
+    };
+
+    let few = interval(100, ConfidenceLevel::P95);
+    let many = interval(10_000, ConfidenceLevel::P95);
+    assert_eq!(few.level, 95);
+    // A hundred times more fixes give an interval about ten times narrower.
+    for (few, many) in [(few.north, many.north), (few.east, many.east)] {
+        assert!(many < few);
+        assert!((few / many - 10.).abs() < 2.);
+    }
+    assert!(many.horizontal < few.horizontal);
+
+    let wider = interval(10_000, ConfidenceLevel::P99);
+    assert!((wider.north / many.north - 2.5758 / 1.96).abs() < 1e-9);
+    assert!(wider.horizontal > many.horizontal);
+    // About 2.45 standard errors contain 95% of the horizontal errors.
+    assert!((ConfidenceLevel::P95.radius_factor() - 2.4477).abs() < 1e-4);
+}
+
+#[test]
+fn skip_proprietary_and_unused_sentences() {
+    let parsed = parse_asset("mixed_types", &ParseOptions::default()).unwrap();
+    assert_eq!(parsed.fixes.len(), 5);
+    assert_eq!(parsed.rejected, 0);
+    assert!(parsed.fixes.iter().all(|fix| fix.hdop() == Some(0.9)));
+
+    let skipped = parsed.sentences.skipped().collect::<Vec<_>>();
+    assert_eq!(
+        skipped,
+        [
+            ("GSV", 10),
+            ("PGRMZ", 5),
+            ("PMTK314", 1),
+            ("PUBX", 5),
+            ("TXT", 1),
+            ("VTG", 5)
+        ]
+    );
+}
+
+#[test]
+fn histogram_csv_round_trip() {
+    // Uniformly scattered horizontal positions have no outliers, unlike the scattered altitudes.
+    let fixes = scattered_fixes(1_000)
+        .into_iter()
+        .enumerate()
+        .map(|(idx, fix)| Fix {
+            position: DVec3::new(fix.position.x, fix.position.y, (idx % 10) as f64),
+            ..fix
+        })
+        .collect::<Vec<_>>();
+    let options = Options {
+        bin_layout: BinLayout::Count(12),
+        ..Options::default()
+    };
+    let summary = Summary::new(&fixes, Filtered::new(&fixes, &options).unwrap(), &options);
+    assert_eq!(summary.n_filtered, fixes.len());
+
+    let csv = format_histogram_csv(&summary.histograms);
+    let mut lines = csv.lines();
+    assert_eq!(lines.next(), Some("axis,lower,upper,count"));
+    let rows = lines
+        .map(|line| {
+            let fields = line.split(',').collect::<Vec<_>>();
+            let bound = |idx: usize| fields[idx].parse::<f64>().unwrap();
+            (
+                fields[0].to_owned(),
+                (bound(1), bound(2)),
+                fields[3].parse::<i32>().unwrap(),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    for (name, histogram) in ["latitude", "longitude", "altitude"]
+        .iter()
+        .zip(&summary.histograms)
+    {
+        let axis_rows = rows.iter().filter(|(axis, ..)| axis == name);
+        let bounds = axis_rows.clone().map(|(_, bounds, _)| *bounds);
+        assert_eq!(bounds.collect::<Vec<_>>(), histogram.divisions());
+        let total = axis_rows.map(|(.., count)| count).sum::<i32>();
+        assert_eq!(total as usize, summary.n_filtered);
+    }
+}
+
+#[test]
+fn detect_gaps_and_jumps() {
+    let fixes = parse_asset("jump", &ParseOptions::default()).unwrap().fixes;
+    let events = JumpDetection::default().detect(&fixes);
+    let lines = events
+        .iter()
+        .map(|event| (event.previous_line, event.line, event.is_jump()))
+        .collect::<Vec<_>>();
+    assert_eq!(lines, [(5, 6, false), (10, 11, false), (10, 11, true)]);
+    assert!(matches!(events[1].kind, EventKind::Gap { seconds } if seconds == 36.));
+    let EventKind::Jump {
+        north,
+        east,
+        horizontal,
+        ..
+    } = events[2].kind
+    else {
+        panic!("expected a jump");
+    };
+    assert!((north - 55.5).abs() < 0.1 && east.abs() < 1e-6);
+    assert_eq!(horizontal, north);
+
+    // Only position jumps split the fixes.
+    let split = segments(&fixes, &events);
+    assert_eq!(split.len(), 2);
+    assert_eq!((split[0].len(), split[1].len()), (10, 10));
+    assert_eq!(split[1][0].line, 11);
+
+    // Larger limits detect nothing.
+    let lenient = JumpDetection {
+        max_gap_s: 60.,
+        max_jump_m: 100.,
+        split: true,
+    };
+    assert!(lenient.detect(&fixes).is_empty());
+    assert_eq!(segments(&fixes, &[]), [fixes.as_slice()]);
+}
+
+#[test]
+fn jump_detection_arguments() {
+    let Action::Run { config, .. } = action_from_args(&[
+        "tests/assets/jump",
+        "--detect-jumps",
+        "--max-jump",
+        "2.5",
+        "--split-on-jump",
+    ])
+    .unwrap() else {
+        panic!("expected a run");
+    };
+    assert_eq!(
+        config.jumps,
+        Some(JumpDetection {
+            max_gap_s: DEFAULT_MAX_GAP_S,
+            max_jump_m: 2.5,
+            split: true,
+        })
+    );
+    assert!(matches!(
+        config.plan().estimators.last(),
+        Some(Estimator::Jumps { split: true, .. })
+    ));
+
+    assert!(action_from_args(&["tests/assets/jump", "--split-on-jump"]).is_err());
+    assert!(action_from_args(&["tests/assets/jump", "--detect-jumps", "--max-gap", "0"]).is_err());
+}
+
+#[test]
+fn identical_fixes_have_no_spread() {
+    let fixes = parse_asset("frozen", &ParseOptions::default())
+        .unwrap()
+        .fixes;
+    for bin_layout in [Options::default().bin_layout, BinLayout::Count(10)] {
+        let options = Options {
+            bin_layout,
+            ..Options::default()
+        };
+        let summary = Summary::new(&fixes, Filtered::new(&fixes, &options).unwrap(), &options);
+        assert_eq!(summary.n_filtered, 12);
+        assert_eq!(summary.avg, DVec3::new(37.3748, -6.0007, 40.));
+        assert_eq!(summary.std_dev, DVec3::ZERO);
+        assert_eq!(summary.repeated, [Some(37.3748), Some(-6.0007), Some(40.)]);
+        assert!(summary
+            .histograms
+            .iter()
+            .all(|histogram| histogram.counts.is_empty()));
+        assert_eq!(
+            format_histogram_csv(&summary.histograms),
+            "axis,lower,upper,count\n"
+        );
+    }
+
+    // Rounding leaves a tiny spread, which still doesn't filter anything.
+    let center = DVec3::new(37.3748, -6.0007, 40.);
+    let kept = filter_outliers(&fixes, (center, DVec3::splat(1e-15)), 3.);
+    assert_eq!(kept.len(), fixes.len());
+}
+
+#[test]
+fn ellipsoidal_heights() {
+    let read = |height| {
+        let options = InputOptions {
+            height,
+            ..InputOptions::default()
+        };
+        read_inputs(&[PathBuf::from("tests/assets/geoid")], &options).unwrap()
+    };
+    let average_altitude = |fixes: &[Fix]| {
+        let positions = fixes.iter().map(|fix| fix.position).collect::<Vec<_>>();
+        mean_and_std_dev(&positions).0.z
+    };
+
+    let orthometric = read(HeightSystem::Orthometric);
+    assert_eq!(orthometric.fixes.len(), 10);
+    assert_eq!(orthometric.no_geoid_separation, 0);
+    assert!((average_altitude(&orthometric.fixes) - 40.45).abs() < 1e-4);
+
+    let ellipsoidal = read(HeightSystem::Ellipsoidal);
+    assert_eq!(ellipsoidal.fixes.len(), 8);
+    assert_eq!(ellipsoidal.no_geoid_separation, 2);
+    assert!(ellipsoidal
+        .fixes
+        .iter()
+        .all(|fix| fix.line != 4 && fix.line != 8));
+    assert!((average_altitude(&ellipsoidal.fixes) - (40.4375 + 47.2)).abs() < 1e-4);
+
+    // Plain input has no geoid separation.
+    assert!(action_from_args(&[
+        "tests/assets/weighted",
+        "--input-format",
+        "plain",
+        "--height",
+        "ellipsoidal"
+    ])
+    .is_err());
+}
+
+#[test]
+fn balloon_heights_keep_their_precision() {
+    let options = InputOptions {
+        height: HeightSystem::Ellipsoidal,
+        ..InputOptions::default()
+    };
+    let data = read_inputs(&[PathBuf::from("tests/assets/balloon")], &options).unwrap();
+    assert_eq!(data.fixes.len(), 10);
+    // An f32 only resolves about 4mm at 35km, and rounds 35012.3456 to 35012.34375.
+    for (idx, fix) in data.fixes.iter().enumerate() {
+        let expected = 35012.3456 + 0.0013 * idx as f64 + 47.213;
+        assert!(
+            (fix.position.z - expected).abs() < 1e-9,
+            "{} {expected}",
+            fix.position.z
+        );
+    }
+
+    let options = Options::default();
+    let filtered = Filtered::new(&data.fixes, &options).unwrap();
+    let summary = Summary::new(&data.fixes, filtered, &options);
+    let expected = 35012.3456 + 0.0013 * 4.5 + 47.213;
+    assert!(
+        (summary.avg.z - expected).abs() < 1e-9,
+        "{} {expected}",
+        summary.avg.z
+    );
+    assert!(summary.std_dev.z < 0.005);
+}
+
+#[test]
+fn histogram_overlays_rejected_fixes() {
+    let fixes = masked_outlier_fixes();
+    let summarize = |hist_include_rejected| {
+        let options = Options {
+            max_passes: MAX_FILTER_PASSES,
+            hist_include_rejected,
+            ..Options::default()
+        };
+        let filtered = Filtered::new(&fixes, &options).unwrap();
+        assert_eq!(filtered.rejected, fixes[100..]);
+        Summary::new(&fixes, filtered, &options)
+    };
+
+    // Only the filtered fixes are counted by default.
+    let summary = summarize(false);
+    for histogram in &summary.histograms {
+        assert_eq!(histogram.counts.iter().sum::<i32>(), 100);
+        assert_eq!(histogram.rejected, None);
+    }
+
+    // The two outliers masked by the wild one fall inside the bins, unlike the wild one.
+    let summary = summarize(true);
+    for histogram in &summary.histograms {
+        assert_eq!(histogram.counts.iter().sum::<i32>(), 100);
+        let rejected = histogram.rejected.as_ref().unwrap();
+        assert_eq!(rejected.iter().sum::<i32>(), 2);
+        assert_eq!(rejected.len(), histogram.counts.len());
+    }
+
+    let csv = format_histogram_csv(&summary.histograms);
+    assert!(csv.starts_with("axis,lower,upper,count,rejected\n"));
+    let rejected = csv
+        .lines()
+        .skip(1)
+        .map(|line| line.split(',').nth(4).unwrap().parse::<i32>().unwrap())
+        .sum::<i32>();
+    assert_eq!(rejected, 6);
+
+    let [x, y, z] = &summary.histograms;
+    let axes = || {
+        [x, y, z].map(|histogram| AxisHistogram {
+            name: "Axis",
+            histogram,
+        })
+    };
+    let table = format_histogram_table(axes());
+    assert!(table.starts_with("  Axis (filtered, rejected)\t"));
+    assert!(table.lines().skip(1).all(|line| line
+        .split('\t')
+        .filter(|cell| !cell.is_empty())
+        .count()
+        == 9));
+    let bars = format_histogram_bars(axes());
+    let row = bars
+        .lines()
+        .find(|line| {
+            x.divisions()
+                .iter()
+                .any(|(inf, _)| line.starts_with(&format!("({inf:.6}")))
+        })
+        .unwrap();
+    assert_eq!(
+        row.split_whitespace()
+            .filter(|word| word.parse::<i32>().is_ok())
+            .count(),
+        2
+    );
+}
+
+#[test]
+fn color_choice() {
+    let no_color = Some(OsStr::new("1"));
+    assert!(ColorChoice::Auto.enabled(true, None));
+    assert!(ColorChoice::Auto.enabled(true, Some(OsStr::new(""))));
+    assert!(!ColorChoice::Auto.enabled(true, no_color));
+    assert!(!ColorChoice::Auto.enabled(false, None));
+    assert!(ColorChoice::Always.enabled(false, no_color));
+    assert!(!ColorChoice::Never.enabled(true, None));
+
+    let Action::Run { config, .. } =
+        action_from_args(&["tests/assets/1", "--color", "never"]).unwrap()
+    else {
+        panic!("expected a run");
+    };
+    assert_eq!(config.output.color, ColorChoice::Never);
+
+    // Styling is disabled globally, as the program does with `NO_COLOR` set.
+    colored::control::set_override(ColorChoice::Auto.enabled(true, no_color));
+    let histogram = Histogram::new(&[DVec3::ZERO, DVec3::ONE], |x| x.x, vec![0., 0.5, 1.]);
+    let axes = [&histogram; 3].map(|histogram| AxisHistogram {
+        name: "Axis",
+        histogram,
+    });
+    let formatted = format_histogram_bars(axes);
+    colored::control::unset_override();
+    assert!(formatted.contains('█'));
+    assert!(!formatted.contains('\x1b'));
+}
+
+#[test]
+fn welford_matches_two_pass_formula() {
+    let positions = scattered_fixes(10_000)
+        .iter()
+        .map(|fix| fix.position)
+        .collect::<Vec<_>>();
+    let n = positions.len() as f64;
+    let avg = positions.iter().sum::<DVec3>() / n;
+    let variance = positions
+        .iter()
+        .map(|position| sq_elements(*position - avg))
+        .sum::<DVec3>()
+        / (n - 1.);
+
+    let stats = positions.iter().copied().sum::<Welford>();
+    assert_eq!(stats.count(), positions.len());
+    assert!(((stats.mean() - avg) / avg).abs().max_element() < 1e-14);
+    assert!(
+        ((stats.variance() - variance) / variance)
+            .abs()
+            .max_element()
+            < 1e-10
+    );
+
+    assert!(Welford::default().mean().is_nan());
+    let single = [avg].into_iter().sum::<Welford>();
+    assert_eq!((single.mean(), single.variance()), (avg, DVec3::ZERO));
+}
+
+#[test]
+fn element_wise_helpers_match_powers() {
+    let value = DVec3::new(-3.5, 0., 1e-9);
+    assert_eq!(sq_elements(value), value.powf(2.));
+    assert_eq!(sqrt_elements(sq_elements(value)), value.abs());
+    assert!(sqrt_elements(DVec3::new(-1., 4., 9.)).x.is_nan());
+
+    // The statistics of the fixtures are the same as with the powers of glam, which the helpers
+    // replaced.
+    for asset in ["golden", "1", "stationary", "frozen", "balloon"] {
+        let parsed = parse_asset(asset, &ParseOptions::default()).unwrap();
+        let positions = parsed
+            .fixes
+            .iter()
+            .map(|fix| fix.position)
+            .collect::<Vec<_>>();
+        let stats = positions.iter().copied().sum::<Welford>();
+        assert_eq!(stats.std_dev(), stats.variance().powf(0.5), "{asset}");
+        let (first, second) = positions.split_at(positions.len() / 3);
+        let (first, second) = (
+            first.iter().copied().sum::<Welford>(),
+            second.iter().copied().sum::<Welford>(),
+        );
+        let delta = second.mean() - first.mean();
+        let (n1, n2) = (first.count() as f64, second.count() as f64);
+        let m2 = first.variance() * (n1 - 1.)
+            + second.variance() * (n2 - 1.)
+            + delta.powf(2.) * (n1 * n2 / (n1 + n2));
+        let merged = first.merge(&second).variance();
+        let expected = m2 / (n1 + n2 - 1.);
+        assert!(
+            (merged - expected).abs().cmple(expected * 1e-12).all(),
+            "{asset}"
+        );
+    }
+}
+
+#[test]
+fn welford_keeps_precision_far_from_zero() {
+    // A million values far from zero, with a known variance: 4, 7, 13 and 16 have a population
+    // variance of 22.5.
+    let offset = 1e9;
+    let n = 1_000_000;
+    let value = |idx: usize| offset + [4., 7., 13., 16.][idx % 4];
+    let exact_variance = 22.5 * n as f64 / (n - 1) as f64;
+
+    // Summing the squares cancels catastrophically, since they are 18 orders of magnitude larger
+    // than the variance.
+    let (sum, sum_squares) = (0..n).map(value).fold((0., 0.), |(sum, sum_squares), x| {
+        (sum + x, sum_squares + x * x)
+    });
+    let naive_variance = (sum_squares - sum * sum / n as f64) / (n - 1) as f64;
+    assert!((naive_variance - exact_variance).abs() > 1.);
+
+    let stats = (0..n)
+        .map(|idx| DVec3::new(value(idx), -value(idx), 0.))
+        .sum::<Welford>();
+    assert_eq!(stats.mean(), DVec3::new(offset + 10., -offset - 10., 0.));
+    let variance = stats.variance();
+    assert!((variance.x - exact_variance).abs() < 1e-6);
+    assert!((variance.y - exact_variance).abs() < 1e-6);
+    assert_eq!(variance.z, 0.);
+}
+
+#[test]
+fn demultiplex_tagged_receivers() {
+    assert_eq!(split_tag("A:$GPGGA,1"), Some(("A", "$GPGGA,1")));
+    assert_eq!(split_tag("rover_2:1, 2, 3"), Some(("rover_2", "1, 2, 3")));
+    assert_eq!(split_tag("$GPGGA,120000.00"), None);
+    assert_eq!(split_tag(":$GPGGA"), None);
+
+    let options = InputOptions {
+        demux: true,
+        ..InputOptions::default()
+    };
+    let streams = read_demuxed_inputs(&[PathBuf::from("tests/assets/tagged")], &options).unwrap();
+    assert_eq!(streams.keys().collect::<Vec<_>>(), ["A", "B"]);
+    let (a, b) = (&streams["A"], &streams["B"]);
+    assert_eq!((a.fixes.len(), b.fixes.len()), (10, 10));
+    // The lines are those of the multiplexed file, and each stream is assembled on its own.
+    assert_eq!(a.fixes[1].line, 5);
+    assert_eq!(b.fixes[1].line, 7);
+    assert!(a
+        .fixes
+        .iter()
+        .all(|fix| fix.hdop().is_some() && fix.line % 4 == 1));
+    assert_eq!(a.unassociated + b.unassociated, 0);
+
+    let summary = |data: &InputData| {
+        let options = Options::default();
+        Summary::new(
+            &data.fixes,
+            Filtered::new(&data.fixes, &options).unwrap(),
+            &options,
+        )
+    };
+    let baseline = summary(b).reference_error(summary(a).avg);
+    assert!((baseline.east - 10.).abs() < 0.05);
+    assert!(baseline.north.abs() < 0.01);
+    assert!((baseline.up - 1.5).abs() < 1e-3);
+
+    // Files without tags can't be demultiplexed, unless their lines are skipped.
+    let error = read_demuxed_inputs(&[PathBuf::from("tests/assets/1")], &options)
+        .err()
+        .unwrap();
+    assert!(format!("{error:#}").contains("line 1 "));
+    let lenient = InputOptions {
+        parse: ParseOptions {
+            policy: LinePolicy::lenient(),
+            ..ParseOptions::default()
+        },
+        ..options
+    };
+    let streams = read_demuxed_inputs(&[PathBuf::from("tests/assets/1")], &lenient).unwrap();
+    assert!(streams.is_empty());
+
+    assert!(action_from_args(&["tests/assets/tagged", "--demux-prefix", "-l"]).is_err());
+}
+
+#[test]
+fn parse_durations() {
+    assert_eq!(parse_duration("45s"), Ok(45.));
+    assert_eq!(parse_duration("90m"), Ok(5400.));
+    assert_eq!(parse_duration("2h"), Ok(7200.));
+    assert_eq!(parse_duration("1.5h"), Ok(5400.));
+    assert_eq!(parse_duration("1d"), Ok(86400.));
+    for invalid in ["", "2", "h", "2x", "-1h", "infh", "1 d"] {
+        assert!(parse_duration(invalid).is_err(), "{invalid:?}");
+    }
+    assert_eq!(format_duration(7503.), "2h 5m 3s");
+    assert_eq!(format_duration(90000.), "1d 1h 0m 0s");
+    assert_eq!(format_duration(59.6), "1m 0s");
+    assert_eq!(format_duration(-5.), "-5s");
+}
+
+#[test]
+fn age_of_the_last_fix() {
+    let mut fixes = parse_asset("1", &ParseOptions::default()).unwrap().fixes;
+    let date = NaiveDate::from_ymd_opt(2023, 3, 2).unwrap();
+    let last_time = fixes.last().unwrap().time().unwrap();
+    assert_eq!(last_fix_time(&fixes), Some(date.and_time(last_time)));
+
+    // Without RMC sentences of its own, a fix after midnight is dated on the next day.
+    let last = fixes.last_mut().unwrap().epoch.as_mut().unwrap();
+    last.rmc = None;
+    last.gga.time = NaiveTime::from_hms_opt(0, 0, 5);
+    let next_day = date.succ_opt().unwrap().and_hms_opt(0, 0, 5).unwrap();
+    assert_eq!(last_fix_time(&fixes), Some(next_day));
+
+    // The date can't be resolved without any RMC sentence.
+    for fix in &mut fixes {
+        fix.epoch.as_mut().unwrap().rmc = None;
+    }
+    assert_eq!(last_fix_time(&fixes), None);
+}
+
+#[test]
+fn max_age_gate() {
+    let data = read_inputs(&[PathBuf::from("tests/assets/1")], &InputOptions::default()).unwrap();
+    let options = Options::default();
+    let filtered = Filtered::new(&data.fixes, &options).unwrap();
+    let summary = Summary::new(&data.fixes, filtered, &options);
+    let last_fix_time = summary.last_fix_time.unwrap();
+    let age_s = summary.last_fix_age_s(last_fix_time + chrono::Duration::hours(3));
+    assert_eq!(age_s, Some(10800.));
+
+    let gates = Gates {
+        max_age_s: Some(parse_duration("2h").unwrap()),
+        ..Gates::default()
+    };
+    let failed_gates = gates.check(&summary, None, age_s);
+    assert_eq!(
+        failed_gates,
+        ["last fix 3h 0m 0s old, above the maximum of 2h 0m 0s"]
+    );
+    assert!(gates.check(&summary, None, Some(3600.)).is_empty());
+    assert_eq!(gates.check(&summary, None, None).len(), 1);
+
+    let Action::Run { config, .. } =
+        action_from_args(&["tests/assets/1", "--max-age", "90m"]).unwrap()
+    else {
+        panic!("expected a run");
+    };
+    assert_eq!(config.gates.max_age_s, Some(5400.));
+    assert!(action_from_args(&["tests/assets/1", "--max-age", "90"]).is_err());
+}
+
+#[test]
+fn correct_inconsistent_hemispheres() {
+    // The minutes are negative, so the coordinates come out with the opposite sign to the one of
+    // their hemisphere letters.
+    let northeast = "$GPGGA,120000.00,00-30.00000,N,000-15.00000,E,1,09,0.90,40.0,M,47.2,M,,*6D";
+    let southwest = "$GPGGA,120001.00,00-30.00000,S,00600.00000,W,1,09,0.90,40.0,M,47.2,M,,*4C";
+    let position = |line, verify_hemisphere| match parse_line(line, verify_hemisphere) {
+        Ok(Some(Sentence::Gga(gga))) => (gga.position.unwrap(), gga.hemisphere_corrected),
+        other => panic!("expected a GGA sentence, got {other:?}"),
+    };
+
+    let (unverified, corrected) = position(northeast, false);
+    assert_eq!((unverified.x, unverified.y), (-0.5, -0.25));
+    assert!(!corrected);
+    let (verified, corrected) = position(northeast, true);
+    assert_eq!((verified.x, verified.y), (0.5, 0.25));
+    assert!(corrected);
+
+    // Only the latitude disagrees.
+    let (verified, corrected) = position(southwest, true);
+    assert_eq!((verified.x, verified.y), (-0.5, -6.));
+    assert!(corrected);
+
+    let file = format!("{northeast}\n{southwest}\n");
+    let parsed = parse_file(file.as_bytes(), &ParseOptions::default()).unwrap();
+    assert_eq!(parsed.hemisphere_corrected, 2);
+    assert!(parsed.fixes.iter().all(|fix| fix.position.x.abs() == 0.5));
+    let parsed = parse_asset("1", &ParseOptions::default()).unwrap();
+    assert_eq!(parsed.hemisphere_corrected, 0);
+
+    let options = |args: &[&str]| {
+        let Action::Run { config, .. } =
+            action_from_args(&[&["tests/assets/1"], args].concat()).unwrap()
+        else {
+            panic!("expected a run");
+        };
+        config.input.parse.verify_hemisphere
+    };
+    assert!(options(&[]));
+    assert!(!options(&["--verify-hemisphere", "false"]));
+}
+
+#[test]
+fn merged_summaries_match_the_whole_data() {
+    let mut fixes = scattered_fixes(5_000);
+    for (idx, fix) in fixes.iter_mut().enumerate() {
+        fix.weight = 1. + (idx % 7) as f64;
+    }
+    // The cutoff keeps every fix, so each chunk is filtered like the whole data, and the bins are
+    // the same for every chunk.
+    let (min, max) = fixes
+        .iter()
+        .fold((DVec3::INFINITY, DVec3::NEG_INFINITY), |(min, max), fix| {
+            (min.min(fix.position), max.max(fix.position))
+        });
+    let margin = DVec3::new(1e-6, 1e-6, 0.1);
+    let options = Options {
+        cutoff: 1e9,
+        weighted: true,
+        bin_layout: BinLayout::Fixed {
+            min: (min - margin).into(),
+            max: (max + margin).into(),
+            count: 50,
+        },
+        ..Options::default()
+    };
+    let summarize =
+        |fixes: &[Fix]| Summary::new(fixes, Filtered::new(fixes, &options).unwrap(), &options);
+    let whole = summarize(&fixes);
+    let close = |a: DVec3, b: DVec3, tolerance: f64| {
+        ((a - b).abs() / b.abs().max(DVec3::splat(1e-300))).max_element() < tolerance
+    };
+
+    let mut state = 0x9e37_79b9_7f4a_7c15_u64;
+    for _ in 0..20 {
+        let mut splits = (0..1 + state as usize % 5)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                1 + (state >> 33) as usize % (fixes.len() - 1)
+            })
+            .collect::<Vec<_>>();
+        splits.sort();
+        splits.dedup();
+        let bounds = std::iter::once(0)
+            .chain(splits)
+            .chain(std::iter::once(fixes.len()))
+            .collect::<Vec<_>>();
+        let merged = bounds
+            .windows(2)
+            .map(|bounds| summarize(&fixes[bounds[0]..bounds[1]]))
+            .collect::<anyhow::Result<Summary>>()
+            .unwrap();
+
+        assert_eq!(merged.n, whole.n);
+        assert_eq!(merged.n_filtered, whole.n_filtered);
+        assert_eq!(merged.removed_per_pass, whole.removed_per_pass);
+        assert_eq!(merged.repeated, whole.repeated);
+        assert_eq!(merged.histograms, whole.histograms);
+        assert_eq!((merged.min, merged.max), (whole.min, whole.max));
+        assert_eq!(merged.moments.count(), whole.moments.count());
+        assert!(close(merged.avg, whole.avg, 1e-14));
+        assert!(close(merged.std_dev, whole.std_dev, 1e-10));
+        // The offsets in meters of each chunk are from its own average, whose local frame is a
+        // little tilted from the one of the whole data, which is far below a micrometer.
+        assert!((merged.std_dev_m - whole.std_dev_m).abs().max_element() < 1e-6);
+        assert!(close(
+            merged.avg_weighted.unwrap(),
+            whole.avg_weighted.unwrap(),
+            1e-14
+        ));
+        assert!((merged.total_weight - whole.total_weight).abs() < 1e-9);
+    }
+}
+
+#[test]
+fn merge_histograms_and_reject_mismatches() {
+    let positions = masked_outlier_fixes()
+        .iter()
+        .map(|fix| fix.position)
+        .collect::<Vec<_>>();
+    let (first, second) = positions.split_at(40);
+    let edges = vec![-100., 0., 100., 1000.];
+    let histogram = |positions: &[DVec3]| Histogram::new(positions, |x| x.x, edges.clone());
+    let merged = histogram(first).merge(&histogram(second)).unwrap();
+    assert_eq!(merged, histogram(&positions));
+    let with_rejected = histogram(first).with_rejected(second, |x| x.x);
+    assert!(with_rejected.merge(&histogram(second)).is_err());
+    let other_edges = Histogram::new(second, |x| x.x, vec![-50., 0., 50.]);
+    assert!(histogram(first).merge(&other_edges).is_err());
+    assert!(Histogram::default().merge(&Histogram::default()).is_ok());
+
+    // The bin edges of summaries with the default layout depend on their fixes.
+    let fixes = scattered_fixes(100);
+    let options = Options::default();
+    let summarize =
+        |fixes: &[Fix]| Summary::new(fixes, Filtered::new(fixes, &options).unwrap(), &options);
+    let error = summarize(&fixes[..50])
+        .merge(&summarize(&fixes[50..]))
+        .unwrap_err();
+    assert_eq!(error.to_string(), "The histograms have different bin edges");
+    let robust = Options {
+        robust: true,
+        ..Options::default()
+    };
+    let median = Summary::new(&fixes, Filtered::new(&fixes, &robust).unwrap(), &robust);
+    assert!(median.merge(&median).is_err());
+    assert!(std::iter::empty::<Summary>()
+        .collect::<anyhow::Result<Summary>>()
+        .is_err());
+}
+
+#[test]
+fn round_half_to_even() {
+    for (value, decimals, expected) in [
+        (1.005, 2, "1.00"),
+        (1.015, 2, "1.02"),
+        (1.025, 2, "1.02"),
+        (1.0051, 2, "1.01"),
+        (0.125, 2, "0.12"),
+        (0.375, 2, "0.38"),
+        (2.5, 0, "2"),
+        (3.5, 0, "4"),
+        (9.995, 2, "10.00"),
+        (-1.005, 2, "-1.00"),
+        (-1.015, 2, "-1.02"),
+        (37.37485, 4, "37.3748"),
+        (37.37475, 4, "37.3748"),
+        (765582.8885, 3, "765582.888"),
+        (35.25, 1, "35.2"),
+        (1e-7, 3, "0.000"),
+        (12., 3, "12.000"),
+        (-0.00001, 4, "0.0000"),
+        (-0.00005, 4, "0.0000"),
+        (-0.00006, 4, "-0.0001"),
+    ] {
+        assert_eq!(format_rounded(value, decimals), expected, "{value}");
+    }
+    // The standard formatting rounds the binary value, which is a little below 1.015.
+    assert_eq!(format!("{:.2}", 1.015), "1.01");
+
+    // Ties on the thousandths of seconds, 0.0025" and 0.0035".
+    assert_eq!(
+        format_dms(0.0025 / 3600., 0.),
+        "0°00'00.002\"N 0°00'00.000\"E"
+    );
+    assert_eq!(
+        format_dms(0.0035 / 3600., 0.),
+        "0°00'00.004\"N 0°00'00.000\"E"
+    );
+    // Ties on the ten-thousandths, though the binary values times 3600e3 are a little below 2979.5
+    // and above 9930.5.
+    assert_eq!(
+        format_dms(2.9795 / 3600., -9.9305 / 3600.),
+        "0°00'02.980\"N 0°00'09.930\"W"
+    );
+}
+
+#[test]
+fn average_in_all_formats() {
+    let block = representations(GOLDEN_AVERAGE.x, GOLDEN_AVERAGE.y)
+        .into_iter()
+        .map(|(coord_format, formatted)| format!("{}: {formatted}", coord_format.label()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        block,
+        [
+            "Decimal degrees: 37.3748º, -6.0007º".to_owned(),
+            format!("Degrees, minutes and seconds: {GOLDEN_DMS}"),
+            format!("UTM: {GOLDEN_UTM}"),
+        ]
+    );
+    let json = serde_json::to_value(representations(GOLDEN_AVERAGE.x, GOLDEN_AVERAGE.y)).unwrap();
+    assert_eq!(json["dms"], GOLDEN_DMS);
+    assert_eq!(json["utm"], GOLDEN_UTM);
+
+    // UTM isn't defined near the poles.
+    let polar = representations(85., 10.);
+    assert_eq!(
+        polar.keys().copied().collect::<Vec<_>>(),
+        [CoordFormat::Dd, CoordFormat::Dms]
+    );
+
+    let Action::Run { config, .. } = action_from_args(&["f", "--all-formats"]).unwrap() else {
+        panic!("expected a run");
+    };
+    assert!(config.output.all_formats);
+    assert!(action_from_args(&["f", "--all-formats", "--short"]).is_err());
+}
+
+#[test]
+fn rounding_error_of_each_format() {
+    let position = DVec3::new(37.3748, -6.0007, 40.);
+    let dd = CoordFormat::Dd.rounding_error_m(position);
+    // Half of 0.0001º is about 5.5m of latitude, and less of longitude away from the equator.
+    assert!((dd.x - 5.55).abs() < 0.01);
+    assert!((dd.y - 5.55 * 37.3748f64.to_radians().cos()).abs() < 0.02);
+    assert_eq!(dd.z, 0.05);
+    let dms = CoordFormat::Dms.rounding_error_m(position);
+    assert!((dms.x - dd.x * 0.0005 / 3600. / 0.00005).abs() < 1e-4);
+    assert_eq!(
+        CoordFormat::Utm.rounding_error_m(position),
+        DVec3::new(0.0005, 0.0005, 0.05)
+    );
+}
+
+#[test]
+fn merge_overlapping_ranges() {
+    assert_eq!(
+        merge_ranges(vec![20..=30, 1..=5, 25..=40, 6..=8, 50..=50, 10..=12]),
+        [1..=8, 10..=12, 20..=40, 50..=50]
+    );
+    assert_eq!(merge_ranges(vec![3..=9, 4..=5]), [3..=9]);
+    assert!(merge_ranges(Vec::new()).is_empty());
+
+    let exclusions = |args: &[&str]| {
+        let Action::Run { config, .. } =
+            action_from_args(&[&["tests/assets/1"], args].concat()).unwrap()
+        else {
+            panic!("expected a run");
+        };
+        config.input.exclusions
+    };
+    let parsed = exclusions(&[
+        "--exclude-lines",
+        "1200..1850",
+        "--exclude-lines",
+        "1000..1300",
+        "--exclude-time",
+        "10:41..10:44",
+        "--exclude-time",
+        "23:59:30..00:00:30.5",
+    ]);
+    assert_eq!(parsed.lines, [1000..=1850]);
+    assert_eq!(
+        parsed.times,
+        [
+            TimeWindow {
+                from: time("10:41:00"),
+                to: time("10:44:00"),
+            },
+            TimeWindow {
+                from: time("23:59:30"),
+                to: time("00:00:30.5"),
+            },
+        ]
+    );
+    assert!(exclusions(&[]).is_empty());
+    for invalid in [
+        ["--exclude-lines", "1850..1200"],
+        ["--exclude-lines", "0..10"],
+        ["--exclude-lines", "10"],
+        ["--exclude-time", "10:41"],
+        ["--exclude-time", "10:41..25:00"],
+    ] {
+        assert!(
+            action_from_args(&[&["tests/assets/1"], &invalid[..]].concat()).is_err(),
+            "{invalid:?}"
+        );
+    }
+    assert!(action_from_args(&[
+        "tests/assets/weighted",
+        "--input-format",
+        "plain",
+        "--exclude-time",
+        "10:41..10:44"
+    ])
+    .is_err());
+}
+
+#[test]
+fn exclude_ranges_before_the_time_window() {
+    let read = |exclusions: Exclusions, window: Option<TimeWindow>| {
+        let options = InputOptions {
+            exclusions,
+            window,
+            ..InputOptions::default()
+        };
+        read_inputs(&[PathBuf::from("tests/assets/1")], &options).unwrap()
+    };
+    let all = read(Exclusions::default(), None);
+    assert_eq!(all.excluded, 0);
+
+    // Overlapping ranges exclude each fix once.
+    let exclusions = Exclusions {
+        lines: Vec::new(),
+        times: vec![
+            TimeWindow {
+                from: time("15:04:00"),
+                to: time("15:05:00"),
+            },
+            TimeWindow {
+                from: time("15:04:30"),
+                to: time("15:05:30"),
+            },
+        ],
+    };
+    let excluded = read(exclusions.clone(), None);
+    let in_ranges = |from: &str, to: &str| {
+        let window = TimeWindow {
+            from: time(from),
+            to: time(to),
+        };
+        all.fixes
+            .iter()
+            .filter(|fix| window.contains(fix.time().unwrap()))
+            .count()
+    };
+    assert_eq!(excluded.excluded, in_ranges("15:04:00", "15:05:30"));
+    assert_eq!(excluded.fixes.len() + excluded.excluded, all.fixes.len());
+
+    // The time window only counts the fixes left by the exclusions.
+    let window = TimeWindow {
+        from: time("15:04:45"),
+        to: None,
+    };
+    let both = read(exclusions, Some(window));
+    assert_eq!(both.excluded, excluded.excluded);
+    assert_eq!(
+        both.outside_window,
+        all.fixes.len() - excluded.excluded - in_ranges("15:05:30.01", "23:59:59")
+    );
+
+    let lines = Exclusions {
+        lines: merge_ranges(vec![1..=100, 50..=150]),
+        times: Vec::new(),
+    };
+    let excluded = read(lines, None);
+    let expected = all.fixes.iter().filter(|fix| fix.line <= 150).count();
+    assert_eq!(excluded.excluded, expected);
+    assert!(excluded.fixes.iter().all(|fix| fix.line > 150));
+}
+
+/// Explains the statistics of `n` scattered fixes, with their offsets from the center scaled by
+/// `scale`, over `duration_s` seconds and with the given events.
+fn explain_fixes(n: usize, scale: f64, duration_s: Option<f64>, events: &[Event]) -> Vec<String> {
+    let center = DVec3::new(37.3748, -6.0007, 40.);
+    let fixes = scattered_fixes(n)
+        .into_iter()
+        .map(|fix| Fix {
+            position: center + (fix.position - center) * scale,
+            ..fix
+        })
+        .collect::<Vec<_>>();
+    let options = Options::default();
+    let summary = Summary::new(&fixes, Filtered::new(&fixes, &options).unwrap(), &options);
+    let findings = Findings {
+        confidence: summary.confidence_interval(options.confidence),
+        duration_s,
+        events,
+        hemisphere_corrected: 0,
+        failed_gates: &[],
+    };
+    explain(&summary, &findings)
+}
+
+#[test]
+fn explain_crafted_summaries() {
+    let has = |lines: &[String], start: &str| lines.iter().any(|line| line.starts_with(start));
+
+    let good = explain_fixes(1000, 0.2, Some(7200.), &[]);
+    assert!(has(&good, "The horizontal standard deviation is 0.8"));
+    assert!(has(&good, "The 965 fixes after the filter are plenty"));
+    assert!(has(&good, "The fixes span 2h 0m 0s, long enough"));
+    assert!(!has(&good, "Warning"));
+    assert!(good
+        .last()
+        .unwrap()
+        .starts_with("Verdict: the average is reliable, to about 0.0"));
+
+    let few = explain_fixes(12, 0.2, Some(7200.), &[]);
+    assert!(has(&few, "Only 12 fixes remain after the filter, too few"));
+    assert_eq!(
+        few.last().unwrap(),
+        "Verdict: don't rely on the average, since there are too few fixes."
+    );
+
+    let spread = explain_fixes(1000, 3., Some(1200.), &[]);
+    assert!(has(&spread, "A standard deviation above 5m is high"));
+    assert!(has(&spread, "The fixes span 20m 0s, enough"));
+    assert_eq!(
+        spread.last().unwrap(),
+        "Verdict: use the average with caution, since the fixes are widely spread."
+    );
+
+    let short = explain_fixes(1000, 0.2, None, &[]);
+    assert!(has(&short, "The fixes have no times"));
+    let short = explain_fixes(1000, 0.2, Some(300.), &[]);
+    assert!(has(&short, "The fixes span only 5m 0s"));
+
+    let jump = Event {
+        index: 500,
+        previous_line: 500,
+        line: 501,
+        kind: EventKind::Jump {
+            north: 20.,
+            east: 0.,
+            up: 0.,
+            horizontal: 20.,
+        },
+    };
+    let gap = Event {
+        kind: EventKind::Gap { seconds: 60. },
+        ..jump
+    };
+    let moved = explain_fixes(1000, 0.2, Some(300.), &[jump, gap, jump]);
+    assert!(has(&moved, "Warning: there are 2 position jumps"));
+    assert!(has(&moved, "Warning: there is 1 gap"));
+    assert_eq!(
+        moved.last().unwrap(),
+        "Verdict: don't rely on the average, since the log is short and the receiver moved."
+    );
+}
+
+#[test]
+fn selftest_passes() {
+    let checks = selftest::run();
+    assert_eq!(checks.len(), 7);
+    for check in checks {
+        assert_eq!(check.failure, None, "{}", check.name);
+    }
+    assert_eq!(action_from_args(&["selftest"]).unwrap(), Action::Selftest);
+}
+
+#[test]
+fn read_golden_asset() {
+    let data = read_inputs(
+        &[PathBuf::from("tests/assets/golden")],
+        &InputOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(data.fixes.len(), GOLDEN_FIXES);
+    let options = Options::default();
+    let filtered = Filtered::new(&data.fixes, &options).unwrap();
+    let outliers = filtered
+        .rejected
+        .iter()
+        .map(|fix| fix.line)
+        .collect::<Vec<_>>();
+    assert_eq!(outliers, GOLDEN_OUTLIER_LINES);
+    let summary = Summary::new(&data.fixes, filtered, &options);
+    for (found, expected) in [
+        (summary.avg, GOLDEN_AVERAGE),
+        (summary.std_dev, GOLDEN_STD_DEV),
+        (summary.std_dev_m, GOLDEN_STD_DEV_M),
+    ] {
+        assert!(
+            (found - expected).abs().max_element() < 1e-9,
+            "{found} {expected}"
+        );
+    }
+}
+
+#[test]
+fn summary_diff_of_saved_results() {
+    let old = SavedSummary::read(Path::new("tests/assets/summary_v1.json")).unwrap();
+    let new = SavedSummary::read(Path::new("tests/assets/summary_v2.json")).unwrap();
+    // The older results lack the fields added since.
+    assert_eq!(old.schema_version, 1);
+    assert_eq!(old.height, None);
+    assert_eq!(old.confidence_interval_m, None);
+    assert_eq!(old.duration_s, None);
+    assert_eq!(new.schema_version, SCHEMA_VERSION);
+    assert_eq!(new.duration_s, Some(299.));
+
+    let gates = Gates {
+        max_std_dev_m: Some(3.),
+        min_fixes: Some(250),
+        max_error_m: Some(2.5),
+        max_age_s: None,
+    };
+    assert_eq!(
+        diff(&old, &new, &gates),
+        [
+            "The results have different schema versions (1 and 2); the fields missing from the older ones are unknown.",
+            "Displacement of the average: N +1.132m, E -3.202m, U -0.299m, 3.396m horizontally",
+            "Fixes: 240 → 300 (+60)",
+            "Fixes after filter: 236 → 297 (+61)",
+            "Horizontal standard deviation: 3.895m → 2.630m (-1.265m)",
+            "Vertical standard deviation: 3.100m → 1.972m (-1.128m)",
+            "Horizontal 95% confidence radius: unknown → 0.264m",
+            "Horizontal error from the reference: 2.219m → 3.382m (+1.162m)",
+            "Duration: unknown → 4m 59s",
+            "Old results fail the quality checks: 236 fixes after filter, below the minimum of 250; horizontal standard deviation of 3.89m, above the maximum of 3m",
+            "New results fail the quality checks: error of 3.38m, above the maximum of 2.5m",
+        ]
+    );
+    // Without gates, nothing is checked.
+    let same = diff(&new, &new, &Gates::default());
+    assert_eq!(
+        same[0],
+        "Displacement of the average: N +0.000m, E +0.000m, U +0.000m, 0.000m horizontally"
+    );
+    assert!(!same.iter().any(|line| line.contains("quality checks")));
+
+    let ellipsoidal = SavedSummary {
+        height: Some(HeightSystem::Ellipsoidal),
+        ..new.clone()
+    };
+    assert!(diff(&new, &ellipsoidal, &Gates::default())[0].contains("different height systems"));
+
+    assert_eq!(
+        action_from_args(&["summary-diff", "old.json", "new.json", "--min-fixes", "100"]).unwrap(),
+        Action::SummaryDiff {
+            old: PathBuf::from("old.json"),
+            new: PathBuf::from("new.json"),
+            gates: Gates {
+                min_fixes: Some(100),
+                ..Gates::default()
+            },
+        }
+    );
+    assert!(SavedSummary::read(Path::new("tests/assets/golden")).is_err());
+}
+
+#[test]
+fn sha256_test_vectors() {
+    let hash = |data: &[u8]| hash_reader(data).unwrap();
+    assert_eq!(
+        hash(b""),
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    );
+    assert_eq!(
+        hash(b"abc"),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    assert_eq!(
+        hash(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+        "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+    );
+    // Read in several pieces.
+    assert_eq!(
+        hash(&[b'a'; 1_000_000]),
+        "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0"
+    );
+}
+
+#[test]
+fn provenance_of_accepted_fixes() {
+    let paths = [
+        PathBuf::from("tests/assets/golden"),
+        PathBuf::from("tests/assets/crlf.gz"),
+    ];
+    let options = Options::default();
+    let data = read_inputs(&paths, &InputOptions::default()).unwrap();
+    let filtered = Filtered::new(&data.fixes, &options).unwrap();
+    let provenance = Provenance::new(&paths, &filtered.fixes).unwrap();
+    // The outliers split the ranges of the golden data.
+    assert_eq!(
+        provenance.files[0].accepted_lines,
+        [[1, 50], [52, 150], [152, 250], [252, 300]]
+    );
+    // Compressed files are hashed as they are on disk, while their sentences are read
+    // decompressed, without the carriage returns.
+    assert_eq!(
+        provenance.files[1].sha256,
+        "590a0f5c3d62b2a5cc023d28b4e29a8271da003b69f2893d974956ae4f80048e"
+    );
+    assert_eq!(provenance.files[1].accepted_lines, [[6, 143]]);
+    assert_eq!(
+        provenance.accepted_sha256,
+        "185d5d2c18c25219188ce3d83ba7228c137bc8f022de930be2ca9f1c018f761c"
+    );
+    assert_eq!(provenance.verify(&paths).unwrap(), Vec::<String>::new());
+    assert!(provenance.verify(&paths[..1]).is_err());
+    assert!(action_from_args(&["tests/assets/golden", "--provenance"]).is_err());
+    assert!(action_from_args(&[
+        "tests/assets/weighted",
+        "--input-format",
+        "plain",
+        "--format",
+        "json",
+        "--provenance"
+    ])
+    .is_err());
+    assert_eq!(
+        action_from_args(&["verify-provenance", "results.json", "golden"]).unwrap(),
+        Action::VerifyProvenance {
+            results: PathBuf::from("results.json"),
+            input_paths: vec![PathBuf::from("golden")],
+        }
+    );
+
+    // The provenance is read back from the JSON results.
+    let results =
+        std::env::temp_dir().join(format!("gpsavg-provenance-{}.json", std::process::id()));
+    fs::write(
+        &results,
+        serde_json::json!({ "entries": 301, "provenance": provenance }).to_string(),
+    )
+    .unwrap();
+    let read = Provenance::read(&results);
+    fs::write(&results, r#"{"entries": 301}"#).unwrap();
+    let missing = Provenance::read(&results);
+    fs::remove_file(&results).unwrap();
+    assert_eq!(read.unwrap(), provenance);
+    assert!(missing.is_err());
+}
+
+#[test]
+fn provenance_detects_tampered_inputs() {
+    let golden = [PathBuf::from("tests/assets/golden")];
+    let options = Options::default();
+    let data = read_inputs(&golden, &InputOptions::default()).unwrap();
+    let filtered = Filtered::new(&data.fixes, &options).unwrap();
+    let provenance = Provenance::new(&golden, &filtered.fixes).unwrap();
+
+    let tampered = std::env::temp_dir().join(format!("gpsavg-tampered-{}", std::process::id()));
+    let verify = |line: usize, from: &str, to: &str| {
+        let contents = fs::read_to_string("tests/assets/golden").unwrap();
+        let mut lines = contents.lines().map(str::to_owned).collect::<Vec<_>>();
+        lines[line - 1] = lines[line - 1].replacen(from, to, 1);
+        fs::write(&tampered, lines.join("\n") + "\n").unwrap();
+        let mismatches = provenance.verify(std::slice::from_ref(&tampered)).unwrap();
+        fs::remove_file(&tampered).unwrap();
+        mismatches
+    };
+    let file_mismatch = format!(
+        "{} doesn't match the SHA-256 of tests/assets/golden",
+        tampered.display()
+    );
+    let sentences_mismatch =
+        "The accepted sentences don't match the SHA-256 of the ones the results were computed from";
+
+    // An untouched copy verifies.
+    assert_eq!(verify(10, "", ""), Vec::<String>::new());
+    // A digit changed in an accepted fix changes both hashes.
+    assert_eq!(
+        verify(10, "3722.48657", "3722.48658"),
+        [file_mismatch.clone(), sentences_mismatch.to_owned()]
+    );
+    // A change in an outlier only changes the hash of the file.
+    assert_eq!(verify(51, "47.2,M", "47.3,M"), [file_mismatch]);
 }
@@ -0,0 +1,59 @@
+//! Measurement of the time taken by each stage of the program.
+
+use std::time::Instant;
+
+/// Time taken by each stage of the program, in seconds, and the throughput of the parser.
+#[derive(Debug, serde::Serialize)]
+pub struct Timing {
+    pub parsing_s: f64,
+    pub filtering_s: f64,
+    /// Time taken to read the input files again for `--provenance`.
+    pub provenance_s: f64,
+    pub statistics_s: f64,
+    pub output_s: f64,
+    pub lines_per_s: f64,
+    pub mb_per_s: f64,
+}
+
+impl Timing {
+    /// Builds the timing information from the instants at which each stage started, followed by
+    /// the instant at which the last one finished.
+    pub fn new(
+        [start, parsed, filtered, hashed, computed, printed]: [Instant; 6],
+        lines: usize,
+        bytes: u64,
+    ) -> Self {
+        let parsing_s = (parsed - start).as_secs_f64();
+        // Avoid infinite throughput values for (almost) empty inputs.
+        let parsing_s_nonzero = parsing_s.max(f64::MIN_POSITIVE);
+
+        Self {
+            parsing_s,
+            filtering_s: (filtered - parsed).as_secs_f64(),
+            provenance_s: (hashed - filtered).as_secs_f64(),
+            statistics_s: (computed - hashed).as_secs_f64(),
+            output_s: (printed - computed).as_secs_f64(),
+            lines_per_s: lines as f64 / parsing_s_nonzero,
+            mb_per_s: bytes as f64 / 1e6 / parsing_s_nonzero,
+        }
+    }
+}
+
+impl std::fmt::Display for Timing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Timing: parsing {:.3}ms, filtering {:.3}ms, provenance {:.3}ms, statistics {:.3}ms, output {:.3}ms",
+            self.parsing_s * 1e3,
+            self.filtering_s * 1e3,
+            self.provenance_s * 1e3,
+            self.statistics_s * 1e3,
+            self.output_s * 1e3
+        )?;
+        write!(
+            f,
+            "Throughput: {:.0} lines/s, {:.2} MB/s",
+            self.lines_per_s, self.mb_per_s
+        )
+    }
+}
@@ -0,0 +1,230 @@
+//! Runs the binary against the test assets, to check that the arguments reach the code they drive
+//! and what is printed and returned for each kind of run.
+
+use std::{fs, path::PathBuf};
+
+use assert_cmd::{assert::Assert, Command};
+use predicates::{
+    prelude::PredicateBooleanExt,
+    str::{contains, starts_with},
+};
+
+fn run(args: &[&str]) -> Assert {
+    Command::cargo_bin("gpsavg")
+        .unwrap()
+        .args(args)
+        .env("NO_COLOR", "1")
+        .assert()
+}
+
+fn stdout(assert: &Assert) -> String {
+    String::from_utf8(assert.get_output().stdout.clone()).unwrap()
+}
+
+/// Returns a path in the temporary directory for a file named after `name`, unique to this run.
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("gpsavg-cli-{}-{name}", std::process::id()))
+}
+
+#[test]
+fn default_output() {
+    run(&["tests/assets/golden"]).success().stdout(
+        contains("Average: (37.3748º, -6.0007º, 40.0m)")
+            .and(contains("Number of entries: 300 (297 after filter)"))
+            .and(contains("Horizontally: within 0.264m"))
+            .and(contains("Histogram values:").not()),
+    );
+}
+
+#[test]
+fn short_output() {
+    run(&["-s", "-q", "tests/assets/golden"])
+        .success()
+        .stdout("37.374790195286195, -6.000736147025814, 39.95117845117845\n")
+        .stderr("");
+
+    run(&["-s", "--coord-format", "dms", "-q", "tests/assets/golden"])
+        .stdout(starts_with("37°22'29."));
+}
+
+#[test]
+fn histogram_output() {
+    run(&["-l", "tests/assets/golden"])
+        .success()
+        .stdout(contains("Histogram values:").and(contains("Latitude (º)")));
+}
+
+#[test]
+fn missing_input_file() {
+    run(&["tests/assets/missing"])
+        .code(1)
+        .stdout("")
+        .stderr(contains(
+            "Failed to read input file at tests/assets/missing",
+        ));
+}
+
+#[test]
+fn broken_input_file() {
+    run(&["tests/assets/1_broken"])
+        .code(1)
+        .stderr(contains("Failed to parse line 6 of the input file"));
+
+    // Skipping the broken lines is enough for the rest to be averaged.
+    run(&["--lenient", "tests/assets/1_broken"]).success();
+}
+
+#[test]
+fn invalid_arguments() {
+    // Values checked after parsing exit with 1.
+    run(&["--sigma", "0", "tests/assets/golden"])
+        .code(1)
+        .stderr(contains(
+            "--sigma must be a positive number of standard deviations",
+        ));
+    run(&[
+        "--input-format",
+        "plain",
+        "--dedup",
+        "tests/assets/weighted",
+    ])
+    .code(1)
+    .stderr(contains("--dedup requires --input-format nmea"));
+
+    // Arguments rejected by the parser fail with the usage.
+    run(&["-s", "--format", "json", "tests/assets/golden"])
+        .failure()
+        .stderr(contains("Usage:"));
+    run(&["--provenance", "tests/assets/golden"])
+        .code(1)
+        .stderr(contains("--provenance requires --format json"));
+}
+
+#[test]
+fn json_output() {
+    let assert = run(&[
+        "--format",
+        "json",
+        "--all-formats",
+        "--robust",
+        "--reference",
+        "37.3748,-6.0007,40",
+        "tests/assets/golden",
+    ])
+    .success();
+    let results: serde_json::Value = serde_json::from_str(&stdout(&assert)).unwrap();
+    assert_eq!(results["entries"], 300);
+    assert_eq!(results["height"], "orthometric");
+    assert!(results["median"]["latitude"].is_f64());
+    assert!(results["error_m"]["horizontal"].is_f64());
+    assert!(results["representations"]["utm"].is_string());
+    assert!(results.get("provenance").is_none());
+}
+
+#[test]
+fn quality_gates() {
+    // The results are printed anyway.
+    run(&["--max-stddev-m", "1", "tests/assets/golden"])
+        .code(2)
+        .stdout(contains("Average:"))
+        .stderr(contains(
+            "Quality check failed: horizontal standard deviation of 2.63m, above the maximum of 1m",
+        ));
+
+    run(&[
+        "--max-stddev-m",
+        "3",
+        "--min-fixes",
+        "250",
+        "tests/assets/golden",
+    ])
+    .success();
+}
+
+#[test]
+fn output_file() {
+    let path = temp_path("results.json");
+    let _ = fs::remove_file(&path);
+    let path_arg = path.to_str().unwrap();
+    let args = ["--format", "json", "-o", path_arg, "tests/assets/golden"];
+    // The text report goes to stdout, and the results to the file.
+    run(&args).success().stdout(contains("Average:"));
+    let results: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(results["entries_filtered"], 297);
+
+    // The file isn't overwritten unless forced.
+    let refused = run(&args);
+    let forced = run(&[&args[..], &["--force"]].concat());
+    fs::remove_file(&path).unwrap();
+    refused.code(1);
+    forced.success();
+}
+
+#[test]
+fn dry_run() {
+    // The input files aren't read.
+    run(&["--dry-run", "--robust", "tests/assets/missing"])
+        .success()
+        .stdout(contains("tests/assets/missing (NMEA, staleness 1s)").and(contains("Median")));
+}
+
+#[test]
+fn selftest() {
+    let assert = run(&["selftest"]).success().stdout(contains("FAIL").not());
+    assert!(stdout(&assert).lines().last().unwrap().starts_with("All "));
+}
+
+#[test]
+fn summary_diff() {
+    run(&[
+        "summary-diff",
+        "tests/assets/summary_v1.json",
+        "tests/assets/summary_v2.json",
+        "--min-fixes",
+        "250",
+    ])
+    .success()
+    .stdout(
+        contains("Fixes: 240 → 300 (+60)")
+            .and(contains("Old results fail the quality checks"))
+            .and(contains("New results pass the quality checks")),
+    );
+}
+
+#[test]
+fn provenance_round_trip() {
+    let results = temp_path("provenance.json");
+    let tampered = temp_path("tampered");
+    run(&[
+        "--format",
+        "json",
+        "--provenance",
+        "-o",
+        results.to_str().unwrap(),
+        "--force",
+        "tests/assets/golden",
+    ])
+    .success();
+
+    let verified = run(&[
+        "verify-provenance",
+        results.to_str().unwrap(),
+        "tests/assets/golden",
+    ]);
+    let golden = fs::read_to_string("tests/assets/golden").unwrap();
+    fs::write(&tampered, golden.replacen("3722.48657", "3722.48658", 1)).unwrap();
+    let mismatched = run(&[
+        "verify-provenance",
+        results.to_str().unwrap(),
+        tampered.to_str().unwrap(),
+    ]);
+    fs::remove_file(&results).unwrap();
+    fs::remove_file(&tampered).unwrap();
+
+    verified.success().stdout(starts_with("VERIFIED"));
+    let mismatched = mismatched.code(1);
+    let lines = stdout(&mismatched);
+    assert_eq!(lines.lines().count(), 2);
+    assert!(lines.lines().all(|line| line.starts_with("MISMATCH")));
+}
@@ -0,0 +1,48 @@
+//! Runs the binary in `-s` mode, to check what it prints to stdout and stderr.
+
+use std::process::{Command, Output};
+
+fn run(args: &[&str]) -> Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_gpsavg"))
+        .args(args)
+        .env("NO_COLOR", "1")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    output
+}
+
+#[test]
+fn short_output_with_audit_line() {
+    let output = run(&["-s", "tests/assets/1", "--max-hdop", "2"]);
+
+    // Stdout holds exactly the three numbers of the average.
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let line = stdout.strip_suffix('\n').unwrap();
+    assert!(!line.contains('\n'));
+    let numbers = line
+        .split(", ")
+        .map(|number| number.parse::<f64>().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(numbers.len(), 3);
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let line = stderr.strip_suffix('\n').unwrap();
+    assert!(!line.contains('\n'));
+    let rest = line.strip_prefix("# gpsavg: ").unwrap();
+    let (counts, rest) = rest.split_once(" after ").unwrap();
+    let (n, n_filtered) = counts.split_once(" fixes (").unwrap();
+    assert!(n.parse::<usize>().unwrap() >= n_filtered.parse::<usize>().unwrap());
+    let (filters, rest) = rest.split_once("), ").unwrap();
+    assert_eq!(filters, "sigma=3.0, hdop<=2.0");
+    let (duration, std_dev) = rest.split_once(", σ_h=").unwrap();
+    assert_eq!(duration, "4m35s");
+    std_dev.strip_suffix('m').unwrap().parse::<f64>().unwrap();
+}
+
+#[test]
+fn quiet_short_output() {
+    let output = run(&["-s", "-q", "tests/assets/1"]);
+    assert_eq!(output.stdout, run(&["-s", "tests/assets/1"]).stdout);
+    assert!(output.stderr.is_empty());
+}